@@ -5,6 +5,8 @@
 
 #[macro_use]
 extern crate kanren;
+#[macro_use]
+extern crate kanren_derive;
 
 use kanren::core::{State, Var, VarStore};
 use kanren::core::reify::Reifier;
@@ -14,14 +16,13 @@ use kanren::list::List;
 use kanren::list::Nil;
 use kanren::builtins::contains;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, FiniteDomain)]
 enum Month {
     May,
     June,
     July,
     August,
 }
-value_wrapper!(Month);
 
 fn main() {
     use Month::*;
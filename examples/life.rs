@@ -3,8 +3,8 @@ extern crate kanren;
 
 use kanren::core::{State, Unifier, Var, VarStore, VarRetrieve};
 use kanren::iter::{StateIter, single};
-use std::io::Read;
-use std::rc::Rc;
+use kanren::finitedomain::Fd;
+use kanren::constraints::{Cardinality, Reified, FdEqual, BoolAnd, BoolOr};
 use std::fmt::{Debug, Formatter};
 
 struct CellGrid {
@@ -83,100 +83,38 @@ impl<'a> Debug for CellGridWriter<'a> {
     }
 }
 
-fn life_sum(state: State, a: Var<i32>, b: Var<i32>, cell: Var<bool>) -> StateIter {
-    conde!(state, {
-        state.unify(cell, false).unify(a, b);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 0).unify(b, 1);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 1).unify(b, 2);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 2).unify(b, 3);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 3).unify(b, 4);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 4).unify(b, 5);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 5).unify(b, 6);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 6).unify(b, 7);
-        state
-    }, {
-        state.unify(cell, true).unify(a, 7).unify(b, 8);
-        state
-    })
-}
-
-fn neighbor_sum(mut state: State, neighbors: &[Var<bool>], total: Var<i32>) -> StateIter {
-    let mut tmp_sums: Vec<Var<i32>> = neighbors.iter().map(|_| state.make_var()).collect();
-    tmp_sums.push(total);
-    state.unify(tmp_sums[0], 0);
-    let result = neighbors.iter().enumerate().fold(single(state), |iter, (i, &cell)| {
-        let a = tmp_sums[i];
-        let b = tmp_sums[i + 1];
-        iter.and(move |state| {
-            life_sum(state, a, b, cell)
-        })
-    });
-    result
-}
-
-fn cell_survival(state: State, focus: Var<bool>, sum: Var<i32>, result: Var<bool>) -> StateIter {
-    conde!(state, {
-        state.unify(sum, 3).unify(result, true);
-        state
-    }, {
-        state.unify(focus, true).unify(sum, 2).unify(result, true);
-        state
-    }, {
-        state.unify(focus, false).unify(sum, 2).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 0).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 1).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 4).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 5).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 6).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 7).unify(result, false);
-        state
-    }, {
-        state.unify(sum, 8).unify(result, false);
-        state
-    })
-}
-
-fn step(state: State, old: &CellGrid, new: &CellGrid) -> StateIter {
-    let mut iter = single(state);
+fn step(state: &mut State, old: &CellGrid, new: &CellGrid) {
     for y in 0..old.size.1 {
         for x in 0..old.size.0 {
-            let neighbors = Box::new(old.neighbors(x, y));
             let oldcell = old.get(x, y).unwrap();
             let newcell = new.get(x, y).unwrap();
-            iter = iter.and(move |mut state| {
-                fresh!(state, sum);
-                neighbor_sum(state, &**neighbors, sum)
-                .and(move |state| cell_survival(state, oldcell, sum, newcell))
-            });
+            // newcell = sum == 3 || (oldcell && sum == 2)
+            fresh!(state, sum, three, two, survives);
+            state.add_constraint(Cardinality::new(old.neighbors(x, y), sum));
+            state.add_constraint(Reified::new(three, FdEqual::new(sum, Fd::new_single(3))));
+            state.add_constraint(Reified::new(two, FdEqual::new(sum, Fd::new_single(2))));
+            state.add_constraint(BoolAnd::new(oldcell, two, survives));
+            state.add_constraint(BoolOr::new(three, survives, newcell));
         }
     }
-    iter
+}
+
+fn label(state: State, grids: &[&CellGrid]) -> StateIter {
+    let cells: Vec<Var<bool>> = grids.iter().flat_map(|grid| grid.cells.iter().cloned()).collect();
+    cells.into_iter().fold(single(state), |iter, cell| {
+        iter.and(move |state| {
+            if state.get_value(cell).is_some() {
+                return single(state);
+            }
+            conde!(state, {
+                state.unify(cell, false);
+                state
+            }, {
+                state.unify(cell, true);
+                state
+            })
+        })
+    })
 }
 
 #[allow(dead_code)]
@@ -191,37 +129,31 @@ fn reverse_glider() {
         "     ",
         ];
     let grid = CellGrid::from_strings(&mut state, &input);
-    let oldgrid = Rc::new(CellGrid::new(&mut state, grid.size.0, grid.size.1));
-    let old2 = Rc::new(CellGrid::new(&mut state, grid.size.0, grid.size.1));
-    let old3 = Rc::new(CellGrid::new(&mut state, grid.size.0, grid.size.1));
+    let oldgrid = CellGrid::new(&mut state, grid.size.0, grid.size.1);
+    let old2 = CellGrid::new(&mut state, grid.size.0, grid.size.1);
+    let old3 = CellGrid::new(&mut state, grid.size.0, grid.size.1);
     let newgrid = grid;
 
     println!("beginning with\n{:?}\nand\n{:?}\n",
              CellGridWriter::new(&state, &oldgrid),
              CellGridWriter::new(&state, &newgrid));
 
-    let printold = oldgrid.clone();
-    let printold2 = old2.clone();
-    let printold3 = old3.clone();
-    let old2_tmp = old2.clone();
-
-    let iter =
-        step(state, &oldgrid, &newgrid)
-        .and(move |state| step(state, &*old2, &*oldgrid))
-        .and(move |state| step(state, &*old3, &*old2_tmp));
+    step(&mut state, &oldgrid, &newgrid);
+    step(&mut state, &old2, &oldgrid);
+    step(&mut state, &old3, &old2);
 
-    for (i, state) in iter.into_iter().enumerate().take(100) {
+    for (i, state) in label(state, &[&old3, &old2, &oldgrid]).into_iter().enumerate().take(100) {
         println!("solution {}:\n{:?}\nbecomes\n{:?}\nbecomes\n{:?}\nbecomes{:?}", i,
-                 CellGridWriter::new(&state, &printold3),
-                 CellGridWriter::new(&state, &printold2),
-                 CellGridWriter::new(&state, &printold),
+                 CellGridWriter::new(&state, &old3),
+                 CellGridWriter::new(&state, &old2),
+                 CellGridWriter::new(&state, &oldgrid),
                  CellGridWriter::new(&state, &newgrid));
     }
 }
 
 fn main() {
     let mut state = State::new();
-    let oldgrid = Rc::new(CellGrid::from_strings(&mut state, &[
+    let grid = CellGrid::from_strings(&mut state, &[
         "       ",
         " ????? ",
         " ????? ",
@@ -229,11 +161,9 @@ fn main() {
         " ????? ",
         " ????? ",
         "       ",
-    ]));
-    let savegrid = oldgrid.clone();
-    let iter = single(state)
-        .and(move |state| step(state, &oldgrid, &oldgrid));
-    for (i, state) in iter.into_iter().enumerate().take(100) {
-        println!("still-life {}:\n{:?}", i, CellGridWriter::new(&state, &savegrid));
+    ]);
+    step(&mut state, &grid, &grid);
+    for (i, state) in label(state, &[&grid]).into_iter().enumerate().take(100) {
+        println!("still-life {}:\n{:?}", i, CellGridWriter::new(&state, &grid));
     }
 }
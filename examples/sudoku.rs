@@ -8,9 +8,19 @@ use kanren::list::{Pair, Nil};
 use std::io::Read;
 use std::fmt::{Formatter, Debug};
 use kanren::finitedomain::Fd;
-use kanren::constraints::AllDiffConstraint;
+use kanren::constraints::{AllDiffConstraint, AllDiffPropagation};
+use kanren::core::Labeling;
 
+///! Reads a puzzle from stdin.  Pass `--value` or `--bounds` for weaker propagation, and
+///! `--backjumping` or `--nogoods` to label with backjumping.
 fn main() {
+    let args: Vec<String> = ::std::env::args().skip(1).collect();
+    let has_arg = |name: &str| args.iter().any(|x| x == name);
+    let propagation = if has_arg("--value") { AllDiffPropagation::Value }
+        else if has_arg("--bounds") { AllDiffPropagation::Bounds }
+        else { AllDiffPropagation::Domain };
+    let labeling = Labeling::new().backjumping(has_arg("--backjumping")).nogoods(has_arg("--nogoods"));
+
     let mut state = State::new();
     fresh!(state, orig_list);
     let mut list = orig_list;
@@ -36,7 +46,7 @@ fn main() {
     for ((x, y), &puz) in xy.zip(puzzle.iter()) {
         fresh!(state, entry, tail);
         let value = match puz {
-            Some(x) => Fd::new_single(x as usize),
+            Some(x) => Fd::new_single(x as i64),
             None => Fd::new_values((1..10).collect()),
         };
         state.unify(entry, value);
@@ -49,10 +59,10 @@ fn main() {
     state.unify(list, Nil);
     //println!("colvars: {:?}, rowvars: {:?}, groupvars: {:?}", colvars, rowvars, groupvars);
     for vars in colvars.into_iter().chain(rowvars).chain(groupvars) {
-        state.add_constraint(AllDiffConstraint::new(vars));
+        state.add_constraint(AllDiffConstraint::with_propagation(vars, propagation));
     }
     #[allow(unused_variables)]
-    fn get_fds(state: State, list: Var<List<Fd>>) -> StateIter {
+    fn get_fds(state: State, list: Var<List<Fd>>, labeling: Labeling) -> StateIter {
         //conde!(state, {
             //state.unify(list, Nil);
             //single(state)
@@ -60,7 +70,7 @@ fn main() {
             //fresh!(state, head, tail);
             //state.unify(list, Pair(head, tail));
         //}
-        ::kanren::core::assign_all_values(state)
+        ::kanren::core::assign_all_values_with(state, labeling)
     }
 
     struct UnderscoreWriter<T>(Option<T>);
@@ -83,7 +93,7 @@ fn main() {
         }
     }
 
-    for (i, mut state) in get_fds(state, orig_list).into_iter().enumerate().take(100) {
+    for (i, mut state) in get_fds(state, orig_list, labeling).into_iter().enumerate().take(100) {
         //let reifier = Reifier::new(&state);
         println!("solution {}:", i);
         display_list(&mut state, orig_list);
@@ -4,16 +4,19 @@ extern crate kanren;
 use kanren::core::{State, Unifier, Var, ToVar, VarStore, VarRetrieve};
 use kanren::iter::{StateIter, single};
 use kanren::core::vars::__;
-use kanren::core::reify::Reifier;
+use kanren::core::assign_all_values;
 use kanren::builtins::contains;
 use kanren::list::{List, Pair};
+use kanren::domain::Domain;
+use kanren::constraints::DomainAllDiff;
+use std::fmt::Debug;
 use Cigarettes::*;
 use Nationalities::*;
 use Colors::*;
 use Drinks::*;
 use Animals::*;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Cigarettes {
     OldGold,
     Kool,
@@ -22,7 +25,7 @@ enum Cigarettes {
     Parliament,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Nationalities {
     English,
     Spanish,
@@ -31,7 +34,7 @@ enum Nationalities {
     Japanese,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Animals {
     Dog,
     Snail,
@@ -40,7 +43,7 @@ enum Animals {
     Zebra,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Colors {
     Red,
     Green,
@@ -49,7 +52,7 @@ enum Colors {
     Blue,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 enum Drinks {
     Coffee,
     Tea,
@@ -58,14 +61,7 @@ enum Drinks {
     Water,
 }
 
-
-value_wrapper!(Cigarettes);
-value_wrapper!(Nationalities);
-value_wrapper!(Animals);
-value_wrapper!(Colors);
-value_wrapper!(Drinks);
-
-type VarHouse = (Var<Nationalities>, Var<Colors>, Var<Animals>, Var<Drinks>, Var<Cigarettes>);
+type VarHouse = (Var<Domain<Nationalities>>, Var<Domain<Colors>>, Var<Domain<Animals>>, Var<Domain<Drinks>>, Var<Domain<Cigarettes>>);
 
 #[derive(Default, Debug, Clone, Copy)]
 struct House {
@@ -79,11 +75,11 @@ struct House {
 impl ToVar for House {
     type VarType = VarHouse;
     fn into_var<U: VarStore+Unifier>(self, state: &mut U) -> Var<VarHouse> {
-        let nationality = self.nationality.map(|x| state.make_var_of(x)).unwrap_or(state.make_var());
-        let color = self.color.map(|x| state.make_var_of(x)).unwrap_or(state.make_var());
-        let pet = self.pet.map(|x| state.make_var_of(x)).unwrap_or(state.make_var());
-        let drink = self.drink.map(|x| state.make_var_of(x)).unwrap_or(state.make_var());
-        let cigarette = self.cigarette.map(|x| state.make_var_of(x)).unwrap_or(state.make_var());
+        let nationality = self.nationality.map(|x| state.make_var_of(Domain::new_single(x))).unwrap_or(state.make_var());
+        let color = self.color.map(|x| state.make_var_of(Domain::new_single(x))).unwrap_or(state.make_var());
+        let pet = self.pet.map(|x| state.make_var_of(Domain::new_single(x))).unwrap_or(state.make_var());
+        let drink = self.drink.map(|x| state.make_var_of(Domain::new_single(x))).unwrap_or(state.make_var());
+        let cigarette = self.cigarette.map(|x| state.make_var_of(Domain::new_single(x))).unwrap_or(state.make_var());
         state.make_var_of((nationality, color, pet, drink, cigarette))
     }
 }
@@ -119,12 +115,28 @@ fn neighbor(mut state: State, a: House, b: House, list: Var<List<VarHouse>>) ->
     })
 }
 
+///! Make a variable for each house which can take any of `values`, and no two of which are the
+///! same.
+fn attribute<T>(state: &mut State, values: &[T]) -> Vec<Var<Domain<T>>> where T: Ord + Clone + Debug + 'static {
+    let vars: Vec<_> = (0..5).map(|_| state.make_var_of(Domain::new(values.to_vec()))).collect();
+    state.add_constraint(DomainAllDiff::new(vars.clone()));
+    vars
+}
+
 fn main() {
     let mut state = State::new();
     fresh!(state, houses);
     let empty = Default::default();
-    fresh!(state, a, b, c, d, e);
-    state.unify(houses, [a, b, c, d, e]);
+    let nationalities = attribute(&mut state, &[English, Spanish, Ukranian, Norwegian, Japanese]);
+    let colors = attribute(&mut state, &[Red, Green, Ivory, Yellow, Blue]);
+    let pets = attribute(&mut state, &[Dog, Snail, Fox, Horse, Zebra]);
+    let drinks = attribute(&mut state, &[Coffee, Tea, Milk, OrangeJuice, Water]);
+    let cigarettes = attribute(&mut state, &[OldGold, Kool, Chesterfield, LuckyStrike, Parliament]);
+    let vars: Vec<Var<VarHouse>> = (0..5).map(|i| {
+        state.make_var_of((nationalities[i], colors[i], pets[i], drinks[i], cigarettes[i]))
+    }).collect();
+    let (a, c) = (vars[0], vars[2]);
+    state.unify(houses, [vars[0], vars[1], vars[2], vars[3], vars[4]]);
     state.unify(c, House { drink: Some(Milk), .. empty });
     state.unify(a, House { nationality: Some(Norwegian), .. empty });
     let states = single(state)
@@ -141,19 +153,17 @@ fn main() {
         .and(move |state| neighbor(state, House { cigarette: Some(Kool), .. empty }, House { pet: Some(Horse), .. empty }, houses))
         .and(move |state| neighbor(state, House { cigarette: Some(Chesterfield), .. empty }, House { pet: Some(Fox), .. empty }, houses))
         .and(move |state| contains(state, House { drink: Some(Water), .. empty }, houses))
-        .and(move |state| contains(state, House { pet: Some(Zebra), .. empty }, houses));
+        .and(move |state| contains(state, House { pet: Some(Zebra), .. empty }, houses))
+        .and(assign_all_values);
+    fn value<T>(state: &State, var: Var<Domain<T>>) -> T where T: Ord + Clone + Debug + 'static {
+        state.get_value(var).and_then(|x| x.single_value()).cloned().unwrap()
+    }
     for (i, state) in states.into_iter().enumerate().take(100) {
-        let mut reifier = Reifier::new(&state);
         println!("solution {}:", i);
         let result = state.get_value(houses).unwrap();
         let resultlist: Vec<_> = result.iter(&state).map(|x| {
             let (nationality, color, pet, drink, cigarette) = *x.unwrap();
-            let nationality = reifier.reify(nationality);
-            let color = reifier.reify(color);
-            let pet = reifier.reify(pet);
-            let drink = reifier.reify(drink);
-            let cigarette = reifier.reify(cigarette);
-            (nationality, color, pet, drink, cigarette)
+            (value(&state, nationality), value(&state, color), value(&state, pet), value(&state, drink), value(&state, cigarette))
         }).collect();
         println!("{:?}", resultlist);
     }
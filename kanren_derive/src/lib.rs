@@ -0,0 +1,118 @@
+//! Provides `#[derive(FiniteDomain)]`, which maps the variants of a field-less enum onto the
+//! indexes of a `kanren::finitedomain::Fd`, and makes the enum a value that can be stored in a
+//! `Var`, like `value_wrapper!` does.  `kanren::constraints::FdEnumConstraint` links a `Var` of
+//! the enum to the `Fd` of its index.
+//!
+//! ```ignore
+//! #[macro_use] extern crate kanren_derive;
+//!
+//! #[derive(Copy, Clone, Debug, FiniteDomain)]
+//! enum Color { Red, Green, Blue }
+//! ```
+//!
+//! The enum must implement `Debug`, as `ToVar` requires.  The variants are numbered from 0 in
+//! declaration order; explicit discriminants are ignored.
+
+extern crate proc_macro;
+
+use proc_macro::{TokenStream, TokenTree, Delimiter};
+
+#[proc_macro_derive(FiniteDomain)]
+pub fn derive_finite_domain(input: TokenStream) -> TokenStream {
+    let code = match parse_enum(input) {
+        Ok((name, variants)) => finite_domain_impl(&name, &variants),
+        Err(msg) => format!("compile_error!({:?});", msg),
+    };
+    code.parse().unwrap()
+}
+
+///! Return the name and variant names of a field-less enum.
+fn parse_enum(input: TokenStream) -> Result<(String, Vec<String>), String> {
+    let mut tokens = input.into_iter();
+    // Skip attributes and visibility, up to the `enum` keyword.
+    loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(ref ident)) if ident.to_string() == "enum" => break,
+            Some(TokenTree::Ident(ref ident)) if ident.to_string() == "struct" || ident.to_string() == "union" => {
+                return Err("FiniteDomain can only be derived for enums".to_string());
+            },
+            Some(_) => (),
+            None => { return Err("expected an enum".to_string()); },
+        }
+    }
+    let name = match tokens.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => { return Err("expected the enum's name".to_string()); },
+    };
+    let body = match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Brace => group.stream(),
+        _ => { return Err("FiniteDomain can't be derived for generic enums".to_string()); },
+    };
+
+    let mut variants = Vec::new();
+    let mut body = body.into_iter();
+    while let Some(token) = body.next() {
+        match token {
+            // attributes on a variant
+            TokenTree::Punct(ref punct) if punct.as_char() == '#' => { body.next(); },
+            TokenTree::Ident(ident) => {
+                variants.push(ident.to_string());
+                match body.next() {
+                    None => (),
+                    Some(TokenTree::Punct(ref punct)) if punct.as_char() == ',' => (),
+                    // an explicit discriminant, which runs up to the next comma
+                    Some(TokenTree::Punct(ref punct)) if punct.as_char() == '=' => {
+                        for token in body.by_ref() {
+                            if let TokenTree::Punct(ref punct) = token {
+                                if punct.as_char() == ',' { break; }
+                            }
+                        }
+                    },
+                    Some(_) => {
+                        return Err(format!("FiniteDomain can't be derived for {}, because variant {} has fields", name, ident));
+                    },
+                }
+            },
+            _ => { return Err(format!("unexpected token in enum {}", name)); },
+        }
+    }
+    if variants.is_empty() {
+        return Err(format!("FiniteDomain can't be derived for {}, because it has no variants", name));
+    }
+    Ok((name, variants))
+}
+
+fn finite_domain_impl(name: &str, variants: &[String]) -> String {
+    let to_index: String = variants.iter().enumerate()
+        .map(|(i, variant)| format!("{}::{} => {},", name, variant, i))
+        .collect();
+    let from_index: String = variants.iter().enumerate()
+        .map(|(i, variant)| format!("{} => Some({}::{}),", i, name, variant))
+        .collect();
+    format!("
+        impl ::kanren::finitedomain::FiniteDomain for {name} {{
+            fn to_index(&self) -> i64 {{
+                match *self {{ {to_index} }}
+            }}
+            fn from_index(index: i64) -> Option<{name}> {{
+                match index {{ {from_index} _ => None, }}
+            }}
+            fn count() -> i64 {{ {count} }}
+        }}
+
+        impl ::kanren::core::VarWrapper for {name} {{
+            fn unify_with(&self, other: &::kanren::core::VarWrapper, _: &mut ::kanren::core::StateProxy) -> ::kanren::core::UnifyResult {{
+                let other = other.get_wrapped_value::<{name}>();
+                let index = <{name} as ::kanren::finitedomain::FiniteDomain>::to_index;
+                (index(self) == index(other)).into()
+            }}
+        }}
+
+        impl ::kanren::core::ToVar for {name} {{
+            type VarType = {name};
+            fn into_var<U: ::kanren::core::VarStore + ::kanren::core::Unifier>(self, state: &mut U) -> ::kanren::core::Var<{name}> {{
+                state.store_value(self)
+            }}
+        }}",
+        name = name, to_index = to_index, from_index = from_index, count = variants.len())
+}
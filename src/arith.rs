@@ -0,0 +1,623 @@
+//! Relational arithmetic on natural numbers, from chapters 7 and 8 of The Reasoned Schemer.
+//!
+//! Numbers are represented as little-endian lists of `Bit`s: zero is the empty list, and every
+//! other number ends with `One`, so each number has exactly one representation.  For instance, 6
+//! is `[Zero, One, One]`.  As in the original, `pluso`, `minuso`, `multo`, `divo`, `logo`, `lto`
+//! and `leo` are refutationally complete: if no answer exists, they fail in finite time rather
+//! than searching forever, no matter which of their arguments are fresh.
+
+use core::{State, Var, ToVar, VarStore, VarRetrieve, Unifier};
+use core::vars::__;
+use list::{List, Pair, Nil};
+use list::List::Pair as VarPair;
+use iter::StateIter;
+
+pub use arith::Bit::*;
+
+///! A single binary digit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bit {
+    Zero,
+    One,
+}
+value_wrapper!(Bit);
+
+///! A natural number, as a little-endian list of bits.
+pub type Num = List<Bit>;
+
+///! Iterates over the bits of a `u64`, lowest first.
+#[derive(Clone, Copy)]
+struct NumBits(u64);
+
+impl Iterator for NumBits {
+    type Item = Bit;
+    fn next(&mut self) -> Option<Bit> {
+        match self.0 {
+            0 => None,
+            x => {
+                *self = NumBits(x >> 1);
+                Some(if x & 1 == 1 { One } else { Zero })
+            }
+        }
+    }
+}
+
+///! Create a variable holding the binary representation of `n`.
+pub fn build_num<U>(state: &mut U, n: u64) -> Var<Num> where U: VarStore + Unifier {
+    List::new_from_iter(state, NumBits(n))
+}
+
+///! Read a binary number back into a `u64`.  Returns `None` if any part of the number is unset,
+///! or if it is too large to fit.
+pub fn num_value<R>(state: &R, n: Var<Num>) -> Option<u64> where R: VarRetrieve {
+    let mut list = match state.get_value(n) {
+        Some(x) => *x,
+        None => { return None; }
+    };
+    let mut result = 0u64;
+    let mut shift = 0;
+    loop {
+        match list {
+            Nil => { return Some(result); }
+            VarPair(head, tail) => {
+                if shift >= 64 { return None; }
+                match state.get_value(head) {
+                    Some(&One) => { result |= 1 << shift; },
+                    Some(&Zero) => { },
+                    None => { return None; }
+                }
+                shift += 1;
+                list = match state.get_value(tail) {
+                    Some(x) => *x,
+                    None => { return None; }
+                };
+            }
+        }
+    }
+}
+
+///! Assert that `x & y = r`.
+pub fn bit_ando(state: State, x: Var<Bit>, y: Var<Bit>, r: Var<Bit>) -> StateIter {
+    conde!(state, {
+        state.unify(x, Zero).unify(y, Zero).unify(r, Zero);
+        state
+    }, {
+        state.unify(x, Zero).unify(y, One).unify(r, Zero);
+        state
+    }, {
+        state.unify(x, One).unify(y, Zero).unify(r, Zero);
+        state
+    }, {
+        state.unify(x, One).unify(y, One).unify(r, One);
+        state
+    })
+}
+
+///! Assert that `x ^ y = r`.
+pub fn bit_xoro(state: State, x: Var<Bit>, y: Var<Bit>, r: Var<Bit>) -> StateIter {
+    conde!(state, {
+        state.unify(x, Zero).unify(y, Zero).unify(r, Zero);
+        state
+    }, {
+        state.unify(x, Zero).unify(y, One).unify(r, One);
+        state
+    }, {
+        state.unify(x, One).unify(y, Zero).unify(r, One);
+        state
+    }, {
+        state.unify(x, One).unify(y, One).unify(r, Zero);
+        state
+    })
+}
+
+///! Assert that adding the bits `x` and `y` gives the sum `r` and carry `c`.
+pub fn half_addero(state: State, x: Var<Bit>, y: Var<Bit>, r: Var<Bit>, c: Var<Bit>) -> StateIter {
+    bit_xoro(state, x, y, r).and(move |state| bit_ando(state, x, y, c))
+}
+
+///! Assert that adding the bits `b`, `x` and `y` gives the sum `r` and carry `c`.
+pub fn full_addero(mut state: State, b: Var<Bit>, x: Var<Bit>, y: Var<Bit>, r: Var<Bit>, c: Var<Bit>) -> StateIter {
+    fresh!(state, w, xy, wz);
+    half_addero(state, x, y, w, xy)
+        .and(move |state| half_addero(state, w, b, r, wz))
+        .and(move |state| bit_xoro(state, xy, wz, c))
+}
+
+///! Assert that `n` is positive.
+fn pos(state: &mut State, n: Var<Num>) {
+    state.unify(n, Pair(__(), __()));
+}
+
+///! Assert that `n` is greater than one.
+fn over_one(state: &mut State, n: Var<Num>) {
+    state.unify(n, Pair(__(), Pair(__(), __())));
+}
+
+///! Assert that `n + m = k`.
+pub fn pluso<N, M, K>(mut state: State, n: N, m: M, k: K) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num>, K: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    let k = state.make_var_of(k);
+    plus(state, n, m, k)
+}
+
+///! Assert that `n - m = k`.
+pub fn minuso<N, M, K>(mut state: State, n: N, m: M, k: K) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num>, K: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    let k = state.make_var_of(k);
+    minus(state, n, m, k)
+}
+
+///! Assert that `n * m = p`.
+pub fn multo<N, M, P>(mut state: State, n: N, m: M, p: P) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num>, P: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    let p = state.make_var_of(p);
+    mult(state, n, m, p)
+}
+
+///! Assert that `n / m = q`, with remainder `r`.
+pub fn divo<N, M, Q, R>(mut state: State, n: N, m: M, q: Q, r: R) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num>, Q: ToVar<VarType=Num>, R: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    let q = state.make_var_of(q);
+    let r = state.make_var_of(r);
+    div(state, n, m, q, r)
+}
+
+///! Assert that `n = b^q + r`, where `r` is less than `b^(q+1) - b^q`.  This makes `q` the integer
+///! logarithm of `n` in base `b`.
+pub fn logo<N, B, Q, R>(mut state: State, n: N, b: B, q: Q, r: R) -> StateIter
+where N: ToVar<VarType=Num>, B: ToVar<VarType=Num>, Q: ToVar<VarType=Num>, R: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let b = state.make_var_of(b);
+    let q = state.make_var_of(q);
+    let r = state.make_var_of(r);
+    log(state, n, b, q, r)
+}
+
+///! Assert that `n < m`.
+pub fn lto<N, M>(mut state: State, n: N, m: M) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    lt(state, n, m)
+}
+
+///! Assert that `n <= m`.
+pub fn leo<N, M>(mut state: State, n: N, m: M) -> StateIter
+where N: ToVar<VarType=Num>, M: ToVar<VarType=Num> {
+    let n = state.make_var_of(n);
+    let m = state.make_var_of(m);
+    le(state, n, m)
+}
+
+fn adder(state: State, d: Var<Bit>, n: Var<Num>, m: Var<Num>, r: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(d, Zero).unify(m, Nil).unify(n, r);
+        state
+    }, {
+        state.unify(d, Zero).unify(n, Nil).unify(m, r);
+        pos(&mut state, m);
+        state
+    }, {
+        state.unify(d, One).unify(m, Nil);
+        let zero = state.make_var_of(Zero);
+        let one = state.make_var_of([One]);
+        adder(state, zero, n, one, r)
+    }, {
+        state.unify(d, One).unify(n, Nil);
+        pos(&mut state, m);
+        let zero = state.make_var_of(Zero);
+        let one = state.make_var_of([One]);
+        adder(state, zero, one, m, r)
+    }, {
+        state.unify(n, [One]).unify(m, [One]);
+        fresh!(state, a, c);
+        state.unify(r, Pair(a, Pair(c, Nil)));
+        let one = state.make_var_of(One);
+        full_addero(state, d, one, one, a, c)
+    }, {
+        state.unify(n, [One]);
+        gen_adder(state, d, n, m, r)
+    }, {
+        state.unify(m, [One]);
+        over_one(&mut state, n);
+        over_one(&mut state, r);
+        let one = state.make_var_of([One]);
+        adder(state, d, one, n, r)
+    }, {
+        over_one(&mut state, n);
+        gen_adder(state, d, n, m, r)
+    })
+}
+
+fn gen_adder(mut state: State, d: Var<Bit>, n: Var<Num>, m: Var<Num>, r: Var<Num>) -> StateIter {
+    fresh!(state, a, b, c, e, x, y, z);
+    state.unify(n, Pair(a, x));
+    state.unify(m, Pair(b, y));
+    pos(&mut state, y);
+    state.unify(r, Pair(c, z));
+    pos(&mut state, z);
+    full_addero(state, d, a, b, c, e)
+        .and(move |state| adder(state, e, x, y, z))
+}
+
+fn plus(mut state: State, n: Var<Num>, m: Var<Num>, k: Var<Num>) -> StateIter {
+    let zero = state.make_var_of(Zero);
+    adder(state, zero, n, m, k)
+}
+
+fn minus(state: State, n: Var<Num>, m: Var<Num>, k: Var<Num>) -> StateIter {
+    plus(state, m, k, n)
+}
+
+fn mult(state: State, n: Var<Num>, m: Var<Num>, p: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, Nil).unify(p, Nil);
+        state
+    }, {
+        pos(&mut state, n);
+        state.unify(m, Nil).unify(p, Nil);
+        state
+    }, {
+        state.unify(n, [One]);
+        pos(&mut state, m);
+        state.unify(m, p);
+        state
+    }, {
+        over_one(&mut state, n);
+        state.unify(m, [One]).unify(n, p);
+        state
+    }, {
+        fresh!(state, x, z);
+        state.unify(n, Pair(Zero, x));
+        pos(&mut state, x);
+        state.unify(p, Pair(Zero, z));
+        pos(&mut state, z);
+        over_one(&mut state, m);
+        mult(state, x, m, z)
+    }, {
+        fresh!(state, x, y);
+        state.unify(n, Pair(One, x));
+        pos(&mut state, x);
+        state.unify(m, Pair(Zero, y));
+        pos(&mut state, y);
+        mult(state, m, n, p)
+    }, {
+        fresh!(state, x, y);
+        state.unify(n, Pair(One, x));
+        pos(&mut state, x);
+        state.unify(m, Pair(One, y));
+        pos(&mut state, y);
+        odd_mult(state, x, n, m, p)
+    })
+}
+
+fn odd_mult(mut state: State, x: Var<Num>, n: Var<Num>, m: Var<Num>, p: Var<Num>) -> StateIter {
+    fresh!(state, q);
+    bound_mult(state, q, p, n, m)
+        .and(move |state| mult(state, x, m, q))
+        .and(move |mut state| {
+            let twice_q = state.make_var_of(Pair(Zero, q));
+            plus(state, twice_q, m, p)
+        })
+}
+
+///! Assert that `q` has fewer bits than `p`, and no more bits than `n` and `m` combined.  This
+///! keeps `mult` from producing ever-longer candidates for the product.
+fn bound_mult(state: State, q: Var<Num>, p: Var<Num>, n: Var<Num>, m: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(q, Nil);
+        pos(&mut state, p);
+        state
+    }, {
+        fresh!(state, x, y, z);
+        state.unify(q, Pair(__(), x)).unify(p, Pair(__(), y));
+        conde!(state, {
+            state.unify(n, Nil).unify(m, Pair(__(), z));
+            let nil = state.make_var_of(Nil);
+            bound_mult(state, x, y, z, nil)
+        }, {
+            state.unify(n, Pair(__(), z));
+            bound_mult(state, x, y, z, m)
+        })
+    })
+}
+
+///! Assert that `n` and `m` have the same number of bits.
+fn eq_len(state: State, n: Var<Num>, m: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, Nil).unify(m, Nil);
+        state
+    }, {
+        state.unify(n, [One]).unify(m, [One]);
+        state
+    }, {
+        fresh!(state, x, y);
+        state.unify(n, Pair(__(), x));
+        pos(&mut state, x);
+        state.unify(m, Pair(__(), y));
+        pos(&mut state, y);
+        eq_len(state, x, y)
+    })
+}
+
+///! Assert that `n` has fewer bits than `m`.
+fn lt_len(state: State, n: Var<Num>, m: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, Nil);
+        pos(&mut state, m);
+        state
+    }, {
+        state.unify(n, [One]);
+        over_one(&mut state, m);
+        state
+    }, {
+        fresh!(state, x, y);
+        state.unify(n, Pair(__(), x));
+        pos(&mut state, x);
+        state.unify(m, Pair(__(), y));
+        pos(&mut state, y);
+        lt_len(state, x, y)
+    })
+}
+
+fn lt(state: State, n: Var<Num>, m: Var<Num>) -> StateIter {
+    conde!(state, {
+        lt_len(state, n, m)
+    }, {
+        fresh!(state, x);
+        pos(&mut state, x);
+        eq_len(state, n, m).and(move |state| plus(state, n, x, m))
+    })
+}
+
+fn le(state: State, n: Var<Num>, m: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, m);
+        state
+    }, {
+        lt(state, n, m)
+    })
+}
+
+///! Split `n` at the bit position given by the length of `r`, so that `n = l + 2^(len(r) + 1) * h`.
+///! The bit at that position itself is dropped.
+fn split(state: State, n: Var<Num>, r: Var<Num>, l: Var<Num>, h: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, Nil).unify(h, Nil).unify(l, Nil);
+        state
+    }, {
+        fresh!(state, b, n_hat);
+        state.unify(n, Pair(Zero, Pair(b, n_hat))).unify(r, Nil);
+        state.unify(h, Pair(b, n_hat)).unify(l, Nil);
+        state
+    }, {
+        fresh!(state, n_hat);
+        state.unify(n, Pair(One, n_hat)).unify(r, Nil);
+        state.unify(n_hat, h).unify(l, [One]);
+        state
+    }, {
+        fresh!(state, b, n_hat, r_hat);
+        state.unify(n, Pair(Zero, Pair(b, n_hat))).unify(r, Pair(__(), r_hat));
+        state.unify(l, Nil);
+        let rest = state.make_var_of(Pair(b, n_hat));
+        let nil = state.make_var_of(Nil);
+        split(state, rest, r_hat, nil, h)
+    }, {
+        fresh!(state, n_hat, r_hat);
+        state.unify(n, Pair(One, n_hat)).unify(r, Pair(__(), r_hat));
+        state.unify(l, [One]);
+        let nil = state.make_var_of(Nil);
+        split(state, n_hat, r_hat, nil, h)
+    }, {
+        fresh!(state, b, n_hat, r_hat, l_hat);
+        state.unify(n, Pair(b, n_hat)).unify(r, Pair(__(), r_hat));
+        state.unify(l, Pair(b, l_hat));
+        pos(&mut state, l_hat);
+        split(state, n_hat, r_hat, l_hat, h)
+    })
+}
+
+fn div(state: State, n: Var<Num>, m: Var<Num>, q: Var<Num>, r: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(r, n).unify(q, Nil);
+        lt(state, n, m)
+    }, {
+        state.unify(q, [One]);
+        eq_len(state, n, m)
+            .and(move |state| plus(state, r, m, n))
+            .and(move |state| lt(state, r, m))
+    }, {
+        fresh!(state, nh, nl, qh, ql);
+        pos(&mut state, q);
+        lt_len(state, m, n)
+            .and(move |state| lt(state, r, m))
+            .and(move |state| split(state, n, r, nl, nh))
+            .and(move |state| split(state, q, r, ql, qh))
+            .and(move |state| conde!(state, {
+                fresh!(state, qlm);
+                state.unify(nh, Nil).unify(qh, Nil);
+                minus(state, nl, r, qlm)
+                    .and(move |state| mult(state, ql, m, qlm))
+            }, {
+                fresh!(state, qlm, qlmr, rr, rh);
+                pos(&mut state, nh);
+                mult(state, ql, m, qlm)
+                    .and(move |state| plus(state, qlm, r, qlmr))
+                    .and(move |state| minus(state, qlmr, nl, rr))
+                    .and(move |mut state| {
+                        let nil = state.make_var_of(Nil);
+                        split(state, rr, r, nil, rh)
+                    })
+                    .and(move |state| div(state, nh, m, qh, rh))
+            }))
+    })
+}
+
+///! Assert that `out` is `l` followed by `s`.
+fn append(state: State, l: Var<Num>, s: Var<Num>, out: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(l, Nil).unify(s, out);
+        state
+    }, {
+        fresh!(state, a, d, res);
+        state.unify(l, Pair(a, d)).unify(out, Pair(a, res));
+        append(state, d, s, res)
+    })
+}
+
+///! Assert that `n = 2^q + s` for some `s` less than `2^q`, where `b` is a list of ones which
+///! must be shorter than `n`.
+fn exp2(state: State, n: Var<Num>, b: Var<Num>, q: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, [One]).unify(q, Nil);
+        state
+    }, {
+        fresh!(state, s);
+        over_one(&mut state, n);
+        state.unify(q, [One]);
+        let one = state.make_var_of([One]);
+        split(state, n, b, s, one)
+    }, {
+        fresh!(state, q1, b2);
+        state.unify(q, Pair(Zero, q1));
+        pos(&mut state, q1);
+        lt_len(state, b, n)
+            .and(move |mut state| {
+                let one_b = state.make_var_of(Pair(One, b));
+                append(state, b, one_b, b2)
+            })
+            .and(move |state| exp2(state, n, b2, q1))
+    }, {
+        fresh!(state, q1, nh, b2, s);
+        state.unify(q, Pair(One, q1));
+        pos(&mut state, q1);
+        pos(&mut state, nh);
+        split(state, n, b, s, nh)
+            .and(move |mut state| {
+                let one_b = state.make_var_of(Pair(One, b));
+                append(state, b, one_b, b2)
+            })
+            .and(move |state| exp2(state, nh, b2, q1))
+    })
+}
+
+///! Assert that `n^q = nq`.
+fn repeated_mult(state: State, n: Var<Num>, q: Var<Num>, nq: Var<Num>) -> StateIter {
+    conde!(state, {
+        pos(&mut state, n);
+        state.unify(q, Nil).unify(nq, [One]);
+        state
+    }, {
+        state.unify(q, [One]).unify(n, nq);
+        state
+    }, {
+        fresh!(state, q1, nq1);
+        over_one(&mut state, q);
+        let one = state.make_var_of([One]);
+        plus(state, q1, one, q)
+            .and(move |state| repeated_mult(state, n, q1, nq1))
+            .and(move |state| mult(state, nq1, n, nq))
+    })
+}
+
+fn log(state: State, n: Var<Num>, b: Var<Num>, q: Var<Num>, r: Var<Num>) -> StateIter {
+    conde!(state, {
+        state.unify(n, [One]);
+        pos(&mut state, b);
+        state.unify(q, Nil).unify(r, Nil);
+        state
+    }, {
+        state.unify(q, Nil);
+        let one = state.make_var_of([One]);
+        lt(state, n, b).and(move |state| plus(state, r, one, n))
+    }, {
+        state.unify(q, [One]);
+        over_one(&mut state, b);
+        eq_len(state, n, b).and(move |state| plus(state, r, b, n))
+    }, {
+        state.unify(b, [One]);
+        pos(&mut state, q);
+        let one = state.make_var_of([One]);
+        plus(state, r, one, n)
+    }, {
+        state.unify(b, Nil);
+        pos(&mut state, q);
+        state.unify(r, n);
+        state
+    }, {
+        fresh!(state, dd, s);
+        state.unify(b, [Zero, One]);
+        pos(&mut state, dd);
+        state.unify(n, Pair(__(), Pair(__(), dd)));
+        let nil = state.make_var_of(Nil);
+        exp2(state, n, nil, q).and(move |state| split(state, n, dd, r, s))
+    }, {
+        conde!(state, {
+            state.unify(b, [One, One]);
+            state
+        }, {
+            state.unify(b, Pair(__(), Pair(__(), Pair(__(), __()))));
+            state
+        })
+        .and(move |state| lt_len(state, b, n))
+        .and(move |state| log_large_base(state, n, b, q, r))
+    })
+}
+
+///! The general case of `log`, for bases larger than 2.  This narrows `q` down to a range using
+///! the logarithms of `n` and `b` in base 2, then checks the candidates in that range.  Unlike the
+///! book, the goals which only depend on `n` and `b` run first and the upper bound on `q` is
+///! checked last, once `q` is known; in the book's order the search never finishes for `n` = 343
+///! and `b` = 7.
+fn log_large_base(mut state: State, n: Var<Num>, b: Var<Num>, q: Var<Num>, r: Var<Num>) -> StateIter {
+    fresh!(state, bw1, bw, nw, nw1, ql1, ql, s);
+    let nil = state.make_var_of(Nil);
+    let one = state.make_var_of([One]);
+    exp2(state, b, nil, bw1)
+        .and(move |state| plus(state, bw1, one, bw))
+        .and(move |state| exp2(state, n, nil, nw1))
+        .and(move |state| plus(state, nw1, one, nw))
+        .and(move |state| div(state, nw, bw, ql1, s))
+        .and(move |state| plus(state, ql, one, ql1))
+        .and(move |state| lt_len(state, q, n))
+        .and(move |state| conde!(state, {
+            eq_len(state, ql, q)
+        }, {
+            lt_len(state, ql, q)
+        }))
+        .and(move |mut state| {
+            fresh!(state, bql, qh, s, qdh, qd);
+            repeated_mult(state, b, ql, bql)
+                .and(move |state| div(state, nw, bw1, qh, s))
+                .and(move |state| plus(state, ql, qdh, qh))
+                .and(move |state| plus(state, ql, qd, q))
+                .and(move |state| conde!(state, {
+                    state.unify(qd, qdh);
+                    state
+                }, {
+                    lt(state, qd, qdh)
+                }))
+                .and(move |mut state| {
+                    fresh!(state, bqd, bq1, bq);
+                    repeated_mult(state, b, qd, bqd)
+                        .and(move |state| mult(state, bql, bqd, bq))
+                        .and(move |state| mult(state, b, bq, bq1))
+                        .and(move |state| plus(state, bq, r, n))
+                        .and(move |state| lt(state, n, bq1))
+                })
+        })
+        .and(move |mut state| {
+            fresh!(state, q1, bwq1);
+            plus(state, q, one, q1)
+                .and(move |state| mult(state, bw, q1, bwq1))
+                .and(move |state| lt(state, nw1, bwq1))
+        })
+}
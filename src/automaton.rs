@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::Peekable;
+use std::str::Chars;
+
+///! A deterministic finite automaton over `i64` symbols, as used by the `Regular` constraint.
+///! States are numbered from 0.  A symbol with no transition from the current state rejects the
+///! sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dfa {
+    start: usize,
+    accepting: Vec<bool>,
+    transitions: Vec<BTreeMap<i64, usize>>,
+}
+
+impl Dfa {
+    ///! Create an automaton with `states` states and no transitions.
+    pub fn new(states: usize, start: usize, accepting: &[usize]) -> Dfa {
+        assert!(start < states);
+        assert!(accepting.iter().all(|&state| state < states));
+        let mut dfa = Dfa { start: start, accepting: vec![false; states], transitions: vec![BTreeMap::new(); states] };
+        for &state in accepting.iter() {
+            dfa.accepting[state] = true;
+        }
+        dfa
+    }
+
+    ///! Add a transition from `from` to `to` on `symbol`, replacing any existing one.
+    pub fn transition(mut self, from: usize, symbol: i64, to: usize) -> Dfa {
+        assert!(from < self.accepting.len() && to < self.accepting.len());
+        self.transitions[from].insert(symbol, to);
+        self
+    }
+
+    pub fn states(&self) -> usize {
+        self.accepting.len()
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    ///! Return the state reached from `state` on `symbol`, if any.
+    pub fn next(&self, state: usize, symbol: i64) -> Option<usize> {
+        self.transitions[state].get(&symbol).cloned()
+    }
+
+    ///! Iterate over the symbols leaving `state` and the states they lead to.
+    pub fn transitions_from<'a>(&'a self, state: usize) -> Box<Iterator<Item=(i64, usize)> + 'a> {
+        Box::new(self.transitions[state].iter().map(|(&symbol, &to)| (symbol, to)))
+    }
+
+    ///! Return whether the automaton accepts a sequence of symbols.
+    pub fn accepts(&self, symbols: &[i64]) -> bool {
+        let mut state = self.start;
+        for &symbol in symbols.iter() {
+            state = match self.next(state, symbol) {
+                Some(next) => next,
+                None => { return false; },
+            };
+        }
+        self.accepting[state]
+    }
+
+    ///! Build an automaton from a regular expression over digits.  Each digit is a symbol, and
+    ///! `<n>` is the symbol `n`, for any integer.  `[...]` matches any of the symbols or ranges,
+    ///! like `[0-2<10>]`, and `|`, `*`, `+`, `?` and parentheses work as usual.  Whitespace is
+    ///! ignored.  Ranges may hold at most `REGEX_RANGE_LIMIT` symbols.
+    ///!
+    ///! For example, `([01]|2[01]|22[01])*(2|22)?` accepts sequences of 0, 1 and 2 with no more
+    ///! than two 2s in a row.
+    pub fn from_regex(pattern: &str) -> Result<Dfa, String> {
+        let mut parser = Parser { chars: pattern.chars().peekable() };
+        let regex = try!(parser.alternation());
+        if let Some(c) = parser.chars.next() {
+            return Err(format!("unexpected '{}' in regex", c));
+        }
+        let mut nfa = Nfa { epsilon: Vec::new(), symbols: Vec::new() };
+        let start = nfa.add_state();
+        let end = nfa.add_state();
+        nfa.build(&regex, start, end);
+        Ok(nfa.to_dfa(start, end))
+    }
+}
+
+enum Regex {
+    Empty,
+    Symbols(Vec<i64>),
+    Concat(Vec<Regex>),
+    Alternation(Vec<Regex>),
+    Star(Box<Regex>),
+    Plus(Box<Regex>),
+    Optional(Box<Regex>),
+}
+
+///! The most symbols a range in a regex may hold, since each becomes its own transition.
+pub const REGEX_RANGE_LIMIT: i64 = 4096;
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<char> {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() { return Some(c); }
+            self.chars.next();
+        }
+        None
+    }
+
+    fn alternation(&mut self) -> Result<Regex, String> {
+        let mut options = vec![try!(self.concat())];
+        while self.peek() == Some('|') {
+            self.chars.next();
+            options.push(try!(self.concat()));
+        }
+        Ok(if options.len() == 1 { options.pop().unwrap() } else { Regex::Alternation(options) })
+    }
+
+    fn concat(&mut self) -> Result<Regex, String> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                _ => { items.push(try!(self.repeat())); },
+            }
+        }
+        Ok(match items.len() {
+            0 => Regex::Empty,
+            1 => items.pop().unwrap(),
+            _ => Regex::Concat(items),
+        })
+    }
+
+    fn repeat(&mut self) -> Result<Regex, String> {
+        let mut item = try!(self.atom());
+        loop {
+            item = match self.peek() {
+                Some('*') => Regex::Star(Box::new(item)),
+                Some('+') => Regex::Plus(Box::new(item)),
+                Some('?') => Regex::Optional(Box::new(item)),
+                _ => { return Ok(item); },
+            };
+            self.chars.next();
+        }
+    }
+
+    fn atom(&mut self) -> Result<Regex, String> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let inner = try!(self.alternation());
+                if self.peek() != Some(')') { return Err("missing ')' in regex".to_string()); }
+                self.chars.next();
+                Ok(inner)
+            },
+            Some('[') => {
+                self.chars.next();
+                let mut symbols = Vec::new();
+                loop {
+                    if self.peek() == Some(']') {
+                        self.chars.next();
+                        break;
+                    }
+                    let lo = try!(self.symbol());
+                    if self.peek() == Some('-') {
+                        self.chars.next();
+                        let hi = try!(self.symbol());
+                        if hi < lo { return Err(format!("empty range {}-{} in regex", lo, hi)); }
+                        match hi.checked_sub(lo) {
+                            Some(width) if width < REGEX_RANGE_LIMIT => symbols.extend((0..width + 1).map(|x| lo + x)),
+                            _ => { return Err(format!("range {}-{} in regex is too wide", lo, hi)); },
+                        }
+                    } else {
+                        symbols.push(lo);
+                    }
+                }
+                Ok(Regex::Symbols(symbols))
+            },
+            _ => Ok(Regex::Symbols(vec![try!(self.symbol())])),
+        }
+    }
+
+    fn symbol(&mut self) -> Result<i64, String> {
+        match self.peek() {
+            Some('<') => {
+                self.chars.next();
+                let mut digits = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('>') => break,
+                        Some(c) => digits.push(c),
+                        None => { return Err("missing '>' in regex".to_string()); },
+                    }
+                }
+                digits.trim().parse().map_err(|_| format!("bad symbol <{}> in regex", digits))
+            },
+            Some(c) if c.is_digit(10) => {
+                self.chars.next();
+                Ok(c.to_digit(10).unwrap() as i64)
+            },
+            Some(c) => Err(format!("unexpected '{}' in regex", c)),
+            None => Err("unexpected end of regex".to_string()),
+        }
+    }
+}
+
+///! A nondeterministic automaton with epsilon transitions, built from a `Regex` by Thompson's
+///! construction.
+struct Nfa {
+    epsilon: Vec<Vec<usize>>,
+    symbols: Vec<Vec<(i64, usize)>>,
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> usize {
+        self.epsilon.push(Vec::new());
+        self.symbols.push(Vec::new());
+        self.epsilon.len() - 1
+    }
+
+    ///! Add states matching `regex` on the way from `from` to `to`.
+    fn build(&mut self, regex: &Regex, from: usize, to: usize) {
+        match *regex {
+            Regex::Empty => { self.epsilon[from].push(to); },
+            Regex::Symbols(ref symbols) => {
+                for &symbol in symbols.iter() {
+                    self.symbols[from].push((symbol, to));
+                }
+            },
+            Regex::Concat(ref items) => {
+                let mut current = from;
+                for (i, item) in items.iter().enumerate() {
+                    let next = if i + 1 == items.len() { to } else { self.add_state() };
+                    self.build(item, current, next);
+                    current = next;
+                }
+            },
+            Regex::Alternation(ref options) => {
+                for option in options.iter() {
+                    self.build(option, from, to);
+                }
+            },
+            Regex::Star(ref inner) | Regex::Plus(ref inner) => {
+                // Loop through a separate state, so that the repetition can't leak into whatever
+                // else leaves `from` or enters `to`.
+                let (loop_start, loop_end) = (self.add_state(), self.add_state());
+                self.epsilon[from].push(loop_start);
+                self.build(inner, loop_start, loop_end);
+                self.epsilon[loop_end].push(loop_start);
+                self.epsilon[loop_end].push(to);
+                if let Regex::Star(_) = *regex {
+                    self.epsilon[from].push(to);
+                }
+            },
+            Regex::Optional(ref inner) => {
+                self.build(inner, from, to);
+                self.epsilon[from].push(to);
+            },
+        }
+    }
+
+    fn closure(&self, states: &mut BTreeSet<usize>) {
+        let mut pending: Vec<usize> = states.iter().cloned().collect();
+        while let Some(state) = pending.pop() {
+            for &next in self.epsilon[state].iter() {
+                if states.insert(next) { pending.push(next); }
+            }
+        }
+    }
+
+    ///! Convert to a `Dfa` by the subset construction.
+    fn to_dfa(&self, start: usize, end: usize) -> Dfa {
+        let mut initial = BTreeSet::new();
+        initial.insert(start);
+        self.closure(&mut initial);
+
+        let mut ids = BTreeMap::new();
+        ids.insert(initial.clone(), 0);
+        let mut sets = vec![initial];
+        let mut transitions = Vec::new();
+        let mut i = 0;
+        while i < sets.len() {
+            let mut moves: BTreeMap<i64, BTreeSet<usize>> = BTreeMap::new();
+            for &state in sets[i].iter() {
+                for &(symbol, next) in self.symbols[state].iter() {
+                    moves.entry(symbol).or_insert_with(BTreeSet::new).insert(next);
+                }
+            }
+            let mut row = BTreeMap::new();
+            for (symbol, mut set) in moves.into_iter() {
+                self.closure(&mut set);
+                let id = match ids.get(&set) {
+                    Some(&id) => id,
+                    None => {
+                        sets.push(set.clone());
+                        sets.len() - 1
+                    },
+                };
+                ids.insert(set, id);
+                row.insert(symbol, id);
+            }
+            transitions.push(row);
+            i += 1;
+        }
+        let accepting = sets.iter().map(|set| set.contains(&end)).collect();
+        Dfa { start: 0, accepting: accepting, transitions: transitions }
+    }
+}
@@ -1,14 +1,20 @@
 use std::ops::{Add, Sub};
+use std::cmp::{min, max};
+use std::fmt::Debug;
 use core::{ToVar, ToConstraint, Constraint, Var, StateProxy, ConstraintResult, VarStore, Unifier, VarRetrieve, VarMap, UntypedVar, VarWrapper};
+use core::Assignment;
 use core::StateInner as State;
 use core::ConstraintResult::*;
-use finitedomain::Fd;
-use finitedomain::Fd::*;
-use std::collections::HashSet;
+use finitedomain::{Fd, FiniteDomain};
+use domain::Domain;
+use automaton::Dfa;
 use std::borrow::Cow;
+use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
 use core::disequal::Disequal as VarDisequal;
 
-///! The Disequal constraint enforces that its arguments will never have equal values.
+///! The Disequal constraint enforces that its arguments will never have equal values.  For types
+///! like `Fd`, once one side has a single value it's removed from the other side's domain.
 pub struct Disequal<A, B, C>
 where A: ToVar<VarType=C>, B: ToVar<VarType=C>, C: ToVar + VarWrapper {
     a: A,
@@ -129,40 +135,83 @@ where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
     }
 }
 
+///! Return how far apart two values are, as used by `Constraint::violations`.
+fn distance(a: i64, b: i64) -> usize {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high.wrapping_sub(low) as u64 as usize
+}
+
 impl Constraint for VarFdSumConstraint {
     fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdSumConstraint> {
         let (l, r, result) = {
-            let l = state.get_value(self.l);//.and_then(|x| x.single_value());
-            let r = state.get_value(self.r);//.and_then(|x| x.single_value());
-            let result = state.get_value(self.result);//.and_then(|x| x.single_value());
+            let l = state.get_value(self.l).map(|x| x.clone());
+            let r = state.get_value(self.r).map(|x| x.clone());
+            let result = state.get_value(self.result).map(|x| x.clone());
             //println!("sumconstraint: l = {:?}, r = {:?}, result = {:?}", l, r, result);
-            (l.and_then(|x| x.single_value()),
-                r.and_then(|x| x.single_value()),
-                result.and_then(|x| x.single_value()))
+            (l, r, result)
         };
+        let singles = (l.as_ref().and_then(Fd::single_value),
+            r.as_ref().and_then(Fd::single_value),
+            result.as_ref().and_then(Fd::single_value));
 
-        match (l, r, result) {
+        match singles {
             (Some(l), Some(r), _) => {
-                //let result = state.make_var_of(l + r);
-                let result = Fd::new_single(l + r);
+                let result = match l.checked_add(r) {
+                    Some(x) => Fd::new_single(x),
+                    None => { return Failed; }
+                };
                 //println!("sumconstraint: creating result {:?} and unifying with {:?}", l + r, self.result);
                 state.unify(self.result, result);
-                if state.ok() { Irrelevant } else { Failed }
+                return if state.ok() { Irrelevant } else { Failed };
             },
             (Some(l), _, Some(result)) => {
-                if l > result { return Failed; }
-                let r = Fd::new_single(result - l);
+                let r = match result.checked_sub(l) {
+                    Some(x) => Fd::new_single(x),
+                    None => { return Failed; }
+                };
                 state.unify(r, self.r);
-                if state.ok() { Irrelevant } else { Failed }
+                return if state.ok() { Irrelevant } else { Failed };
             },
             (_, Some(r), Some(result)) => {
-                if r > result { return Failed; }
-                let l = Fd::new_single(result - r);
+                let l = match result.checked_sub(r) {
+                    Some(x) => Fd::new_single(x),
+                    None => { return Failed; }
+                };
                 state.unify(l, self.l);
-                if state.ok() { Irrelevant } else { Failed }
+                return if state.ok() { Irrelevant } else { Failed };
             },
-            _ => Unchanged
+            _ => { }
+        }
+
+        // Without two known values, we can still narrow each domain to the bounds allowed by the
+        // other two.
+        let (orig_l, orig_r, orig_result) = match (l, r, result) {
+            (Some(l), Some(r), Some(result)) => (l, r, result),
+            _ => { return Unchanged; }
+        };
+        let (mut l, mut r, mut result) = (orig_l.clone(), orig_r.clone(), orig_result.clone());
+        loop {
+            let ((lmin, lmax), (rmin, rmax), (min, max)) = match (l.bounds(), r.bounds(), result.bounds()) {
+                (Some(l), Some(r), Some(result)) => (l, r, result),
+                _ => { return Failed; }
+            };
+            let new_result = result.constrain_range(Some(lmin.saturating_add(rmin)), Some(lmax.saturating_add(rmax)));
+            let new_l = l.constrain_range(Some(min.saturating_sub(rmax)), Some(max.saturating_sub(rmin)));
+            let new_r = r.constrain_range(Some(min.saturating_sub(lmax)), Some(max.saturating_sub(lmin)));
+            if new_l == l && new_r == r && new_result == result {
+                break;
+            }
+            l = new_l;
+            r = new_r;
+            result = new_result;
+        }
+        let done = l.single_value().is_some() && r.single_value().is_some();
+        unsafe {
+            if l != orig_l { state.overwrite_var(self.l, l); }
+            if r != orig_r { state.overwrite_var(self.r, r); }
+            if result != orig_result { state.overwrite_var(self.result, result); }
         }
+        if done { Irrelevant } else { Unchanged }
     }
     fn relevant(&self, proxy: &VarMap) -> bool {
         proxy.contains_key(&self.l.untyped())
@@ -174,6 +223,12 @@ impl Constraint for VarFdSumConstraint {
         proxy.update_var(self.r.untyped_mut());
         proxy.update_var(self.result.untyped_mut());
     }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        match (values.get(self.l), values.get(self.r), values.get(self.result)) {
+            (Some(l), Some(r), Some(result)) => Some(distance(l.saturating_add(r), result)),
+            _ => None,
+        }
+    }
 }
 
 ///! Constrains two finite domain variables so that A <= B.
@@ -182,13 +237,15 @@ pub struct FdLessOrEqual<A, B>
 where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
     l: A,
     r: B,
+    // A + offset <= B
+    offset: i64,
 }
 pub type VarFdLessOrEqual = FdLessOrEqual<Var<Fd>, Var<Fd>>;
 
 impl<A, B> FdLessOrEqual<A, B>
 where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
     pub fn new(l: A, r: B) -> FdLessOrEqual<A, B> {
-        FdLessOrEqual { l: l, r: r }
+        FdLessOrEqual { l: l, r: r, offset: 0 }
     }
 }
 
@@ -198,73 +255,66 @@ where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
     fn into_constraint(self, state: &mut State) -> VarFdLessOrEqual {
         let l = state.make_var_of(self.l);
         let r = state.make_var_of(self.r);
-        FdLessOrEqual { l: l, r: r }
+        FdLessOrEqual { l: l, r: r, offset: self.offset }
+    }
+}
+
+///! Constrains two finite domain variables so that A < B.
+#[derive(Debug, Clone)]
+pub struct FdLessThan<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    l: A,
+    r: B,
+}
+
+impl<A, B> FdLessThan<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(l: A, r: B) -> FdLessThan<A, B> {
+        FdLessThan { l: l, r: r }
     }
 }
 
-fn constrain_range(min: Option<usize>, max: Option<usize>, vals: &[usize]) -> Fd {
-    let end = max.map(|max| match vals.binary_search(&max) {
-        Ok(x) => x + 1,
-        Err(x) => x,
-    }).unwrap_or(vals.len());
-    let start = min.map(|min| match vals.binary_search(&min) {
-        Ok(x) => x,
-        Err(x) => x,
-    }).unwrap_or(0);
-    Fd::Values(vals[start..end].iter().map(|x| *x).collect())
+impl<A, B> ToConstraint for FdLessThan<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdLessOrEqual;
+    fn into_constraint(self, state: &mut State) -> VarFdLessOrEqual {
+        let l = state.make_var_of(self.l);
+        let r = state.make_var_of(self.r);
+        FdLessOrEqual { l: l, r: r, offset: 1 }
+    }
 }
 
 impl Constraint for VarFdLessOrEqual {
     fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdLessOrEqual> {
         use core::ConstraintResult::*;
-        let (new_l, new_r) = {
+        let (new_l, new_r, changed_l, changed_r) = {
             let (l, r) = match (state.get_value(self.l), state.get_value(self.r)) {
                 (Some(l), Some(r)) => (l, r),
                 _ => { return Unchanged; },
             };
-            match (l, r) {
-                (&Values(ref lvals), &Values(ref rvals)) => {
-                    //println!("a");
-                    (constrain_range(None, Some(*rvals.last().unwrap()), &*lvals),
-                     constrain_range(Some(*lvals.first().unwrap()), None, &*rvals))
-                },
-                (&Values(ref lvals), &Single(max)) => {
-                    //println!("b");
-                    (constrain_range(None, Some(max), &*lvals),
-                    match max < *lvals.first().unwrap() {
-                        true => Fd::err(),
-                        false => Fd::new_single(max),
-                    })
-                },
-                (&Single(min), &Values(ref rvals)) => {
-                    //println!("c");
-                    (match *rvals.last().unwrap() < min {
-                        true => Fd::err(),
-                        false => Fd::new_single(min),
-                    },
-                    constrain_range(Some(min), None, &*rvals))
-                },
-                (&Single(min), &Single(max)) => {
-                    //println!("d");
-                    match max < min {
-                        true => (Fd::err(), Fd::err()),
-                        false => (Fd::new_single(min), Fd::new_single(max)),
-                    }
-                },
-            }
+            let (min, max) = match (l.min(), r.max()) {
+                (Some(min), Some(max)) => (min, max),
+                _ => { return Failed; },
+            };
+            let new_l = l.constrain_range(None, Some(max.saturating_sub(self.offset)));
+            let new_r = r.constrain_range(Some(min.saturating_add(self.offset)), None);
+            let (changed_l, changed_r) = (new_l != *l, new_r != *r);
+            (new_l, new_r, changed_l, changed_r)
         };
         //println!("got result values: {:?} and {:?}", new_l, new_r);
         if !new_l.is_valid() || !new_r.is_valid() {
             //println!("returning early, failed");
             return Failed;
         }
-        let result = match new_l.single_value().is_some() || new_r.single_value().is_some() {
+        // Once every value of A is no greater than every value of B, nothing can break the
+        // constraint.
+        let result = match new_l.max().map(|x| x.saturating_add(self.offset)) <= new_r.min() {
             true => Irrelevant,
             false => Unchanged,
         };
         unsafe {
-            state.overwrite_var(self.l, new_l);
-            state.overwrite_var(self.r, new_r);
+            if changed_l { state.overwrite_var(self.l, new_l); }
+            if changed_r { state.overwrite_var(self.r, new_r); }
         }
         result
     }
@@ -275,175 +325,3095 @@ impl Constraint for VarFdLessOrEqual {
         proxy.update_var(self.l.untyped_mut());
         proxy.update_var(self.r.untyped_mut());
     }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        let (l, r) = match (values.get(self.l), values.get(self.r)) {
+            (Some(l), Some(r)) => (l.saturating_add(self.offset), r),
+            _ => { return None; },
+        };
+        Some(if l <= r { 0 } else { distance(l, r) })
+    }
 }
 
-impl ToConstraint for AllDiffConstraint {
-    type ConstraintType = AllDiffConstraint;
-    fn into_constraint(self, _: &mut State) -> AllDiffConstraint { self }
+///! Constrains three finite domain variables so that A - B = C.
+#[derive(Debug, Clone)]
+pub struct FdDifferenceConstraint<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    l: A,
+    r: B,
+    result: C,
 }
 
-///! Constrains a set of Fds to have distinct values from one another.
-#[derive(Debug, Clone)]
-pub struct AllDiffConstraint {
-    fds: Vec<Var<Fd>>,
+impl<A, B, C> FdDifferenceConstraint<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    pub fn new(l: A, r: B, result: C) -> FdDifferenceConstraint<A, B, C> {
+        FdDifferenceConstraint { l: l, r: r, result: result }
+    }
 }
 
-impl AllDiffConstraint {
-    pub fn new(fds: Vec<Var<Fd>>) -> AllDiffConstraint {
-        AllDiffConstraint { fds: fds }
+impl<A, B, C> ToConstraint for FdDifferenceConstraint<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdSumConstraint;
+    fn into_constraint(self, state: &mut State) -> VarFdSumConstraint {
+        // A - B = C is the same as B + C = A.
+        let l = state.make_var_of(self.l);
+        let r = state.make_var_of(self.r);
+        let result = state.make_var_of(self.result);
+        FdSumConstraint { l: r, r: result, result: l }
     }
+}
 
-    fn remove_singles(varfds: &mut Vec<(Var<Fd>, Option<Fd>)>, singles: &mut HashSet<usize>, mut state: Option<&mut StateProxy>, remove_var: bool) -> bool {
-        for i in (0..varfds.len()).rev() {
-            let remove = {
-                let (_, ref fd) = varfds[i];
-                if let Some(ref fd) = *fd {
-                    if let Some(single) = fd.single_value() {
-                        //println!("value for {:?}: {:?}", fd, single);
-                        if !singles.insert(single) {
-                            return false;
-                        }
-                        true
-                    } else { false }
-                } else { false }
-            };
-            if remove {
-                let (var, fd) = if remove_var {
-                    varfds.swap_remove(i)
+///! Domains with at most this many pairs of values are propagated by trying every pair, which
+///! removes every unsupported value.  Larger ones are only narrowed by their bounds.
+const FD_ENUMERATION_LIMIT: usize = 4096;
+
+///! The operations supported by `VarFdArithConstraint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FdOp {
+    Product,
+    Quotient,
+    Remainder,
+    Min,
+    Max,
+}
+
+fn abs_bound(x: i64) -> i64 {
+    if x == ::std::i64::MIN { ::std::i64::MAX } else { x.abs() }
+}
+
+fn div_floor(a: i64, b: i64) -> i64 {
+    match a.checked_div(b) {
+        Some(q) if (a % b != 0) && ((a < 0) != (b < 0)) => q - 1,
+        Some(q) => q,
+        None => ::std::i64::MAX,
+    }
+}
+
+fn div_ceil(a: i64, b: i64) -> i64 {
+    match a.checked_div(b) {
+        Some(q) if (a % b != 0) && ((a < 0) == (b < 0)) => q + 1,
+        Some(q) => q,
+        None => ::std::i64::MAX,
+    }
+}
+
+///! Split a domain into its negative and positive parts, leaving out zero.
+fn sign_parts(fd: &Fd) -> Vec<(i64, i64)> {
+    let neg = fd.constrain_range(None, Some(-1));
+    let pos = fd.constrain_range(Some(1), None);
+    neg.bounds().into_iter().chain(pos.bounds().into_iter()).collect()
+}
+
+///! Return the values x for which x * y is in `c` for some y in `d`.
+fn product_factors(c: &Fd, d: &Fd) -> Fd {
+    if c.in_range(0) && d.in_range(0) {
+        return Fd::new_range(::std::i64::MIN, ::std::i64::MAX);
+    }
+    let (cmin, cmax) = match c.bounds() {
+        Some(x) => x,
+        None => { return Fd::err(); },
+    };
+    let ranges = sign_parts(d).into_iter().map(|(lo, hi)| {
+        let corners = [(cmin, lo), (cmin, hi), (cmax, lo), (cmax, hi)];
+        let min = corners.iter().map(|&(c, d)| div_ceil(c, d)).min().unwrap();
+        let max = corners.iter().map(|&(c, d)| div_floor(c, d)).max().unwrap();
+        (min, max)
+    }).collect();
+    Fd::new_intervals(ranges)
+}
+
+impl FdOp {
+    fn apply(self, a: i64, b: i64) -> Option<i64> {
+        use self::FdOp::*;
+        match self {
+            Product => a.checked_mul(b),
+            Quotient => a.checked_div(b),
+            Remainder => a.checked_rem(b),
+            Min => Some(min(a, b)),
+            Max => Some(max(a, b)),
+        }
+    }
+
+    ///! Narrow the domains using their bounds, for domains too large to enumerate.
+    fn narrow(self, a: &Fd, b: &Fd, c: &Fd) -> (Fd, Fd, Fd) {
+        use self::FdOp::*;
+        let ((amin, amax), (bmin, bmax)) = match (a.bounds(), b.bounds(), c.is_valid()) {
+            (Some(a), Some(b), true) => (a, b),
+            _ => { return (Fd::err(), Fd::err(), Fd::err()); },
+        };
+        let zero = Fd::new_single(0);
+        match self {
+            Product => {
+                let corners = [amin.saturating_mul(bmin), amin.saturating_mul(bmax),
+                    amax.saturating_mul(bmin), amax.saturating_mul(bmax)];
+                let c = c.constrain_range(corners.iter().cloned().min(), corners.iter().cloned().max());
+                let (a, b) = if c.in_range(0) { (a.clone(), b.clone()) } else { (a.difference(&zero), b.difference(&zero)) };
+                let new_a = a.combine(&product_factors(&c, &b));
+                let new_b = b.combine(&product_factors(&c, &new_a));
+                (new_a, new_b, c)
+            },
+            Quotient => {
+                let b = b.difference(&zero);
+                let quotients = sign_parts(&b).into_iter().map(|(lo, hi)| {
+                    let corners = [(amin, lo), (amin, hi), (amax, lo), (amax, hi)];
+                    let values: Vec<i64> = corners.iter().map(|&(a, b)| a.checked_div(b).unwrap_or(::std::i64::MAX)).collect();
+                    (*values.iter().min().unwrap(), *values.iter().max().unwrap())
+                }).collect();
+                let c = c.combine(&Fd::new_intervals(quotients));
+                // A = B * C + R, where |R| < |B|.
+                let (bmin, bmax) = match b.bounds() { Some(x) => x, None => { return (Fd::err(), b, c); }, };
+                let (cmin, cmax) = match c.bounds() { Some(x) => x, None => { return (a.clone(), b, c); }, };
+                let corners = [bmin.saturating_mul(cmin), bmin.saturating_mul(cmax),
+                    bmax.saturating_mul(cmin), bmax.saturating_mul(cmax)];
+                let slack = max(abs_bound(bmin), abs_bound(bmax)) - 1;
+                let a = a.constrain_range(corners.iter().cloned().min().map(|x| x.saturating_sub(slack)),
+                    corners.iter().cloned().max().map(|x| x.saturating_add(slack)));
+                (a, b, c)
+            },
+            Remainder => {
+                // The remainder takes the sign of A, and is smaller than both A and B.
+                let b = b.difference(&zero);
+                let limit = max(abs_bound(bmin), abs_bound(bmax)) - 1;
+                let c = c.constrain_range(Some(max(-limit, min(0, amin))), Some(min(limit, max(0, amax))));
+                let (cmin, cmax) = match c.bounds() { Some(x) => x, None => { return (a.clone(), b, c); }, };
+                let (a, b) = if cmin > 0 {
+                    (a.constrain_range(Some(cmin), None), b.difference(&Fd::new_range(-cmin, cmin)))
+                } else if cmax < 0 {
+                    (a.constrain_range(None, Some(cmax)), b.difference(&Fd::new_range(cmax, -cmax)))
                 } else {
-                    let &mut (var, ref mut fd) = &mut varfds[i];
-                    (var, fd.take())
+                    (a.clone(), b)
                 };
-                let fd = fd.unwrap();
-                if let Some(ref mut state) = state {
-                    unsafe { state.overwrite_var(var, fd); }
+                (a, b, c)
+            },
+            Min | Max => {
+                let (a, b, c) = if self == Min {
+                    (a.clone(), b.clone(), c.clone())
+                } else {
+                    (a.negate(), b.negate(), c.negate())
+                };
+                // C is one of A and B, and neither is less than C.
+                let c = c.combine(&a.union(&b)).constrain_range(None, min(a.max(), b.max()));
+                let (a, b) = (a.constrain_range(c.min(), None), b.constrain_range(c.min(), None));
+                let a = if b.min() > c.max() { a.combine(&c) } else { a };
+                let b = if a.min() > c.max() { b.combine(&c) } else { b };
+                if self == Min { (a, b, c) } else { (a.negate(), b.negate(), c.negate()) }
+            },
+        }
+    }
+}
+
+///! Return the values of A and B for which `op` gives a result in C, along with those results.
+fn fd_support(op: FdOp, a: &Fd, b: &Fd, c: &Fd) -> (Fd, Fd, Fd) {
+    let (mut new_a, mut new_b, mut new_c) = (Vec::new(), Vec::new(), Vec::new());
+    for x in a.iter() {
+        let mut supported = false;
+        for y in b.iter() {
+            if let Some(z) = op.apply(x, y) {
+                if c.in_range(z) {
+                    supported = true;
+                    new_b.push(y);
+                    new_c.push(z);
                 }
             }
         }
-        true
+        if supported { new_a.push(x); }
     }
+    new_b.sort();
+    new_b.dedup();
+    new_c.sort();
+    new_c.dedup();
+    (Fd::new_values(new_a), Fd::new_values(new_b), Fd::new_values(new_c))
 }
 
-impl Constraint for AllDiffConstraint {
-    fn update(&self, state: &mut StateProxy) -> ConstraintResult<AllDiffConstraint> {
-        use core::ConstraintResult::*;
-        // unique values which must be removed from other fds
-        // indexes into self.fds, and later replace.fds, for unique fds which can be removed
-        if !self.fds.iter().any(|&var| {
-            state.get_changed_value(var).and_then(|fd| fd.single_value()).is_some()
-        }) {
-            return Unchanged;
-        }
-        let mut varfds: Vec<(Var<Fd>, Option<Fd>)> = self.fds.iter().map(|fd| {
-            (*fd, state.get_value(*fd).map(|x| x.clone()))
-        }).collect();
-        let mut singles = HashSet::new();
-        let all_set = varfds.iter().all(|&(_, ref val)| val.is_some());
-        if !AllDiffConstraint::remove_singles(&mut varfds, &mut singles, None, all_set) {
-            return Failed;
-        }
-        //println!("got {} singles", singles.len());
-        loop {
-            if singles.is_empty() {
-                let overwrite = varfds.into_iter().map(|(var, fd)| {
-                    //println!("overwriting {:?} with {:?}", var, fd);
-                    if let Some(fd) = fd {
-                        unsafe { state.overwrite_var(var, fd); }
-                    }
-                    var
-                });
-                return if all_set {
-                    let replace = AllDiffConstraint::new(overwrite.collect());
-                    Updated(replace)
-                } else {
-                    for _ in overwrite { }
-                    Unchanged
+///! Implementation of the Fd product, quotient, remainder, min and max constraints.  Don't use this
+///! directly, use `FdProductConstraint` and the rest.
+#[derive(Debug, Clone)]
+pub struct VarFdArithConstraint {
+    op: FdOp,
+    l: Var<Fd>,
+    r: Var<Fd>,
+    result: Var<Fd>,
+}
+
+impl Constraint for VarFdArithConstraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdArithConstraint> {
+        let (l, r, result) = {
+            let l = state.get_value(self.l).map(|x| x.clone());
+            let r = state.get_value(self.r).map(|x| x.clone());
+            let result = state.get_value(self.result).map(|x| x.clone());
+            (l, r, result)
+        };
+        let (orig_l, orig_r, orig_result) = match (l, r, result) {
+            (Some(l), Some(r), Some(result)) => (l, r, result),
+            (Some(l), Some(r), None) => {
+                // Without a domain for the result, we can only fill it in once both sides are known.
+                return match (l.single_value(), r.single_value()) {
+                    (Some(l), Some(r)) => match self.op.apply(l, r) {
+                        Some(x) => {
+                            state.unify(self.result, Fd::new_single(x));
+                            if state.ok() { Irrelevant } else { Failed }
+                        },
+                        None => Failed,
+                    },
+                    _ => Unchanged,
                 };
-            }
-            if varfds.is_empty() {
-                return Irrelevant;
-            }
+            },
+            _ => { return Unchanged; },
+        };
 
-            //println!("removing values {:?} from {} fds", singles, varfds.len());
-            for &mut (_, ref mut fd) in varfds.iter_mut() {
-                if let Some(ref mut fd) = *fd {
-                    fd.remove_values(&singles);
-                    if !fd.is_valid() {
-                        return Failed;
-                    }
-                }
-            }
-            singles.clear();
-            if !AllDiffConstraint::remove_singles(&mut varfds, &mut singles, Some(state), all_set) {
+        let (mut l, mut r, mut result) = (orig_l.clone(), orig_r.clone(), orig_result.clone());
+        loop {
+            let small = l.value_count().saturating_mul(r.value_count()) <= FD_ENUMERATION_LIMIT;
+            let (new_l, new_r, new_result) = if small {
+                fd_support(self.op, &l, &r, &result)
+            } else {
+                self.op.narrow(&l, &r, &result)
+            };
+            if !new_l.is_valid() || !new_r.is_valid() || !new_result.is_valid() {
                 return Failed;
             }
+            let unchanged = new_l == l && new_r == r && new_result == result;
+            l = new_l;
+            r = new_r;
+            result = new_result;
+            // Enumerating leaves nothing for another pass to remove.
+            if small || unchanged {
+                break;
+            }
+        }
+        let done = l.single_value().is_some() && r.single_value().is_some();
+        unsafe {
+            if l != orig_l { state.overwrite_var(self.l, l); }
+            if r != orig_r { state.overwrite_var(self.r, r); }
+            if result != orig_result { state.overwrite_var(self.result, result); }
         }
+        if done { Irrelevant } else { Unchanged }
     }
     fn relevant(&self, proxy: &VarMap) -> bool {
-        self.fds.iter().any(|fd| {
-            proxy.contains_key(&fd.untyped())
-        })
+        proxy.contains_key(&self.l.untyped())
+            || proxy.contains_key(&self.r.untyped())
+            || proxy.contains_key(&self.result.untyped())
     }
     fn update_vars(&mut self, proxy: &State) {
-        for fd in self.fds.iter_mut() {
-            proxy.update_var(fd.untyped_mut());
-        }
+        proxy.update_var(self.l.untyped_mut());
+        proxy.update_var(self.r.untyped_mut());
+        proxy.update_var(self.result.untyped_mut());
     }
-    fn need_update(&self, vars: &VarMap) -> bool {
-        self.fds.iter().any(|fd| { vars.need_update(fd.untyped()) })
+}
+
+macro_rules! fd_arith_constraint {
+    ($(#[$attr:meta])* struct $name:ident => $op:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone)]
+        pub struct $name<A, B, C>
+        where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+            l: A,
+            r: B,
+            result: C,
+        }
+
+        impl<A, B, C> $name<A, B, C>
+        where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+            pub fn new(l: A, r: B, result: C) -> $name<A, B, C> {
+                $name { l: l, r: r, result: result }
+            }
+        }
+
+        impl<A, B, C> ToConstraint for $name<A, B, C>
+        where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+            type ConstraintType = VarFdArithConstraint;
+            fn into_constraint(self, state: &mut State) -> VarFdArithConstraint {
+                let l = state.make_var_of(self.l);
+                let r = state.make_var_of(self.r);
+                let result = state.make_var_of(self.result);
+                VarFdArithConstraint { op: $op, l: l, r: r, result: result }
+            }
+        }
     }
 }
 
-///! Constrains an Fd and a usize to have the same value.
+fd_arith_constraint! {
+    ///! Constrains three finite domain variables so that A * B = C.
+    struct FdProductConstraint => FdOp::Product
+}
+
+fd_arith_constraint! {
+    ///! Constrains three finite domain variables so that A / B = C, rounding toward zero.
+    struct FdQuotientConstraint => FdOp::Quotient
+}
+
+fd_arith_constraint! {
+    ///! Constrains three finite domain variables so that A % B = C.  As with Rust's `%`, C takes
+    ///! the sign of A.
+    struct FdRemainderConstraint => FdOp::Remainder
+}
+
+fd_arith_constraint! {
+    ///! Constrains three finite domain variables so that min(A, B) = C.
+    struct FdMinConstraint => FdOp::Min
+}
+
+fd_arith_constraint! {
+    ///! Constrains three finite domain variables so that max(A, B) = C.
+    struct FdMaxConstraint => FdOp::Max
+}
+
+///! Constrains two finite domain variables so that |A| = B.
 #[derive(Debug, Clone)]
-pub struct FdUsizeConstraint<A, B>
-where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
-    fd: A,
-    u: B,
+pub struct FdAbsConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    a: A,
+    b: B,
 }
-pub type VarFdUsizeConstraint = FdUsizeConstraint<Var<Fd>, Var<usize>>;
+pub type VarFdAbsConstraint = FdAbsConstraint<Var<Fd>, Var<Fd>>;
 
-impl<A, B> ToConstraint for FdUsizeConstraint<A, B>
-where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
-    type ConstraintType = FdUsizeConstraint<Var<Fd>, Var<usize>>;
-    fn into_constraint(self, state: &mut State) -> VarFdUsizeConstraint {
-        let fd = state.make_var_of(self.fd);
-        let u = state.make_var_of(self.u);
-        FdUsizeConstraint { fd: fd, u: u }
+impl<A, B> FdAbsConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(a: A, b: B) -> FdAbsConstraint<A, B> {
+        FdAbsConstraint { a: a, b: b }
     }
 }
 
-impl<A, B> FdUsizeConstraint<A, B>
-where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
-    pub fn new(fd: A, u: B) -> FdUsizeConstraint<A, B> {
-        FdUsizeConstraint { fd: fd, u: u }
+impl<A, B> ToConstraint for FdAbsConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdAbsConstraint;
+    fn into_constraint(self, state: &mut State) -> VarFdAbsConstraint {
+        let a = state.make_var_of(self.a);
+        let b = state.make_var_of(self.b);
+        FdAbsConstraint { a: a, b: b }
     }
 }
 
-impl Constraint for VarFdUsizeConstraint {
-    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdUsizeConstraint> {
-        use core::ConstraintResult::*;
-        let single_value = {
-            if let Some(u) = state.get_value(self.u) { Some(*u) }
-            else if let Some(fd) = state.get_value(self.fd) {
-                fd.single_value()
-            }
-            else { None }
+impl Constraint for VarFdAbsConstraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdAbsConstraint> {
+        let (a, b) = (state.get_value(self.a).map(|x| x.clone()), state.get_value(self.b).map(|x| x.clone()));
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(a), None) => {
+                return match a.single_value() {
+                    Some(x) => {
+                        state.unify(self.b, Fd::new_single(abs_bound(x)));
+                        if state.ok() { Irrelevant } else { Failed }
+                    },
+                    None => Unchanged,
+                };
+            },
+            _ => { return Unchanged; },
         };
-        if let Some(x) = single_value {
-            state.unify(Single(x), self.fd);
-            state.unify(x, self.u);
-            Irrelevant
-        } else {
-            Unchanged
+        let nonneg = a.constrain_range(Some(0), None);
+        let neg = a.constrain_range(None, Some(-1)).negate();
+        let new_b = b.combine(&nonneg.union(&neg));
+        let new_a = a.combine(&new_b.union(&new_b.negate()));
+        if !new_a.is_valid() || !new_b.is_valid() {
+            return Failed;
+        }
+        let done = new_a.single_value().is_some();
+        unsafe {
+            if new_a != a { state.overwrite_var(self.a, new_a); }
+            if new_b != b { state.overwrite_var(self.b, new_b); }
         }
+        if done { Irrelevant } else { Unchanged }
     }
     fn relevant(&self, proxy: &VarMap) -> bool {
-        proxy.contains_key(&self.fd.untyped()) || proxy.contains_key(&self.u.untyped())
+        proxy.contains_key(&self.a.untyped()) || proxy.contains_key(&self.b.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.a.untyped_mut());
+        proxy.update_var(self.b.untyped_mut());
+    }
+}
+
+///! The relation between the weighted sum and the constant in an `FdLinear` constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdRelation {
+    Equal,
+    LessOrEqual,
+    NotEqual,
+}
+
+///! Constrains a weighted sum of finite domain variables, so that
+///! `coef1 * var1 + coef2 * var2 + ... <relation> constant`.  Domains are narrowed using the bounds
+///! of the other terms.  A sum which doesn't fit in an `i64` is never equal or less than the
+///! constant.
+#[derive(Debug, Clone)]
+pub struct FdLinear<A> where A: ToVar<VarType=Fd> {
+    terms: Vec<(i64, A)>,
+    relation: FdRelation,
+    constant: i64,
+}
+pub type VarFdLinear = FdLinear<Var<Fd>>;
+
+impl<A> FdLinear<A> where A: ToVar<VarType=Fd> {
+    pub fn new(terms: Vec<(i64, A)>, relation: FdRelation, constant: i64) -> FdLinear<A> {
+        FdLinear { terms: terms, relation: relation, constant: constant }
+    }
+}
+
+impl<A> ToConstraint for FdLinear<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdLinear;
+    fn into_constraint(self, state: &mut State) -> VarFdLinear {
+        // Repeated variables are merged into one term, which narrows better.
+        let mut terms: Vec<(i64, Var<Fd>)> = Vec::with_capacity(self.terms.len());
+        for (coef, var) in self.terms.into_iter() {
+            let var = state.make_var_of(var);
+            match terms.iter().position(|&(_, x)| x.untyped() == var.untyped()) {
+                Some(i) => { terms[i].0 = terms[i].0.saturating_add(coef); },
+                None => { terms.push((coef, var)); },
+            }
+        }
+        terms.retain(|&(coef, _)| coef != 0);
+        FdLinear { terms: terms, relation: self.relation, constant: self.constant }
+    }
+}
+
+///! Return the smallest and largest values of `coef * x`, for x in `fd`.
+fn term_bounds(coef: i64, fd: &Fd) -> Option<(i64, i64)> {
+    fd.bounds().map(|(min, max)| {
+        let (a, b) = (coef.saturating_mul(min), coef.saturating_mul(max));
+        if coef > 0 { (a, b) } else { (b, a) }
+    })
+}
+
+///! Add up `values` without overflowing, clamping the total to the range of an i64 only at the
+///! end.  Returns the total and whether it's exact.
+fn clamped_sum<I: Iterator<Item=i64>>(values: I) -> (i64, bool) {
+    let (mut sum, mut wraps) = (0i64, 0i64);
+    for x in values {
+        let (next, overflowed) = sum.overflowing_add(x);
+        if overflowed { wraps += if x > 0 { 1 } else { -1 }; }
+        sum = next;
+    }
+    if wraps > 0 { (::std::i64::MAX, false) } else if wraps < 0 { (::std::i64::MIN, false) } else { (sum, true) }
+}
+
+///! Add up the bounds of some terms.  A total at `limit` may stand for anything past it, as may a
+///! term bound which saturated there, so both bound nothing and give None.
+fn bound_sum<I: Iterator<Item=i64>>(bounds: I, limit: i64) -> Option<i64> {
+    let bounds: Vec<i64> = bounds.collect();
+    if bounds.iter().any(|&x| x == limit) { return None; }
+    match clamped_sum(bounds.into_iter()) {
+        (sum, _) if sum == limit => None,
+        (sum, _) => Some(sum),
+    }
+}
+
+impl VarFdLinear {
+    ///! Narrow each domain by the bounds of the others, until nothing changes.  Returns false if a
+    ///! domain becomes empty.
+    fn narrow(&self, fds: &mut Vec<Fd>) -> bool {
+        use self::FdRelation::*;
+        loop {
+            let mut bounds = Vec::with_capacity(fds.len());
+            for (fd, &(coef, _)) in fds.iter().zip(self.terms.iter()) {
+                match term_bounds(coef, fd) {
+                    Some(x) => bounds.push(x),
+                    None => { return false; },
+                }
+            }
+            let mut changed = false;
+            for i in 0..fds.len() {
+                // The bounds of the sum of the other terms.
+                let others = || bounds.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, &x)| x);
+                let omin = bound_sum(others().map(|x| x.0), ::std::i64::MIN);
+                let omax = bound_sum(others().map(|x| x.1), ::std::i64::MAX);
+                let hi = omin.map(|omin| self.constant.saturating_sub(omin));
+                let lo = if self.relation == Equal { omax.map(|omax| self.constant.saturating_sub(omax)) } else { None };
+                let coef = self.terms[i].0;
+                let (min, max) = if coef > 0 {
+                    (lo.map(|lo| div_ceil(lo, coef)), hi.map(|hi| div_floor(hi, coef)))
+                } else {
+                    (hi.map(|hi| div_ceil(hi, coef)), lo.map(|lo| div_floor(lo, coef)))
+                };
+                let new = fds[i].constrain_range(min, max);
+                if !new.is_valid() { return false; }
+                if new != fds[i] {
+                    changed = true;
+                    fds[i] = new;
+                }
+            }
+            if !changed { return true; }
+        }
+    }
+
+    ///! Check the sum when every variable has a known value.
+    fn check_sum(&self, values: &[i64]) -> bool {
+        use self::FdRelation::*;
+        let products: Option<Vec<i64>> = self.terms.iter().zip(values.iter())
+            .map(|(&(coef, _), &x)| coef.checked_mul(x))
+            .collect();
+        match (products.map(|x| clamped_sum(x.into_iter())), self.relation) {
+            (Some((sum, exact)), Equal) => exact && sum == self.constant,
+            // an inexact sum was clamped, and lies past the constant unless it's below i64::MIN
+            (Some((sum, exact)), LessOrEqual) => if exact { sum <= self.constant } else { sum == ::std::i64::MIN },
+            (Some((sum, exact)), NotEqual) => !exact || sum != self.constant,
+            (None, NotEqual) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl Constraint for VarFdLinear {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdLinear> {
+        use self::FdRelation::*;
+        let orig: Vec<Option<Fd>> = self.terms.iter().map(|&(_, var)| state.get_value(var).map(|x| x.clone())).collect();
+        let values: Vec<i64> = orig.iter().filter_map(|fd| fd.as_ref().and_then(Fd::single_value)).collect();
+        if values.len() == orig.len() {
+            return if self.check_sum(&values) { Irrelevant } else { Failed };
+        }
+
+        // Unbound variables could have any value.
+        let mut fds: Vec<Fd> = orig.iter().map(|fd| {
+            fd.clone().unwrap_or_else(|| Fd::new_range(::std::i64::MIN, ::std::i64::MAX))
+        }).collect();
+        let result = match self.relation {
+            Equal | LessOrEqual => {
+                if !self.narrow(&mut fds) {
+                    return Failed;
+                }
+                let entailed = self.relation == LessOrEqual && {
+                    let max = self.terms.iter().zip(fds.iter())
+                        .fold(Some(0i64), |sum, (&(coef, _), fd)| {
+                            sum.and_then(|sum| term_bounds(coef, fd).and_then(|(_, hi)| sum.checked_add(hi)))
+                        });
+                    max.map(|max| max <= self.constant).unwrap_or(false)
+                };
+                if entailed { Irrelevant } else { Unchanged }
+            },
+            NotEqual => {
+                // Only one value can be ruled out, once every other variable is known.
+                if values.len() + 1 < orig.len() {
+                    return Unchanged;
+                }
+                let i = fds.iter().position(|fd| fd.single_value().is_none()).unwrap();
+                let rest = self.terms.iter().zip(fds.iter()).enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .fold(Some(0i64), |sum, (_, (&(coef, _), fd))| {
+                        sum.and_then(|sum| coef.checked_mul(fd.single_value().unwrap()).and_then(|x| sum.checked_add(x)))
+                    });
+                let coef = self.terms[i].0;
+                // checked_rem and checked_div fail for a zero coefficient and for i64::MIN / -1.
+                let excluded = rest.and_then(|rest| self.constant.checked_sub(rest))
+                    .and_then(|target| if target.checked_rem(coef) == Some(0) { target.checked_div(coef) } else { None });
+                if let Some(value) = excluded {
+                    fds[i] = fds[i].difference(&Fd::new_single(value));
+                    if !fds[i].is_valid() {
+                        return Failed;
+                    }
+                }
+                if orig[i].is_some() { Irrelevant } else { Unchanged }
+            },
+        };
+
+        for (i, (fd, orig)) in fds.into_iter().zip(orig.into_iter()).enumerate() {
+            let var = self.terms[i].1;
+            match orig {
+                Some(orig) => {
+                    if fd != orig {
+                        unsafe { state.overwrite_var(var, fd); }
+                    }
+                },
+                None => {
+                    // Leave unbound variables alone unless their value is now known.
+                    if fd.single_value().is_some() {
+                        state.unify(var, fd);
+                    }
+                },
+            }
+        }
+        if !state.ok() { Failed } else { result }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.terms.iter().any(|&(_, var)| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for &mut (_, ref mut var) in self.terms.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        use self::FdRelation::*;
+        let mut sum = 0i64;
+        for &(coef, var) in self.terms.iter() {
+            match values.get(var) {
+                Some(value) => { sum = sum.saturating_add(coef.saturating_mul(value)); },
+                None => { return None; },
+            }
+        }
+        Some(match self.relation {
+            Equal => distance(sum, self.constant),
+            LessOrEqual => if sum <= self.constant { 0 } else { distance(sum, self.constant) },
+            NotEqual => if sum != self.constant { 0 } else { 1 },
+        })
+    }
+}
+
+impl ToConstraint for AllDiffConstraint {
+    type ConstraintType = AllDiffConstraint;
+    fn into_constraint(self, _: &mut State) -> AllDiffConstraint { self }
+}
+
+///! Constrains two finite domain variables to be equal.
+#[derive(Debug, Clone)]
+pub struct FdEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    l: A,
+    r: B,
+}
+pub type VarFdEqual = FdEqual<Var<Fd>, Var<Fd>>;
+
+impl<A, B> FdEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(l: A, r: B) -> FdEqual<A, B> {
+        FdEqual { l: l, r: r }
+    }
+}
+
+impl<A, B> ToConstraint for FdEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdEqual;
+    fn into_constraint(self, state: &mut State) -> VarFdEqual {
+        let l = state.make_var_of(self.l);
+        let r = state.make_var_of(self.r);
+        FdEqual { l: l, r: r }
+    }
+}
+
+impl Constraint for VarFdEqual {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdEqual> {
+        state.unify(self.l, self.r);
+        if state.ok() { Irrelevant } else { Failed }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.l.untyped()) || proxy.contains_key(&self.r.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.l.untyped_mut());
+        proxy.update_var(self.r.untyped_mut());
+    }
+}
+
+///! Constrains two finite domain variables to be different.  Once one of them is known, its value
+///! is removed from the other's domain.
+#[derive(Debug, Clone)]
+pub struct FdNotEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    l: A,
+    r: B,
+}
+pub type VarFdNotEqual = FdNotEqual<Var<Fd>, Var<Fd>>;
+
+impl<A, B> FdNotEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(l: A, r: B) -> FdNotEqual<A, B> {
+        FdNotEqual { l: l, r: r }
+    }
+}
+
+impl<A, B> ToConstraint for FdNotEqual<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdNotEqual;
+    fn into_constraint(self, state: &mut State) -> VarFdNotEqual {
+        let l = state.make_var_of(self.l);
+        let r = state.make_var_of(self.r);
+        FdNotEqual { l: l, r: r }
+    }
+}
+
+impl Constraint for VarFdNotEqual {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdNotEqual> {
+        let (l, r) = (state.get_value(self.l).map(|x| x.clone()), state.get_value(self.r).map(|x| x.clone()));
+        let (l, r) = match (l, r) {
+            (Some(l), Some(r)) => (l, r),
+            _ => { return Unchanged; },
+        };
+        if !l.combine(&r).is_valid() {
+            return Irrelevant;
+        }
+        let (var, fd, single) = match (l.single_value(), r.single_value()) {
+            (Some(x), _) => (self.r, r, x),
+            (_, Some(x)) => (self.l, l, x),
+            _ => { return Unchanged; },
+        };
+        let new = fd.difference(&Fd::new_single(single));
+        if !new.is_valid() {
+            return Failed;
+        }
+        unsafe { state.overwrite_var(var, new); }
+        Irrelevant
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.l.untyped()) || proxy.contains_key(&self.r.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.l.untyped_mut());
+        proxy.update_var(self.r.untyped_mut());
+    }
+}
+
+///! Constrains three finite domain variables so that A + B != C.
+#[derive(Debug, Clone)]
+pub struct FdSumNotEqual<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    l: A,
+    r: B,
+    result: C,
+}
+pub type VarFdSumNotEqual = FdSumNotEqual<Var<Fd>, Var<Fd>, Var<Fd>>;
+
+impl<A, B, C> FdSumNotEqual<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    pub fn new(l: A, r: B, result: C) -> FdSumNotEqual<A, B, C> {
+        FdSumNotEqual { l: l, r: r, result: result }
+    }
+}
+
+impl<A, B, C> ToConstraint for FdSumNotEqual<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    type ConstraintType = VarFdSumNotEqual;
+    fn into_constraint(self, state: &mut State) -> VarFdSumNotEqual {
+        let l = state.make_var_of(self.l);
+        let r = state.make_var_of(self.r);
+        let result = state.make_var_of(self.result);
+        FdSumNotEqual { l: l, r: r, result: result }
+    }
+}
+
+impl Constraint for VarFdSumNotEqual {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdSumNotEqual> {
+        let (l, r, result) = {
+            let get = |var| state.get_value(var).and_then(Fd::single_value);
+            (get(self.l), get(self.r), get(self.result))
+        };
+        // Once two values are known, the third can't take the value which would make the sum hold.
+        let (var, excluded) = match (l, r, result) {
+            (Some(l), Some(r), Some(result)) => {
+                return if l.checked_add(r) == Some(result) { Failed } else { Irrelevant };
+            },
+            (Some(l), Some(r), None) => (self.result, l.checked_add(r)),
+            (Some(l), None, Some(result)) => (self.r, result.checked_sub(l)),
+            (None, Some(r), Some(result)) => (self.l, result.checked_sub(r)),
+            _ => { return Unchanged; },
+        };
+        let excluded = match excluded {
+            Some(x) => x,
+            None => { return Irrelevant; },
+        };
+        let fd = match state.get_value(var) {
+            Some(fd) => fd.difference(&Fd::new_single(excluded)),
+            None => { return Unchanged; },
+        };
+        if !fd.is_valid() {
+            return Failed;
+        }
+        unsafe { state.overwrite_var(var, fd); }
+        Irrelevant
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.l.untyped())
+            || proxy.contains_key(&self.r.untyped())
+            || proxy.contains_key(&self.result.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.l.untyped_mut());
+        proxy.update_var(self.r.untyped_mut());
+        proxy.update_var(self.result.untyped_mut());
+    }
+}
+
+///! A constraint which can be linked to a `Var<bool>` by `Reified`.
+pub trait Reifiable: Constraint + Clone {
+    ///! The constraint which holds exactly when this one doesn't.
+    type Negation: Constraint + Clone;
+    fn negation(&self) -> Self::Negation;
+    ///! Return `Some(true)` if the constraint must hold, `Some(false)` if it can't, or `None` if
+    ///! that isn't known yet.
+    fn entailed(&self, state: &StateProxy) -> Option<bool>;
+}
+
+impl Reifiable for VarFdLessOrEqual {
+    type Negation = VarFdLessOrEqual;
+    fn negation(&self) -> VarFdLessOrEqual {
+        // not (A + offset <= B) is B + (1 - offset) <= A.
+        FdLessOrEqual { l: self.r, r: self.l, offset: 1 - self.offset }
+    }
+    fn entailed(&self, state: &StateProxy) -> Option<bool> {
+        let (l, r) = match (state.get_value(self.l).and_then(Fd::bounds), state.get_value(self.r).and_then(Fd::bounds)) {
+            (Some(l), Some(r)) => (l, r),
+            _ => { return None; },
+        };
+        if l.1.saturating_add(self.offset) <= r.0 {
+            Some(true)
+        } else if l.0.saturating_add(self.offset) > r.1 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+fn fd_equal_entailed(state: &StateProxy, l: Var<Fd>, r: Var<Fd>) -> Option<bool> {
+    let (l, r) = match (state.get_value(l), state.get_value(r)) {
+        (Some(l), Some(r)) => (l, r),
+        _ => { return None; },
+    };
+    match (l.single_value(), r.single_value()) {
+        (Some(l), Some(r)) => Some(l == r),
+        _ if !l.combine(r).is_valid() => Some(false),
+        _ => None,
+    }
+}
+
+impl Reifiable for VarFdEqual {
+    type Negation = VarFdNotEqual;
+    fn negation(&self) -> VarFdNotEqual {
+        FdNotEqual { l: self.l, r: self.r }
+    }
+    fn entailed(&self, state: &StateProxy) -> Option<bool> {
+        fd_equal_entailed(state, self.l, self.r)
+    }
+}
+
+impl Reifiable for VarFdNotEqual {
+    type Negation = VarFdEqual;
+    fn negation(&self) -> VarFdEqual {
+        FdEqual { l: self.l, r: self.r }
+    }
+    fn entailed(&self, state: &StateProxy) -> Option<bool> {
+        fd_equal_entailed(state, self.l, self.r).map(|x| !x)
+    }
+}
+
+fn fd_sum_entailed(state: &StateProxy, l: Var<Fd>, r: Var<Fd>, result: Var<Fd>) -> Option<bool> {
+    let (l, r, result) = match (state.get_value(l), state.get_value(r), state.get_value(result)) {
+        (Some(l), Some(r), Some(result)) => (l, r, result),
+        _ => { return None; },
+    };
+    if let (Some(l), Some(r), Some(result)) = (l.single_value(), r.single_value(), result.single_value()) {
+        return Some(l.checked_add(r) == Some(result));
+    }
+    match (l.bounds(), r.bounds()) {
+        (Some((lmin, lmax)), Some((rmin, rmax))) => {
+            let sums = result.constrain_range(Some(lmin.saturating_add(rmin)), Some(lmax.saturating_add(rmax)));
+            if sums.is_valid() { None } else { Some(false) }
+        },
+        _ => Some(false),
+    }
+}
+
+impl Reifiable for VarFdSumConstraint {
+    type Negation = VarFdSumNotEqual;
+    fn negation(&self) -> VarFdSumNotEqual {
+        FdSumNotEqual { l: self.l, r: self.r, result: self.result }
+    }
+    fn entailed(&self, state: &StateProxy) -> Option<bool> {
+        fd_sum_entailed(state, self.l, self.r, self.result)
+    }
+}
+
+impl Reifiable for VarFdSumNotEqual {
+    type Negation = VarFdSumConstraint;
+    fn negation(&self) -> VarFdSumConstraint {
+        FdSumConstraint { l: self.l, r: self.r, result: self.result }
+    }
+    fn entailed(&self, state: &StateProxy) -> Option<bool> {
+        fd_sum_entailed(state, self.l, self.r, self.result).map(|x| !x)
+    }
+}
+
+///! Links a `Var<bool>` to the truth of a constraint, so that the bool is true exactly when the
+///! constraint holds.  Once the bool is known, the constraint or its negation is enforced; until
+///! then, the bool is set as soon as the constraint is known to hold or to fail.
+pub struct Reified<A, C>
+where A: ToVar<VarType=bool>, C: ToConstraint, C::ConstraintType: Reifiable {
+    b: A,
+    constraint: C,
+}
+
+impl<A, C> Reified<A, C>
+where A: ToVar<VarType=bool>, C: ToConstraint, C::ConstraintType: Reifiable {
+    pub fn new(b: A, constraint: C) -> Reified<A, C> {
+        Reified { b: b, constraint: constraint }
+    }
+}
+
+impl<A, C> ToConstraint for Reified<A, C>
+where A: ToVar<VarType=bool>, C: ToConstraint, C::ConstraintType: Reifiable {
+    type ConstraintType = VarReified<C::ConstraintType>;
+    fn into_constraint(self, state: &mut State) -> VarReified<C::ConstraintType> {
+        let b = state.make_var_of(self.b);
+        let positive = self.constraint.into_constraint(state);
+        let negative = positive.negation();
+        VarReified { b: b, positive: positive, negative: negative }
+    }
+}
+
+///! Implementation of `Reified`.  Don't use this directly, use `Reified`.
+#[derive(Debug, Clone)]
+pub struct VarReified<C> where C: Reifiable {
+    b: Var<bool>,
+    positive: C,
+    negative: C::Negation,
+}
+
+impl<C> Constraint for VarReified<C> where C: Reifiable {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarReified<C>> {
+        let b = state.get_value(self.b).map(|x| *x);
+        match b {
+            Some(true) => match self.positive.update(state) {
+                Updated(positive) => Updated(VarReified { b: self.b, positive: positive, negative: self.negative.clone() }),
+                Failed => Failed,
+                Irrelevant => Irrelevant,
+                Unchanged => Unchanged,
+            },
+            Some(false) => match self.negative.update(state) {
+                Updated(negative) => Updated(VarReified { b: self.b, positive: self.positive.clone(), negative: negative }),
+                Failed => Failed,
+                Irrelevant => Irrelevant,
+                Unchanged => Unchanged,
+            },
+            None => match self.positive.entailed(state) {
+                Some(x) => {
+                    state.unify(self.b, x);
+                    if state.ok() { Irrelevant } else { Failed }
+                },
+                None => Unchanged,
+            },
+        }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.b.untyped()) || self.positive.relevant(proxy) || self.negative.relevant(proxy)
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.b.untyped_mut());
+        self.positive.update_vars(proxy);
+        self.negative.update_vars(proxy);
+    }
+    fn need_update(&self, vars: &VarMap) -> bool {
+        vars.need_update(self.b.untyped()) || self.positive.need_update(vars) || self.negative.need_update(vars)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BoolOp {
+    And,
+    Or,
+    Xor,
+    Implies,
+}
+
+impl BoolOp {
+    fn apply(self, l: bool, r: bool) -> bool {
+        match self {
+            BoolOp::And => l && r,
+            BoolOp::Or => l || r,
+            BoolOp::Xor => l != r,
+            BoolOp::Implies => !l || r,
+        }
+    }
+}
+
+///! Implementation of `BoolAnd`, `BoolOr`, `BoolXor` and `BoolImplies`.  Don't use this directly.
+#[derive(Debug, Clone)]
+pub struct VarBoolOpConstraint {
+    op: BoolOp,
+    l: Var<bool>,
+    r: Var<bool>,
+    result: Var<bool>,
+}
+
+impl Constraint for VarBoolOpConstraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarBoolOpConstraint> {
+        let vars = [self.l, self.r, self.result];
+        let known: Vec<Option<bool>> = vars.iter().map(|&var| state.get_value(var).map(|x| *x)).collect();
+
+        // Find which values each variable takes among the assignments satisfying the operation.
+        let mut supported = [[false; 2]; 3];
+        for &l in [false, true].iter() {
+            for &r in [false, true].iter() {
+                let values = [l, r, self.op.apply(l, r)];
+                if values.iter().zip(known.iter()).all(|(value, known)| known.map_or(true, |x| x == *value)) {
+                    for (support, &value) in supported.iter_mut().zip(values.iter()) {
+                        support[value as usize] = true;
+                    }
+                }
+            }
+        }
+        let mut done = true;
+        for ((&var, known), support) in vars.iter().zip(known.into_iter()).zip(supported.iter()) {
+            match (support[0], support[1]) {
+                (false, false) => { return Failed; },
+                (true, true) => { done = false; },
+                (_, value) => if known.is_none() { state.unify(var, value); },
+            }
+        }
+        if !state.ok() { Failed } else if done { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.l.untyped())
+            || proxy.contains_key(&self.r.untyped())
+            || proxy.contains_key(&self.result.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.l.untyped_mut());
+        proxy.update_var(self.r.untyped_mut());
+        proxy.update_var(self.result.untyped_mut());
+    }
+}
+
+macro_rules! bool_op_constraint {
+    ($(#[$attr:meta])* struct $name:ident => $op:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone)]
+        pub struct $name<A, B, C>
+        where A: ToVar<VarType=bool>, B: ToVar<VarType=bool>, C: ToVar<VarType=bool> {
+            l: A,
+            r: B,
+            result: C,
+        }
+
+        impl<A, B, C> $name<A, B, C>
+        where A: ToVar<VarType=bool>, B: ToVar<VarType=bool>, C: ToVar<VarType=bool> {
+            pub fn new(l: A, r: B, result: C) -> $name<A, B, C> {
+                $name { l: l, r: r, result: result }
+            }
+        }
+
+        impl<A, B, C> ToConstraint for $name<A, B, C>
+        where A: ToVar<VarType=bool>, B: ToVar<VarType=bool>, C: ToVar<VarType=bool> {
+            type ConstraintType = VarBoolOpConstraint;
+            fn into_constraint(self, state: &mut State) -> VarBoolOpConstraint {
+                let l = state.make_var_of(self.l);
+                let r = state.make_var_of(self.r);
+                let result = state.make_var_of(self.result);
+                VarBoolOpConstraint { op: $op, l: l, r: r, result: result }
+            }
+        }
+    }
+}
+
+bool_op_constraint! {
+    ///! Constrains three boolean variables so that C = A and B.
+    struct BoolAnd => BoolOp::And
+}
+
+bool_op_constraint! {
+    ///! Constrains three boolean variables so that C = A or B.
+    struct BoolOr => BoolOp::Or
+}
+
+bool_op_constraint! {
+    ///! Constrains three boolean variables so that C = A xor B.
+    struct BoolXor => BoolOp::Xor
+}
+
+bool_op_constraint! {
+    ///! Constrains three boolean variables so that C = A implies B.  Pass `true` as C to simply
+    ///! require the implication.
+    struct BoolImplies => BoolOp::Implies
+}
+
+///! Constrains two boolean variables to have opposite values.
+#[derive(Debug, Clone)]
+pub struct BoolNot<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    a: A,
+    b: B,
+}
+pub type VarBoolNot = BoolNot<Var<bool>, Var<bool>>;
+
+impl<A, B> BoolNot<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    pub fn new(a: A, b: B) -> BoolNot<A, B> {
+        BoolNot { a: a, b: b }
+    }
+}
+
+impl<A, B> ToConstraint for BoolNot<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    type ConstraintType = VarBoolNot;
+    fn into_constraint(self, state: &mut State) -> VarBoolNot {
+        let a = state.make_var_of(self.a);
+        let b = state.make_var_of(self.b);
+        BoolNot { a: a, b: b }
+    }
+}
+
+impl Constraint for VarBoolNot {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarBoolNot> {
+        let (a, b) = (state.get_value(self.a).map(|x| *x), state.get_value(self.b).map(|x| *x));
+        match (a, b) {
+            (Some(a), Some(b)) => { return if a != b { Irrelevant } else { Failed }; },
+            (Some(a), None) => { state.unify(self.b, !a); },
+            (None, Some(b)) => { state.unify(self.a, !b); },
+            (None, None) => { return Unchanged; },
+        }
+        if state.ok() { Irrelevant } else { Failed }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.a.untyped()) || proxy.contains_key(&self.b.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.a.untyped_mut());
+        proxy.update_var(self.b.untyped_mut());
+    }
+}
+
+///! A clause: at least one of the `positive` variables must be true, or at least one of the
+///! `negative` variables must be false.  Once every literal but one is false, that one is set.
+pub struct BoolClause<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    positive: Vec<A>,
+    negative: Vec<B>,
+}
+
+impl<A, B> BoolClause<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    pub fn new(positive: Vec<A>, negative: Vec<B>) -> BoolClause<A, B> {
+        BoolClause { positive: positive, negative: negative }
+    }
+}
+
+impl<A, B> ToConstraint for BoolClause<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=bool> {
+    type ConstraintType = VarBoolClause;
+    fn into_constraint(self, state: &mut State) -> VarBoolClause {
+        let mut literals = Vec::with_capacity(self.positive.len() + self.negative.len());
+        for var in self.positive.into_iter() {
+            literals.push((state.make_var_of(var), true));
+        }
+        for var in self.negative.into_iter() {
+            literals.push((state.make_var_of(var), false));
+        }
+        VarBoolClause { literals: literals }
+    }
+}
+
+///! Implementation of `BoolClause`.  Don't use this directly, use `BoolClause`.
+#[derive(Debug, Clone)]
+pub struct VarBoolClause {
+    literals: Vec<(Var<bool>, bool)>,
+}
+
+impl Constraint for VarBoolClause {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarBoolClause> {
+        let mut unknown = None;
+        for &(var, polarity) in self.literals.iter() {
+            match state.get_value(var) {
+                Some(&value) if value == polarity => { return Irrelevant; },
+                Some(_) => (),
+                None if unknown.is_some() => { return Unchanged; },
+                None => { unknown = Some((var, polarity)); },
+            }
+        }
+        match unknown {
+            Some((var, polarity)) => {
+                state.unify(var, polarity);
+                if state.ok() { Irrelevant } else { Failed }
+            },
+            None => Failed,
+        }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.literals.iter().any(|&(var, _)| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for &mut (ref mut var, _) in self.literals.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains the number of true variables in `vars` to lie in the domain of `count`.  Once
+///! enough variables are true (or false) to reach the bounds of `count`, the rest are set.
+pub struct Cardinality<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    vars: Vec<A>,
+    count: B,
+}
+
+impl<A, B> Cardinality<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    pub fn new(vars: Vec<A>, count: B) -> Cardinality<A, B> {
+        Cardinality { vars: vars, count: count }
+    }
+}
+
+impl<A> Cardinality<A, Fd> where A: ToVar<VarType=bool> {
+    ///! Exactly `k` of `vars` are true.
+    pub fn exactly(vars: Vec<A>, k: i64) -> Cardinality<A, Fd> {
+        Cardinality::new(vars, Fd::new_single(k))
+    }
+    ///! At least `k` of `vars` are true.
+    pub fn at_least(vars: Vec<A>, k: i64) -> Cardinality<A, Fd> {
+        let n = vars.len() as i64;
+        Cardinality::new(vars, Fd::new_range(k, max(k, n)))
+    }
+    ///! At most `k` of `vars` are true.
+    pub fn at_most(vars: Vec<A>, k: i64) -> Cardinality<A, Fd> {
+        Cardinality::new(vars, Fd::new_range(0, k))
+    }
+}
+
+impl<A, B> ToConstraint for Cardinality<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarCardinality;
+    fn into_constraint(self, state: &mut State) -> VarCardinality {
+        let vars = self.vars.into_iter().map(|var| state.make_var_of(var)).collect();
+        let count = state.make_var_of(self.count);
+        VarCardinality { vars: vars, count: count }
+    }
+}
+
+///! Implementation of `Cardinality`.  Don't use this directly, use `Cardinality`.
+#[derive(Debug, Clone)]
+pub struct VarCardinality {
+    vars: Vec<Var<bool>>,
+    count: Var<Fd>,
+}
+
+impl Constraint for VarCardinality {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarCardinality> {
+        let mut trues = 0;
+        let mut unknown = Vec::new();
+        for &var in self.vars.iter() {
+            match state.get_value(var) {
+                Some(&true) => { trues += 1; },
+                Some(&false) => (),
+                None => { unknown.push(var); },
+            }
+        }
+        let possible = Fd::new_range(trues, trues + unknown.len() as i64);
+        let count = state.get_value(self.count).map(|count| {
+            let new = count.combine(&possible);
+            let changed = new != *count;
+            (new, changed)
+        });
+        match count {
+            Some((count, _)) if !count.is_valid() => { return Failed; },
+            Some((count, true)) => unsafe { state.overwrite_var(self.count, count); },
+            Some(_) => (),
+            None => { state.unify(self.count, possible); },
+        }
+        if unknown.is_empty() {
+            return if state.ok() { Irrelevant } else { Failed };
+        }
+
+        let (lo, hi) = state.get_value(self.count).and_then(Fd::bounds).unwrap();
+        let value = if hi == trues {
+            false
+        } else if lo == trues + unknown.len() as i64 {
+            true
+        } else {
+            return if state.ok() { Unchanged } else { Failed };
+        };
+        for var in unknown.into_iter() {
+            state.unify(var, value);
+        }
+        if state.ok() { Irrelevant } else { Failed }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.count.untyped())
+            || self.vars.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.count.untyped_mut());
+        for var in self.vars.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains a weighted sum of boolean variables, `w1 * b1 + w2 * b2 + ...`, to lie in the
+///! domain of `total`.  A variable is set as soon as either value would push the sum out of the
+///! bounds of `total`.
+pub struct PseudoBoolean<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    terms: Vec<(i64, A)>,
+    total: B,
+}
+
+impl<A, B> PseudoBoolean<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    pub fn new(terms: Vec<(i64, A)>, total: B) -> PseudoBoolean<A, B> {
+        PseudoBoolean { terms: terms, total: total }
+    }
+}
+
+impl<A> PseudoBoolean<A, Fd> where A: ToVar<VarType=bool> {
+    ///! The weighted sum is at most `c`.
+    pub fn at_most(terms: Vec<(i64, A)>, c: i64) -> PseudoBoolean<A, Fd> {
+        let min = terms.iter().filter(|&&(w, _)| w < 0).fold(0i64, |sum, &(w, _)| sum.saturating_add(w));
+        PseudoBoolean::new(terms, Fd::new_range(min, c))
+    }
+    ///! The weighted sum is at least `c`.
+    pub fn at_least(terms: Vec<(i64, A)>, c: i64) -> PseudoBoolean<A, Fd> {
+        let max = terms.iter().filter(|&&(w, _)| w > 0).fold(0i64, |sum, &(w, _)| sum.saturating_add(w));
+        PseudoBoolean::new(terms, Fd::new_range(c, max))
+    }
+}
+
+impl<A, B> ToConstraint for PseudoBoolean<A, B>
+where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarPseudoBoolean;
+    fn into_constraint(self, state: &mut State) -> VarPseudoBoolean {
+        let mut terms: Vec<(i64, Var<bool>)> = Vec::with_capacity(self.terms.len());
+        for (w, var) in self.terms.into_iter() {
+            let var = state.make_var_of(var);
+            match terms.iter().position(|&(_, x)| x.untyped() == var.untyped()) {
+                Some(i) => { terms[i].0 = terms[i].0.saturating_add(w); },
+                None => { terms.push((w, var)); },
+            }
+        }
+        terms.retain(|&(w, _)| w != 0);
+        let total = state.make_var_of(self.total);
+        VarPseudoBoolean { terms: terms, total: total }
+    }
+}
+
+///! Implementation of `PseudoBoolean`.  Don't use this directly, use `PseudoBoolean`.
+#[derive(Debug, Clone)]
+pub struct VarPseudoBoolean {
+    terms: Vec<(i64, Var<bool>)>,
+    total: Var<Fd>,
+}
+
+impl Constraint for VarPseudoBoolean {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarPseudoBoolean> {
+        let mut known = 0i64;
+        let mut unknown = Vec::new();
+        for &(w, var) in self.terms.iter() {
+            match state.get_value(var) {
+                Some(&true) => { known = known.saturating_add(w); },
+                Some(&false) => (),
+                None => { unknown.push((w, var)); },
+            }
+        }
+        let orig = state.get_value(self.total).map(|fd| fd.clone());
+        let mut total = orig.clone().unwrap_or_else(|| Fd::new_range(::std::i64::MIN, ::std::i64::MAX));
+
+        // Setting a variable moves one end of the sum's range by its weight; if the slack between
+        // that end and the far bound of the total is smaller, the variable can't take that value.
+        let mut forced = Vec::new();
+        loop {
+            let (lo, hi) = unknown.iter().fold((known, known), |(lo, hi), &(w, _)| {
+                if w < 0 { (lo.saturating_add(w), hi) } else { (lo, hi.saturating_add(w)) }
+            });
+            total = total.constrain_range(Some(lo), Some(hi));
+            let (tmin, tmax) = match total.bounds() {
+                Some(x) => x,
+                None => { return Failed; },
+            };
+            let before = forced.len();
+            unknown.retain(|&(w, var)| {
+                let value = if w > 0 {
+                    if tmax.saturating_sub(lo) < w { Some(false) }
+                    else if hi.saturating_sub(tmin) < w { Some(true) }
+                    else { None }
+                } else {
+                    if hi.saturating_sub(tmin) < 0i64.saturating_sub(w) { Some(false) }
+                    else if tmax.saturating_sub(lo) < 0i64.saturating_sub(w) { Some(true) }
+                    else { None }
+                };
+                match value {
+                    Some(value) => { forced.push((w, var, value)); false },
+                    None => true,
+                }
+            });
+            if forced.len() == before {
+                break;
+            }
+            for &(w, _, value) in forced[before..].iter() {
+                if value { known = known.saturating_add(w); }
+            }
+        }
+
+        match orig {
+            Some(orig) => if orig != total {
+                unsafe { state.overwrite_var(self.total, total); }
+            },
+            None => { state.unify(self.total, total); },
+        }
+        for (_, var, value) in forced.into_iter() {
+            state.unify(var, value);
+        }
+        if !state.ok() { Failed } else if unknown.is_empty() { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.total.untyped())
+            || self.terms.iter().any(|&(_, var)| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.total.untyped_mut());
+        for &mut (_, ref mut var) in self.terms.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains `value` to equal `array[index]`, for an index counting from 0.  Indexes whose
+///! element can't equal `value` are removed, and `value` is narrowed to the elements at the
+///! remaining indexes.  Once the index is known, that element is unified with `value`.
+pub struct Element<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    index: A,
+    array: Vec<B>,
+    value: C,
+}
+
+impl<A, B, C> Element<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    pub fn new(index: A, array: Vec<B>, value: C) -> Element<A, B, C> {
+        Element { index: index, array: array, value: value }
+    }
+}
+
+impl<A, B, C> ToConstraint for Element<A, B, C>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd>, C: ToVar<VarType=Fd> {
+    type ConstraintType = VarElement;
+    fn into_constraint(self, state: &mut State) -> VarElement {
+        let index = state.make_var_of(self.index);
+        let array = self.array.into_iter().map(|var| state.make_var_of(var)).collect();
+        let value = state.make_var_of(self.value);
+        VarElement { index: index, array: array, value: value }
+    }
+}
+
+///! Implementation of `Element`.  Don't use this directly, use `Element`.
+#[derive(Debug, Clone)]
+pub struct VarElement {
+    index: Var<Fd>,
+    array: Vec<Var<Fd>>,
+    value: Var<Fd>,
+}
+
+impl Constraint for VarElement {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarElement> {
+        if self.array.is_empty() { return Failed; }
+        let in_bounds = Fd::new_range(0, self.array.len() as i64 - 1);
+        let (index, old_index) = match state.get_value(self.index) {
+            Some(index) => (index.combine(&in_bounds), Some(index.clone())),
+            None => (in_bounds, None),
+        };
+        let old_value = state.get_value(self.value).cloned();
+
+        // The indexes still possible, and the union of their elements, unless some are unset.
+        let mut indexes = Vec::new();
+        let mut union = Some(Fd::err());
+        for i in index.iter() {
+            match state.get_value(self.array[i as usize]) {
+                Some(elem) => {
+                    let common = match old_value {
+                        Some(ref value) => elem.combine(value),
+                        None => elem.clone(),
+                    };
+                    if !common.is_valid() { continue; }
+                    union = union.map(|union| union.union(&common));
+                },
+                None => { union = None; },
+            }
+            indexes.push(i);
+        }
+        if indexes.is_empty() { return Failed; }
+
+        let index = Fd::new_values(indexes.clone());
+        match old_index {
+            Some(ref old) if *old == index => (),
+            Some(_) => unsafe { state.overwrite_var(self.index, index); },
+            None => { state.unify(self.index, index); },
+        }
+        match (union, old_value) {
+            (Some(ref union), Some(ref old)) if union == old => (),
+            (Some(union), Some(_)) => unsafe { state.overwrite_var(self.value, union); },
+            (Some(union), None) => { state.unify(self.value, union); },
+            (None, _) => (),
+        }
+        if indexes.len() == 1 {
+            state.unify(self.array[indexes[0] as usize], self.value);
+            return if state.ok() { Irrelevant } else { Failed };
+        }
+        if state.ok() { Unchanged } else { Failed }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.index.untyped()) || proxy.contains_key(&self.value.untyped())
+            || self.array.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.index.untyped_mut());
+        proxy.update_var(self.value.untyped_mut());
+        for var in self.array.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains a tuple of finite domain variables to match one of a list of allowed tuples, or
+///! with `Table::forbidden`, none of a list of forbidden ones.  Values that no longer appear in a
+///! possible allowed tuple are removed without branching.
+pub struct Table<A> where A: ToVar<VarType=Fd> {
+    vars: Vec<A>,
+    tuples: Vec<Vec<i64>>,
+    forbidden: bool,
+}
+
+impl<A> Table<A> where A: ToVar<VarType=Fd> {
+    pub fn new(vars: Vec<A>, tuples: Vec<Vec<i64>>) -> Table<A> {
+        assert!(tuples.iter().all(|tuple| tuple.len() == vars.len()));
+        Table { vars: vars, tuples: tuples, forbidden: false }
+    }
+    pub fn forbidden(vars: Vec<A>, tuples: Vec<Vec<i64>>) -> Table<A> {
+        assert!(tuples.iter().all(|tuple| tuple.len() == vars.len()));
+        Table { vars: vars, tuples: tuples, forbidden: true }
+    }
+}
+
+impl<A> ToConstraint for Table<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarTable;
+    fn into_constraint(self, state: &mut State) -> VarTable {
+        let vars = self.vars.into_iter().map(|var| state.make_var_of(var)).collect();
+        // Forbidden tuples are counted, so each must appear only once.
+        let mut tuples = self.tuples;
+        tuples.sort();
+        tuples.dedup();
+        let live = (0..tuples.len()).collect();
+        VarTable { vars: vars, tuples: Rc::new(tuples), live: Rc::new(live), forbidden: self.forbidden }
+    }
+}
+
+///! Implementation of `Table`.  Don't use this directly, use `Table`.
+///!
+///! This is simple tabular reduction: `live` lists the tuples still matching the variables'
+///! domains, and shrinks as they're narrowed, so each tuple is only rejected once per branch.
+#[derive(Debug, Clone)]
+pub struct VarTable {
+    vars: Vec<Var<Fd>>,
+    tuples: Rc<Vec<Vec<i64>>>,
+    live: Rc<Vec<usize>>,
+    forbidden: bool,
+}
+
+impl VarTable {
+    ///! Return the tuples in `live` whose values are all still possible.
+    fn matching(&self, domains: &[Option<Fd>]) -> Vec<usize> {
+        self.live.iter().cloned().filter(|&t| {
+            self.tuples[t].iter().zip(domains.iter()).all(|(&x, domain)| match *domain {
+                Some(ref domain) => domain.in_range(x),
+                None => true,
+            })
+        }).collect()
+    }
+
+    fn update_allowed(&self, state: &mut StateProxy, domains: Vec<Option<Fd>>) -> ConstraintResult<VarTable> {
+        let live = self.matching(&domains);
+        if live.is_empty() { return Failed; }
+        for (i, (&var, domain)) in self.vars.iter().zip(domains.into_iter()).enumerate() {
+            let mut supported: Vec<i64> = live.iter().map(|&t| self.tuples[t][i]).collect();
+            supported.sort();
+            supported.dedup();
+            let supported = Fd::new_values(supported);
+            match domain {
+                Some(ref domain) if supported == *domain => (),
+                Some(_) => unsafe { state.overwrite_var(var, supported); },
+                None => { state.unify(var, supported); },
+            }
+        }
+        // Every remaining tuple is made of supported values, so it still matches.
+        if !state.ok() { return Failed; }
+        if live.len() == 1 { return Irrelevant; }
+        if live.len() == self.live.len() { return Unchanged; }
+        Updated(VarTable { live: Rc::new(live), .. self.clone() })
+    }
+
+    fn update_forbidden(&self, state: &mut StateProxy, mut domains: Vec<Option<Fd>>) -> ConstraintResult<VarTable> {
+        // A value must go once every combination of the other variables' values is forbidden
+        // alongside it.  Removing it can leave another value unsupported, so repeat until nothing
+        // changes.
+        let mut changed = vec![false; self.vars.len()];
+        let live = loop {
+            let live = self.matching(&domains);
+            let sizes: Vec<Option<u64>> = domains.iter().map(|domain| {
+                domain.as_ref().map(|domain| domain.value_count() as u64)
+            }).collect();
+            let mut removed = false;
+            for i in 0..self.vars.len() {
+                let others = sizes.iter().enumerate().filter(|&(j, _)| j != i).fold(Some(1u64), |acc, (_, size)| {
+                    match (acc, *size) {
+                        (Some(acc), Some(size)) => acc.checked_mul(size),
+                        _ => None,
+                    }
+                });
+                let others = match others {
+                    Some(others) if others <= live.len() as u64 && domains[i].is_some() => others,
+                    _ => continue,
+                };
+                let mut counts: HashMap<i64, u64> = HashMap::new();
+                for &t in live.iter() {
+                    *counts.entry(self.tuples[t][i]).or_insert(0) += 1;
+                }
+                let excluded: HashSet<i64> = counts.into_iter().filter(|&(_, count)| count == others).map(|(x, _)| x).collect();
+                if excluded.is_empty() { continue; }
+                let domain = domains[i].as_mut().unwrap();
+                domain.remove_values(&excluded);
+                if !domain.is_valid() { return Failed; }
+                changed[i] = true;
+                removed = true;
+            }
+            if !removed { break live; }
+        };
+        for ((&var, domain), changed) in self.vars.iter().zip(domains.into_iter()).zip(changed.into_iter()) {
+            if changed { unsafe { state.overwrite_var(var, domain.unwrap()); } }
+        }
+        if live.is_empty() { return Irrelevant; }
+        if live.len() == self.live.len() { return Unchanged; }
+        Updated(VarTable { live: Rc::new(live), .. self.clone() })
+    }
+}
+
+impl Constraint for VarTable {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarTable> {
+        let domains: Vec<Option<Fd>> = self.vars.iter().map(|&var| state.get_value(var).cloned()).collect();
+        if self.forbidden {
+            self.update_forbidden(state, domains)
+        } else {
+            self.update_allowed(state, domains)
+        }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.vars.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.vars.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains successor variables to form a single cycle through every node: `succ[i]` is the
+///! node visited after node `i`, counting from 0.  The successors are kept distinct by an
+///! `AllDiffConstraint`, and the end of each chain of known successors can't lead back to its
+///! start until the chain covers every node.
+pub struct Circuit<A> where A: ToVar<VarType=Fd> {
+    succ: Vec<A>,
+}
+
+impl<A> Circuit<A> where A: ToVar<VarType=Fd> {
+    pub fn new(succ: Vec<A>) -> Circuit<A> {
+        Circuit { succ: succ }
+    }
+}
+
+impl<A> ToConstraint for Circuit<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarCircuit;
+    fn into_constraint(self, state: &mut State) -> VarCircuit {
+        let succ: Vec<Var<Fd>> = self.succ.into_iter().map(|var| state.make_var_of(var)).collect();
+        state.add_constraint(AllDiffConstraint::new(succ.clone()));
+        VarCircuit { succ: succ }
+    }
+}
+
+///! Implementation of `Circuit`.  Don't use this directly, use `Circuit`.
+#[derive(Debug, Clone)]
+pub struct VarCircuit {
+    succ: Vec<Var<Fd>>,
+}
+
+impl VarCircuit {
+    ///! Remove the values that would close a subtour, given the successors known so far.  Returns
+    ///! whether any domain changed, or None if the known successors already form a subtour.
+    fn prune_subtours(domains: &mut [Fd]) -> Option<bool> {
+        let n = domains.len();
+        let next: Vec<Option<usize>> = domains.iter().map(|fd| fd.single_value().map(|x| x as usize)).collect();
+        let mut has_pred = vec![false; n];
+        for &succ in next.iter().filter_map(|x| x.as_ref()) {
+            if has_pred[succ] { return None; }
+            has_pred[succ] = true;
+        }
+        let mut visited = vec![false; n];
+        let mut changed = false;
+        for head in (0..n).filter(|&i| !has_pred[i]) {
+            let (mut end, mut len) = (head, 1);
+            visited[head] = true;
+            while let Some(succ) = next[end] {
+                end = succ;
+                len += 1;
+                visited[end] = true;
+            }
+            if len < n && domains[end].in_range(head as i64) {
+                domains[end] = domains[end].difference(&Fd::new_single(head as i64));
+                if !domains[end].is_valid() { return None; }
+                changed = true;
+            }
+        }
+        // Anything not reached from the start of a chain is on a cycle of known successors, which
+        // is only allowed if it covers every node.
+        if let Some(start) = (0..n).find(|&i| !visited[i]) {
+            let (mut node, mut len) = (next[start].unwrap(), 1);
+            while node != start {
+                node = next[node].unwrap();
+                len += 1;
+            }
+            if len < n { return None; }
+        }
+        Some(changed)
+    }
+}
+
+impl Constraint for VarCircuit {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarCircuit> {
+        let n = self.succ.len();
+        if n == 0 { return Irrelevant; }
+        let nodes = Fd::new_range(0, n as i64 - 1);
+        let old: Vec<Option<Fd>> = self.succ.iter().map(|&var| state.get_value(var).cloned()).collect();
+        let mut domains: Vec<Fd> = old.iter().enumerate().map(|(i, fd)| {
+            let fd = match *fd {
+                Some(ref fd) => fd.combine(&nodes),
+                None => nodes.clone(),
+            };
+            // A node can only be its own successor if it's the only one.
+            if n > 1 { fd.difference(&Fd::new_single(i as i64)) } else { fd }
+        }).collect();
+        if domains.iter().any(|fd| !fd.is_valid()) { return Failed; }
+        // Each removed value can fix another successor, so repeat until nothing changes.
+        loop {
+            match VarCircuit::prune_subtours(&mut domains) {
+                None => { return Failed; },
+                Some(true) => (),
+                Some(false) => break,
+            }
+        }
+        for ((&var, old), new) in self.succ.iter().zip(old.into_iter()).zip(domains.iter()) {
+            match old {
+                Some(ref old) if old == new => (),
+                Some(_) => unsafe { state.overwrite_var(var, new.clone()); },
+                None => { state.unify(var, new.clone()); },
+            }
+        }
+        if !state.ok() { return Failed; }
+        if domains.iter().all(|fd| fd.single_value().is_some()) { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.succ.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.succ.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains tasks, each given as a start time, a duration and a demand, so that the total
+///! demand of the tasks running at any time never exceeds `capacity`.  A task runs from its start
+///! up to, but not including, its start plus its duration.
+///!
+///! This uses timetable filtering: each task whose latest start comes before its earliest end must
+///! be running in between, and no other task may start where it would push that total over the
+///! capacity.  Sets of tasks with more work than fits between their earliest start and latest end
+///! fail right away.
+pub struct Cumulative<A> where A: ToVar<VarType=Fd> {
+    tasks: Vec<(A, i64, i64)>,
+    capacity: i64,
+}
+
+impl<A> Cumulative<A> where A: ToVar<VarType=Fd> {
+    pub fn new(tasks: Vec<(A, i64, i64)>, capacity: i64) -> Cumulative<A> {
+        Cumulative { tasks: tasks, capacity: capacity }
+    }
+}
+
+impl<A> ToConstraint for Cumulative<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarCumulative;
+    fn into_constraint(self, state: &mut State) -> VarCumulative {
+        let tasks = self.tasks.into_iter().map(|(start, duration, demand)| {
+            (state.make_var_of(start), duration, demand)
+        }).collect();
+        VarCumulative { tasks: tasks, capacity: self.capacity }
+    }
+}
+
+///! Constrains tasks, each given as a start time and a duration, so that no two run at the same
+///! time.  This is a `Cumulative` constraint where every task uses the whole capacity.
+pub struct Disjunctive<A> where A: ToVar<VarType=Fd> {
+    tasks: Vec<(A, i64)>,
+}
+
+impl<A> Disjunctive<A> where A: ToVar<VarType=Fd> {
+    pub fn new(tasks: Vec<(A, i64)>) -> Disjunctive<A> {
+        Disjunctive { tasks: tasks }
+    }
+}
+
+impl<A> ToConstraint for Disjunctive<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarCumulative;
+    fn into_constraint(self, state: &mut State) -> VarCumulative {
+        let tasks = self.tasks.into_iter().map(|(start, duration)| (start, duration, 1)).collect();
+        Cumulative::new(tasks, 1).into_constraint(state)
+    }
+}
+
+///! Implementation of `Cumulative` and `Disjunctive`.  Don't use this directly.
+#[derive(Debug, Clone)]
+pub struct VarCumulative {
+    tasks: Vec<(Var<Fd>, i64, i64)>,
+    capacity: i64,
+}
+
+impl VarCumulative {
+    ///! Fail if some window between a task's earliest start and another's latest end must hold
+    ///! more work than fits in it.
+    fn overloaded(&self, bounds: &[(i64, i64)]) -> bool {
+        // With the tasks ordered by latest end, one sweep from each earliest start adds up the
+        // work of every window beginning there.
+        let mut by_end: Vec<(i64, i64, i64)> = bounds.iter().zip(self.tasks.iter())
+            .map(|(&(est, lst), &(_, duration, demand))| (lst.saturating_add(duration), est, duration.saturating_mul(demand)))
+            .collect();
+        by_end.sort();
+        let mut starts: Vec<i64> = bounds.iter().map(|&(est, _)| est).collect();
+        starts.sort();
+        starts.dedup();
+        for &start in starts.iter() {
+            let mut energy = 0i64;
+            for &(lct, est, work) in by_end.iter().filter(|&&(_, est, _)| est >= start) {
+                energy = energy.saturating_add(work);
+                if lct > start && energy > self.capacity.saturating_mul(lct.saturating_sub(start)) { return true; }
+            }
+        }
+        false
+    }
+
+    ///! Return the time segments covered by compulsory parts, with the total demand of each.
+    fn profile(&self, bounds: &[(i64, i64)]) -> Vec<(i64, i64, i64)> {
+        let mut events = Vec::new();
+        for (&(est, lst), &(_, duration, demand)) in bounds.iter().zip(self.tasks.iter()) {
+            let ect = est.saturating_add(duration);
+            if lst < ect {
+                events.push((lst, demand));
+                events.push((ect, -demand));
+            }
+        }
+        events.sort();
+        let mut segments = Vec::new();
+        let mut height = 0;
+        for (i, &(time, change)) in events.iter().enumerate() {
+            height += change;
+            match events.get(i + 1) {
+                Some(&(next, _)) if next > time && height > 0 => { segments.push((time, next, height)); },
+                _ => (),
+            }
+        }
+        segments
+    }
+}
+
+impl Constraint for VarCumulative {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarCumulative> {
+        let old: Vec<Fd> = match self.tasks.iter().map(|&(var, _, _)| state.get_value(var).cloned()).collect() {
+            Some(old) => old,
+            None => { return Unchanged; },
+        };
+        let mut starts = old.clone();
+        // Pruning one task can create a compulsory part for it, so repeat until nothing changes.
+        loop {
+            let bounds: Vec<(i64, i64)> = match starts.iter().map(Fd::bounds).collect() {
+                Some(bounds) => bounds,
+                None => { return Failed; },
+            };
+            if self.overloaded(&bounds) { return Failed; }
+            let profile = self.profile(&bounds);
+            if profile.iter().any(|&(_, _, height)| height > self.capacity) { return Failed; }
+
+            let mut changed = false;
+            for (i, &(_, duration, demand)) in self.tasks.iter().enumerate() {
+                if duration <= 0 || demand <= 0 { continue; }
+                let (est, lst) = bounds[i];
+                let ect = est.saturating_add(duration);
+                for &(from, to, height) in profile.iter() {
+                    // Leave out the task's own compulsory part.
+                    let own = if lst <= from && to <= ect { demand } else { 0 };
+                    if height - own + demand <= self.capacity { continue; }
+                    let conflicts = Fd::new_range(from.saturating_sub(duration - 1), to - 1);
+                    let new = starts[i].difference(&conflicts);
+                    if new != starts[i] {
+                        if !new.is_valid() { return Failed; }
+                        starts[i] = new;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed { break; }
+        }
+        for ((&(var, _, _), old), new) in self.tasks.iter().zip(old.iter()).zip(starts.iter()) {
+            if old != new { unsafe { state.overwrite_var(var, new.clone()); } }
+        }
+        if starts.iter().all(|fd| fd.single_value().is_some()) { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.tasks.iter().any(|&(var, _, _)| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for &mut (ref mut var, _, _) in self.tasks.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        // The demand over capacity, summed over time.
+        let mut events: Vec<(i64, i64)> = Vec::with_capacity(self.tasks.len() * 2);
+        for &(start, duration, demand) in self.tasks.iter() {
+            let start = match values.get(start) {
+                Some(start) => start,
+                None => { return None; },
+            };
+            events.push((start, demand));
+            events.push((start.saturating_add(duration), -demand));
+        }
+        events.sort();
+        let (mut total, mut load) = (0usize, 0i64);
+        for (i, &(time, change)) in events.iter().enumerate() {
+            load = load.saturating_add(change);
+            if let Some(&(next, _)) = events.get(i + 1) {
+                if load > self.capacity {
+                    let excess = distance(load, self.capacity).saturating_mul(distance(next, time));
+                    total = total.saturating_add(excess);
+                }
+            }
+        }
+        Some(total)
+    }
+}
+
+///! Constrains how many of `vars` take each of the listed values: for each `(value, count)`,
+///! exactly `count` of the variables equal `value`.  Values that aren't listed may be taken any
+///! number of times.  Each count is narrowed to lie between the number of variables known to take
+///! its value and the number that still can, and once either bound is reached, the remaining
+///! variables are set to or kept from that value.  A value listed more than once has its counts
+///! unified.
+pub struct GlobalCardinality<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    vars: Vec<A>,
+    counts: Vec<(i64, B)>,
+}
+
+impl<A, B> GlobalCardinality<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(vars: Vec<A>, counts: Vec<(i64, B)>) -> GlobalCardinality<A, B> {
+        GlobalCardinality { vars: vars, counts: counts }
+    }
+}
+
+impl<A, B> ToConstraint for GlobalCardinality<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarGlobalCardinality;
+    fn into_constraint(self, state: &mut State) -> VarGlobalCardinality {
+        let vars = self.vars.into_iter().map(|var| state.make_var_of(var)).collect();
+        let mut counts: Vec<(i64, Var<Fd>)> = Vec::new();
+        for (value, count) in self.counts.into_iter() {
+            let count = state.make_var_of(count);
+            match counts.iter().find(|x| x.0 == value).map(|x| x.1) {
+                Some(existing) => { state.unify(existing, count); },
+                None => counts.push((value, count)),
+            }
+        }
+        VarGlobalCardinality { vars: vars, counts: counts }
+    }
+}
+
+///! Constrains exactly `count` of `vars` to equal `value`.  This is a `GlobalCardinality` with a
+///! single value.
+pub struct Count<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    vars: Vec<A>,
+    value: i64,
+    count: B,
+}
+
+impl<A, B> Count<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(vars: Vec<A>, value: i64, count: B) -> Count<A, B> {
+        Count { vars: vars, value: value, count: count }
+    }
+}
+
+impl<A, B> ToConstraint for Count<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarGlobalCardinality;
+    fn into_constraint(self, state: &mut State) -> VarGlobalCardinality {
+        GlobalCardinality::new(self.vars, vec![(self.value, self.count)]).into_constraint(state)
+    }
+}
+
+///! Implementation of `GlobalCardinality` and `Count`.  Don't use this directly.
+#[derive(Debug, Clone)]
+pub struct VarGlobalCardinality {
+    vars: Vec<Var<Fd>>,
+    counts: Vec<(i64, Var<Fd>)>,
+}
+
+impl Constraint for VarGlobalCardinality {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarGlobalCardinality> {
+        // A variable without a domain yet could still take any of the values.
+        let any = Fd::new_range(::std::i64::MIN, ::std::i64::MAX);
+        let old_vars: Vec<Option<Fd>> = self.vars.iter().map(|&var| state.get_value(var).cloned()).collect();
+        let n = old_vars.len() as i64;
+        let old_counts: Vec<Option<Fd>> = self.counts.iter().map(|&(_, count)| state.get_value(count).cloned()).collect();
+        let mut vars: Vec<Fd> = old_vars.iter().map(|fd| fd.clone().unwrap_or_else(|| any.clone())).collect();
+        let mut counts: Vec<Fd> = old_counts.iter().map(|count| match *count {
+            Some(ref count) => count.combine(&Fd::new_range(0, n)),
+            None => Fd::new_range(0, n),
+        }).collect();
+
+        // Setting or removing a value changes the other values' counts, so repeat until nothing
+        // changes.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let total_min = counts.iter().fold(0i64, |sum, count| sum + count.min().unwrap_or(0));
+            for (k, &(value, _)) in self.counts.iter().enumerate() {
+                let sure = vars.iter().filter(|fd| fd.single_value() == Some(value)).count() as i64;
+                let possible = vars.iter().filter(|fd| fd.in_range(value)).count() as i64;
+                // The other values need at least their minimum counts of the variables.
+                let others_min = total_min - counts[k].min().unwrap_or(0);
+                let new = counts[k].constrain_range(Some(sure), Some(min(possible, n - others_min)));
+                if !new.is_valid() { return Failed; }
+                if new != counts[k] {
+                    counts[k] = new;
+                    changed = true;
+                }
+                if sure == possible { continue; }
+                let (lo, hi) = counts[k].bounds().unwrap();
+                if hi == sure {
+                    for fd in vars.iter_mut().filter(|fd| fd.in_range(value) && fd.single_value().is_none()) {
+                        *fd = fd.difference(&Fd::new_single(value));
+                    }
+                    changed = true;
+                } else if lo == possible {
+                    for fd in vars.iter_mut().filter(|fd| fd.in_range(value)) {
+                        *fd = Fd::new_single(value);
+                    }
+                    changed = true;
+                }
+            }
+        }
+
+        for ((&var, old), new) in self.vars.iter().zip(old_vars.into_iter()).zip(vars.iter()) {
+            match old {
+                Some(ref old) if old == new => (),
+                Some(_) => unsafe { state.overwrite_var(var, new.clone()); },
+                None if *new == any => (),
+                None => { state.unify(var, new.clone()); },
+            }
+        }
+        for ((&(_, var), old), new) in self.counts.iter().zip(old_counts.into_iter()).zip(counts.into_iter()) {
+            match old {
+                Some(ref old) if *old == new => (),
+                Some(_) => unsafe { state.overwrite_var(var, new); },
+                None => { state.unify(var, new); },
+            }
+        }
+        if !state.ok() { return Failed; }
+        if vars.iter().all(|fd| fd.single_value().is_some()) { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.vars.iter().any(|var| proxy.contains_key(&var.untyped()))
+            || self.counts.iter().any(|&(_, var)| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.vars.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+        for &mut (_, ref mut var) in self.counts.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        let vars: Vec<i64> = match self.vars.iter().map(|&var| values.get(var)).collect() {
+            Some(vars) => vars,
+            None => { return None; },
+        };
+        let mut total = 0usize;
+        for &(value, count) in self.counts.iter() {
+            let actual = vars.iter().filter(|&&x| x == value).count() as i64;
+            match values.get(count) {
+                Some(count) => { total = total.saturating_add(distance(actual, count)); },
+                None => { return None; },
+            }
+        }
+        Some(total)
+    }
+}
+
+///! Constrains a sequence of finite domain variables, read in order, to be accepted by `dfa`.
+///! Values are removed from each variable unless some path through the automaton uses them and
+///! can still reach an accepting state.
+pub struct Regular<A> where A: ToVar<VarType=Fd> {
+    vars: Vec<A>,
+    dfa: Dfa,
+}
+
+impl<A> Regular<A> where A: ToVar<VarType=Fd> {
+    pub fn new(vars: Vec<A>, dfa: Dfa) -> Regular<A> {
+        Regular { vars: vars, dfa: dfa }
+    }
+}
+
+impl<A> ToConstraint for Regular<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarRegular;
+    fn into_constraint(self, state: &mut State) -> VarRegular {
+        let vars = self.vars.into_iter().map(|var| state.make_var_of(var)).collect();
+        VarRegular { vars: vars, dfa: Rc::new(self.dfa) }
+    }
+}
+
+///! Implementation of `Regular`.  Don't use this directly, use `Regular`.
+#[derive(Debug, Clone)]
+pub struct VarRegular {
+    vars: Vec<Var<Fd>>,
+    dfa: Rc<Dfa>,
+}
+
+impl Constraint for VarRegular {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarRegular> {
+        let dfa = &*self.dfa;
+        let n = self.vars.len();
+        let old: Vec<Option<Fd>> = self.vars.iter().map(|&var| state.get_value(var).cloned()).collect();
+        let allowed = |i: usize, symbol: i64| match old[i] {
+            Some(ref fd) => fd.in_range(symbol),
+            None => true,
+        };
+
+        // Lay out the automaton's states after each variable, keeping those reachable from the
+        // start, then those which can go on to accept.
+        let mut layers = vec![vec![false; dfa.states()]; n + 1];
+        layers[0][dfa.start()] = true;
+        for i in 0..n {
+            for from in 0..dfa.states() {
+                if !layers[i][from] { continue; }
+                for (symbol, to) in dfa.transitions_from(from) {
+                    if allowed(i, symbol) { layers[i + 1][to] = true; }
+                }
+            }
+        }
+        for to in 0..dfa.states() {
+            layers[n][to] = layers[n][to] && dfa.is_accepting(to);
+        }
+        let mut supported = vec![Vec::new(); n];
+        for i in (0..n).rev() {
+            for from in 0..dfa.states() {
+                if !layers[i][from] { continue; }
+                let mut alive = false;
+                for (symbol, to) in dfa.transitions_from(from) {
+                    if allowed(i, symbol) && layers[i + 1][to] {
+                        supported[i].push(symbol);
+                        alive = true;
+                    }
+                }
+                layers[i][from] = alive;
+            }
+        }
+        if !layers[0][dfa.start()] { return Failed; }
+
+        for ((&var, old), mut values) in self.vars.iter().zip(old.iter()).zip(supported.into_iter()) {
+            values.sort();
+            values.dedup();
+            let new = Fd::new_values(values);
+            match *old {
+                Some(ref old) if *old == new => (),
+                Some(_) => unsafe { state.overwrite_var(var, new); },
+                None => { state.unify(var, new); },
+            }
+        }
+        if !state.ok() { return Failed; }
+        let fixed = self.vars.iter().all(|&var| state.get_value(var).and_then(Fd::single_value).is_some());
+        if fixed { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.vars.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.vars.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains two equal-length sequences of finite domain variables so that `a` comes no later
+///! than `b` in lexicographic order.  This is mostly useful for breaking symmetries, by requiring
+///! a solution to come before its mirror image.
+pub struct LexLessEq<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    a: Vec<A>,
+    b: Vec<B>,
+}
+
+impl<A, B> LexLessEq<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(a: Vec<A>, b: Vec<B>) -> LexLessEq<A, B> {
+        assert!(a.len() == b.len());
+        LexLessEq { a: a, b: b }
+    }
+}
+
+impl<A, B> ToConstraint for LexLessEq<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarLexChain;
+    fn into_constraint(self, state: &mut State) -> VarLexChain {
+        let a = self.a.into_iter().map(|var| state.make_var_of(var)).collect();
+        let b = self.b.into_iter().map(|var| state.make_var_of(var)).collect();
+        VarLexChain { rows: vec![a, b] }
+    }
+}
+
+///! Constrains each of a list of equal-length sequences to come no later than the next in
+///! lexicographic order, like a `LexLessEq` between each neighboring pair.
+pub struct LexChain<A> where A: ToVar<VarType=Fd> {
+    rows: Vec<Vec<A>>,
+}
+
+impl<A> LexChain<A> where A: ToVar<VarType=Fd> {
+    pub fn new(rows: Vec<Vec<A>>) -> LexChain<A> {
+        assert!(rows.windows(2).all(|pair| pair[0].len() == pair[1].len()));
+        LexChain { rows: rows }
+    }
+}
+
+impl<A> ToConstraint for LexChain<A> where A: ToVar<VarType=Fd> {
+    type ConstraintType = VarLexChain;
+    fn into_constraint(self, state: &mut State) -> VarLexChain {
+        let rows = self.rows.into_iter().map(|row| {
+            row.into_iter().map(|var| state.make_var_of(var)).collect()
+        }).collect();
+        VarLexChain { rows: rows }
+    }
+}
+
+///! Implementation of `LexLessEq` and `LexChain`.  Don't use this directly.
+#[derive(Debug, Clone)]
+pub struct VarLexChain {
+    rows: Vec<Vec<Var<Fd>>>,
+}
+
+///! Narrow `a` and `b` so that `a <= b` lexicographically.  Returns None if that's impossible, or
+///! whether it's certain to hold.
+fn lex_less_eq(a: &mut [Fd], b: &mut [Fd]) -> Option<bool> {
+    let n = a.len();
+    // Skip the leading positions where both are known and equal.  At the first other position,
+    // a[i] <= b[i], and if everything after it is certain to compare greater, a[i] < b[i].
+    for i in 0..n {
+        let offset = if lex_greater_after(a, b, i) { 1 } else { 0 };
+        let b_max = match b[i].max() { Some(x) => x, None => { return None; } };
+        let a_min = match a[i].min() { Some(x) => x, None => { return None; } };
+        a[i] = a[i].constrain_range(None, Some(b_max.saturating_sub(offset)));
+        b[i] = b[i].constrain_range(Some(a_min.saturating_add(offset)), None);
+        if !a[i].is_valid() || !b[i].is_valid() { return None; }
+        match (a[i].single_value(), b[i].single_value()) {
+            (Some(x), Some(y)) if x == y => continue,
+            _ => (),
+        }
+        return Some(a[i].max() < b[i].min());
+    }
+    Some(true)
+}
+
+///! Return whether `a[i+1..]` is certain to come after `b[i+1..]`.
+fn lex_greater_after(a: &[Fd], b: &[Fd], i: usize) -> bool {
+    for j in (i + 1)..a.len() {
+        if a[j].min() > b[j].max() { return true; }
+        match (a[j].single_value(), b[j].single_value()) {
+            (Some(x), Some(y)) if x == y => continue,
+            _ => { return false; },
+        }
+    }
+    false
+}
+
+impl Constraint for VarLexChain {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarLexChain> {
+        let any = Fd::new_range(::std::i64::MIN, ::std::i64::MAX);
+        let old: Vec<Vec<Option<Fd>>> = self.rows.iter().map(|row| {
+            row.iter().map(|&var| state.get_value(var).cloned()).collect()
+        }).collect();
+        let mut rows: Vec<Vec<Fd>> = old.iter().map(|row| {
+            row.iter().map(|fd| fd.clone().unwrap_or_else(|| any.clone())).collect()
+        }).collect();
+        // Narrowing one row can narrow its neighbors in turn, so repeat until nothing changes.
+        let mut entailed;
+        loop {
+            let before = rows.clone();
+            entailed = true;
+            for i in 1..rows.len() {
+                let (above, below) = rows.split_at_mut(i);
+                match lex_less_eq(&mut above[i - 1], &mut below[0]) {
+                    None => { return Failed; },
+                    Some(x) => { entailed = entailed && x; },
+                }
+            }
+            if rows == before { break; }
+        }
+        for ((vars, old), new) in self.rows.iter().zip(old.iter()).zip(rows.into_iter()) {
+            for ((&var, old), new) in vars.iter().zip(old.iter()).zip(new.into_iter()) {
+                match *old {
+                    Some(ref old) if *old == new => (),
+                    Some(_) => unsafe { state.overwrite_var(var, new); },
+                    None if new == any => (),
+                    None => { state.unify(var, new); },
+                }
+            }
+        }
+        if !state.ok() { return Failed; }
+        if entailed { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.rows.iter().any(|row| row.iter().any(|var| proxy.contains_key(&var.untyped())))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for row in self.rows.iter_mut() {
+            for var in row.iter_mut() {
+                proxy.update_var(var.untyped_mut());
+            }
+        }
+    }
+}
+
+///! Constrains two sequences of finite domain variables to be inverse permutations of each other:
+///! `f[i] = j` exactly when `g[j] = i`, counting from 0.  Each variable keeps only the values
+///! whose counterpart still allows it.
+pub struct Inverse<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    f: Vec<A>,
+    g: Vec<B>,
+}
+
+impl<A, B> Inverse<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    pub fn new(f: Vec<A>, g: Vec<B>) -> Inverse<A, B> {
+        assert!(f.len() == g.len());
+        Inverse { f: f, g: g }
+    }
+}
+
+impl<A, B> ToConstraint for Inverse<A, B> where A: ToVar<VarType=Fd>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarInverse;
+    fn into_constraint(self, state: &mut State) -> VarInverse {
+        let f = self.f.into_iter().map(|var| state.make_var_of(var)).collect();
+        let g = self.g.into_iter().map(|var| state.make_var_of(var)).collect();
+        VarInverse { f: f, g: g }
+    }
+}
+
+///! Implementation of `Inverse`.  Don't use this directly, use `Inverse`.
+#[derive(Debug, Clone)]
+pub struct VarInverse {
+    f: Vec<Var<Fd>>,
+    g: Vec<Var<Fd>>,
+}
+
+impl Constraint for VarInverse {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarInverse> {
+        let n = self.f.len();
+        if n == 0 { return Irrelevant; }
+        let indexes = Fd::new_range(0, n as i64 - 1);
+        let old: Vec<Option<Fd>> = self.f.iter().chain(self.g.iter()).map(|&var| state.get_value(var).cloned()).collect();
+        let domains: Vec<Fd> = old.iter().map(|fd| match *fd {
+            Some(ref fd) => fd.combine(&indexes),
+            None => indexes.clone(),
+        }).collect();
+        let (f, g) = domains.split_at(n);
+        // j stays in f[i] only if i is in g[j], and once f[i] is known to be j, g[j] must be i.
+        // The same goes the other way around.
+        fn narrow(from: &[Fd], to: &[Fd]) -> Vec<Fd> {
+            let mut result: Vec<Fd> = from.iter().enumerate().map(|(i, fd)| {
+                Fd::new_values(fd.iter().filter(|&j| to[j as usize].in_range(i as i64)).collect())
+            }).collect();
+            for (j, fd) in to.iter().enumerate() {
+                if let Some(i) = fd.single_value() {
+                    result[i as usize] = result[i as usize].combine(&Fd::new_single(j as i64));
+                }
+            }
+            result
+        }
+        let (mut new_f, mut new_g) = (f.to_vec(), g.to_vec());
+        loop {
+            let next_f = narrow(&new_f, &new_g);
+            let next_g = narrow(&new_g, &next_f);
+            if next_f.iter().chain(next_g.iter()).any(|fd| !fd.is_valid()) { return Failed; }
+            if next_f == new_f && next_g == new_g { break; }
+            new_f = next_f;
+            new_g = next_g;
+        }
+
+        let vars = self.f.iter().chain(self.g.iter());
+        for ((&var, old), new) in vars.zip(old.iter()).zip(new_f.iter().chain(new_g.iter())) {
+            match *old {
+                Some(ref old) if old == new => (),
+                Some(_) => unsafe { state.overwrite_var(var, new.clone()); },
+                None => { state.unify(var, new.clone()); },
+            }
+        }
+        if !state.ok() { return Failed; }
+        if new_f.iter().all(|fd| fd.single_value().is_some()) { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.f.iter().chain(self.g.iter()).any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.f.iter_mut().chain(self.g.iter_mut()) {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Links an array of boolean variables to a finite domain variable, so that `bools[i]` is true
+///! exactly when `fd` equals `i`, counting from 0.
+pub struct BoolChannel<A, B> where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    bools: Vec<A>,
+    fd: B,
+}
+
+impl<A, B> BoolChannel<A, B> where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    pub fn new(bools: Vec<A>, fd: B) -> BoolChannel<A, B> {
+        BoolChannel { bools: bools, fd: fd }
+    }
+}
+
+impl<A, B> ToConstraint for BoolChannel<A, B> where A: ToVar<VarType=bool>, B: ToVar<VarType=Fd> {
+    type ConstraintType = VarBoolChannel;
+    fn into_constraint(self, state: &mut State) -> VarBoolChannel {
+        let bools = self.bools.into_iter().map(|var| state.make_var_of(var)).collect();
+        let fd = state.make_var_of(self.fd);
+        VarBoolChannel { bools: bools, fd: fd }
+    }
+}
+
+///! Implementation of `BoolChannel`.  Don't use this directly, use `BoolChannel`.
+#[derive(Debug, Clone)]
+pub struct VarBoolChannel {
+    bools: Vec<Var<bool>>,
+    fd: Var<Fd>,
+}
+
+impl Constraint for VarBoolChannel {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarBoolChannel> {
+        if self.bools.is_empty() { return Failed; }
+        let old = state.get_value(self.fd).cloned();
+        let mut fd = match old {
+            Some(ref fd) => fd.constrain_range(Some(0), Some(self.bools.len() as i64 - 1)),
+            None => Fd::new_range(0, self.bools.len() as i64 - 1),
+        };
+        let mut excluded = HashSet::new();
+        for (i, &var) in self.bools.iter().enumerate() {
+            match state.get_value(var) {
+                Some(&true) => { fd = fd.combine(&Fd::new_single(i as i64)); },
+                Some(&false) => { excluded.insert(i as i64); },
+                None => (),
+            }
+        }
+        fd.remove_values(&excluded);
+        if !fd.is_valid() { return Failed; }
+        for (i, &var) in self.bools.iter().enumerate() {
+            if !fd.in_range(i as i64) {
+                state.unify(var, false);
+            } else if fd.single_value().is_some() {
+                state.unify(var, true);
+            }
+        }
+        let fixed = fd.single_value().is_some();
+        match old {
+            Some(ref old) if *old == fd => (),
+            Some(_) => unsafe { state.overwrite_var(self.fd, fd); },
+            None => { state.unify(self.fd, fd); },
+        }
+        if !state.ok() { Failed } else if fixed { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.fd.untyped())
+            || self.bools.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.fd.untyped_mut());
+        for var in self.bools.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! How much work `AllDiffConstraint` does to remove values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllDiffPropagation {
+    ///! Only remove the values of variables which are already known.  This is the cheapest level.
+    Value,
+    ///! Also find Hall intervals, ranges of n values which n variables are confined to, and remove
+    ///! those values from every other variable.
+    Bounds,
+    ///! Remove every value which can't be part of any solution, using Régin's matching-based
+    ///! filtering.  Falls back to `Bounds` when the domains are too large to list.
+    Domain,
+}
+
+///! Constrains a set of Fds to have distinct values from one another.
+#[derive(Debug, Clone)]
+pub struct AllDiffConstraint {
+    fds: Vec<Var<Fd>>,
+    propagation: AllDiffPropagation,
+}
+
+///! `AllDiffPropagation::Domain` lists every value in the variables' domains, and falls back to
+///! bounds reasoning when there are more than this many.
+const ALLDIFF_VALUE_LIMIT: usize = 4096;
+
+impl AllDiffConstraint {
+    pub fn new(fds: Vec<Var<Fd>>) -> AllDiffConstraint {
+        AllDiffConstraint { fds: fds, propagation: AllDiffPropagation::Value }
+    }
+
+    pub fn with_propagation(fds: Vec<Var<Fd>>, propagation: AllDiffPropagation) -> AllDiffConstraint {
+        AllDiffConstraint { fds: fds, propagation: propagation }
+    }
+
+    fn remove_singles(varfds: &mut Vec<(Var<Fd>, Option<Fd>)>, singles: &mut Fd, mut state: Option<&mut StateProxy>, remove_var: bool) -> bool {
+        for i in (0..varfds.len()).rev() {
+            let remove = {
+                let (_, ref fd) = varfds[i];
+                if let Some(ref fd) = *fd {
+                    if let Some(single) = fd.single_value() {
+                        //println!("value for {:?}: {:?}", fd, single);
+                        if singles.in_range(single) {
+                            return false;
+                        }
+                        *singles = singles.union(fd);
+                        true
+                    } else { false }
+                } else { false }
+            };
+            if remove {
+                let (var, fd) = if remove_var {
+                    varfds.swap_remove(i)
+                } else {
+                    let &mut (var, ref mut fd) = &mut varfds[i];
+                    (var, fd.take())
+                };
+                let fd = fd.unwrap();
+                if let Some(ref mut state) = state {
+                    unsafe { state.overwrite_var(var, fd); }
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Constraint for AllDiffConstraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<AllDiffConstraint> {
+        match self.propagation {
+            AllDiffPropagation::Value => self.update_values(state),
+            AllDiffPropagation::Bounds | AllDiffPropagation::Domain => self.update_hall(state),
+        }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.fds.iter().any(|fd| {
+            proxy.contains_key(&fd.untyped())
+        })
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for fd in self.fds.iter_mut() {
+            proxy.update_var(fd.untyped_mut());
+        }
+    }
+    fn need_update(&self, vars: &VarMap) -> bool {
+        self.fds.iter().any(|fd| { vars.need_update(fd.untyped()) })
+    }
+    fn violations(&self, values: &Assignment) -> Option<usize> {
+        // The number of variables which would have to change.
+        let mut seen: Vec<i64> = match self.fds.iter().map(|&fd| values.get(fd)).collect() {
+            Some(seen) => seen,
+            None => { return None; },
+        };
+        seen.sort();
+        seen.dedup();
+        Some(self.fds.len() - seen.len())
+    }
+}
+
+impl AllDiffConstraint {
+    fn update_values(&self, state: &mut StateProxy) -> ConstraintResult<AllDiffConstraint> {
+        use core::ConstraintResult::*;
+        // unique values which must be removed from other fds
+        // indexes into self.fds, and later replace.fds, for unique fds which can be removed
+        if !self.fds.iter().any(|&var| {
+            state.get_changed_value(var).and_then(|fd| fd.single_value()).is_some()
+        }) {
+            return Unchanged;
+        }
+        let mut varfds: Vec<(Var<Fd>, Option<Fd>)> = self.fds.iter().map(|fd| {
+            (*fd, state.get_value(*fd).map(|x| x.clone()))
+        }).collect();
+        let mut singles = Fd::err();
+        let all_set = varfds.iter().all(|&(_, ref val)| val.is_some());
+        if !AllDiffConstraint::remove_singles(&mut varfds, &mut singles, None, all_set) {
+            return Failed;
+        }
+        //println!("got {} singles", singles.len());
+        loop {
+            if !singles.is_valid() {
+                let overwrite = varfds.into_iter().map(|(var, fd)| {
+                    //println!("overwriting {:?} with {:?}", var, fd);
+                    if let Some(fd) = fd {
+                        unsafe { state.overwrite_var(var, fd); }
+                    }
+                    var
+                });
+                return if all_set {
+                    let replace = AllDiffConstraint::with_propagation(overwrite.collect(), self.propagation);
+                    Updated(replace)
+                } else {
+                    for _ in overwrite { }
+                    Unchanged
+                };
+            }
+            if varfds.is_empty() {
+                return Irrelevant;
+            }
+
+            //println!("removing values {:?} from {} fds", singles, varfds.len());
+            for &mut (_, ref mut fd) in varfds.iter_mut() {
+                if let Some(ref mut fd) = *fd {
+                    *fd = fd.difference(&singles);
+                    if !fd.is_valid() {
+                        return Failed;
+                    }
+                }
+            }
+            singles = Fd::err();
+            if !AllDiffConstraint::remove_singles(&mut varfds, &mut singles, Some(state), all_set) {
+                return Failed;
+            }
+        }
+    }
+
+    fn update_hall(&self, state: &mut StateProxy) -> ConstraintResult<AllDiffConstraint> {
+        if !self.fds.iter().any(|&var| state.get_changed_value(var).is_some()) {
+            return Unchanged;
+        }
+        // Unbound variables can always take a value nobody else wants, so they're left out.
+        let (vars, orig): (Vec<Var<Fd>>, Vec<Fd>) = self.fds.iter()
+            .filter_map(|&var| state.get_value(var).map(|fd| (var, fd.clone())))
+            .unzip();
+        let mut fds = orig.clone();
+        let listable = self.propagation == AllDiffPropagation::Domain && {
+            let all = fds.iter().fold(Fd::err(), |all, fd| all.union(fd));
+            all.value_count() <= ALLDIFF_VALUE_LIMIT
+        };
+        let ok = if listable { alldiff_matching(&mut fds) } else { alldiff_hall_intervals(&mut fds) };
+        if !ok {
+            return Failed;
+        }
+        let done = vars.len() == self.fds.len() && fds.iter().all(|fd| fd.single_value().is_some());
+        for ((var, fd), orig) in vars.into_iter().zip(fds.into_iter()).zip(orig.iter()) {
+            if fd != *orig {
+                unsafe { state.overwrite_var(var, fd); }
+            }
+        }
+        if done { Irrelevant } else { Unchanged }
+    }
+}
+
+///! Find Hall intervals, ranges of values with as many variables confined to them as there are
+///! values, and remove those values from the remaining variables.  Returns false if some range has
+///! too many variables.
+fn alldiff_hall_intervals(fds: &mut Vec<Fd>) -> bool {
+    loop {
+        let bounds: Vec<(i64, i64)> = match fds.iter().map(Fd::bounds).collect() {
+            Some(x) => x,
+            None => { return false; },
+        };
+        let mut changed = false;
+        for &(lo, _) in bounds.iter() {
+            for &(_, hi) in bounds.iter() {
+                if lo > hi { continue; }
+                let inside = bounds.iter().filter(|&&(min, max)| min >= lo && max <= hi).count();
+                let size = (hi.wrapping_sub(lo) as u64).saturating_add(1);
+                if inside as u64 > size {
+                    return false;
+                }
+                if inside as u64 == size {
+                    let hall = Fd::new_range(lo, hi);
+                    for (fd, &(min, max)) in fds.iter_mut().zip(bounds.iter()) {
+                        if (min < lo || max > hi) && (min <= hi && max >= lo) {
+                            let new = fd.difference(&hall);
+                            if !new.is_valid() { return false; }
+                            if new != *fd {
+                                *fd = new;
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+            if changed { break; }
+        }
+        if !changed { return true; }
+    }
+}
+
+///! Régin's filtering: find a matching of variables to distinct values, then keep only the values
+///! which appear in some matching.  Returns false if there's no matching.
+fn alldiff_matching(fds: &mut Vec<Fd>) -> bool {
+    let values: Vec<i64> = fds.iter().fold(Fd::err(), |all, fd| all.union(fd)).iter().collect();
+    let edges: Vec<Vec<usize>> = fds.iter().map(|fd| {
+        fd.iter().map(|x| values.binary_search(&x).unwrap()).collect()
+    }).collect();
+
+    // Kuhn's augmenting paths.
+    let mut var_match: Vec<Option<usize>> = vec![None; fds.len()];
+    let mut value_match: Vec<Option<usize>> = vec![None; values.len()];
+    fn augment(var: usize, edges: &[Vec<usize>], seen: &mut [bool], var_match: &mut [Option<usize>], value_match: &mut [Option<usize>]) -> bool {
+        for &value in edges[var].iter() {
+            if seen[value] { continue; }
+            seen[value] = true;
+            let free = match value_match[value] {
+                None => true,
+                Some(other) => augment(other, edges, seen, var_match, value_match),
+            };
+            if free {
+                var_match[var] = Some(value);
+                value_match[value] = Some(var);
+                return true;
+            }
+        }
+        false
+    }
+    for var in 0..fds.len() {
+        let mut seen = vec![false; values.len()];
+        if !augment(var, &edges, &mut seen, &mut var_match, &mut value_match) {
+            return false;
+        }
+    }
+
+    // Nodes 0..n are variables, and the rest are values.  Matched edges point from variable to
+    // value, and the others point from value to variable.
+    let n = fds.len();
+    let mut graph: Vec<Vec<usize>> = vec![Vec::new(); n + values.len()];
+    for (var, vals) in edges.iter().enumerate() {
+        for &value in vals.iter() {
+            if var_match[var] == Some(value) {
+                graph[var].push(n + value);
+            } else {
+                graph[n + value].push(var);
+            }
+        }
+    }
+    // An edge can be used if it's on an alternating path from a free value...
+    let mut reachable = vec![false; graph.len()];
+    let mut stack: Vec<usize> = (0..values.len()).filter(|&v| value_match[v].is_none()).map(|v| n + v).collect();
+    for &node in stack.iter() { reachable[node] = true; }
+    while let Some(node) = stack.pop() {
+        for &next in graph[node].iter() {
+            if !reachable[next] {
+                reachable[next] = true;
+                stack.push(next);
+            }
+        }
+    }
+    // ...or on an alternating cycle.
+    let components = strongly_connected_components(&graph);
+
+    for (var, fd) in fds.iter_mut().enumerate() {
+        let keep: Vec<i64> = edges[var].iter()
+            .filter(|&&value| {
+                var_match[var] == Some(value) || reachable[n + value] || components[var] == components[n + value]
+            })
+            .map(|&value| values[value])
+            .collect();
+        if keep.len() != fd.value_count() {
+            *fd = Fd::new_values(keep);
+        }
+    }
+    true
+}
+
+///! Tarjan's algorithm, returning the component number of each node.
+fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<usize> {
+    struct Tarjan<'a> {
+        graph: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        component: Vec<usize>,
+        next_index: usize,
+        next_component: usize,
+    }
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, node: usize) {
+            self.index[node] = Some(self.next_index);
+            self.lowlink[node] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+            for &next in self.graph[node].iter() {
+                match self.index[next] {
+                    None => {
+                        self.visit(next);
+                        self.lowlink[node] = min(self.lowlink[node], self.lowlink[next]);
+                    },
+                    Some(index) if self.on_stack[next] => {
+                        self.lowlink[node] = min(self.lowlink[node], index);
+                    },
+                    _ => { },
+                }
+            }
+            if Some(self.lowlink[node]) == self.index[node] {
+                loop {
+                    let member = self.stack.pop().unwrap();
+                    self.on_stack[member] = false;
+                    self.component[member] = self.next_component;
+                    if member == node { break; }
+                }
+                self.next_component += 1;
+            }
+        }
+    }
+    let len = graph.len();
+    let mut tarjan = Tarjan {
+        graph: graph,
+        index: vec![None; len],
+        lowlink: vec![0; len],
+        on_stack: vec![false; len],
+        stack: Vec::new(),
+        component: vec![0; len],
+        next_index: 0,
+        next_component: 0,
+    };
+    for node in 0..len {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+    tarjan.component
+}
+
+///! Constrains a set of `Domain` variables to have distinct values from one another.  Known values
+///! are removed from the other domains, and the constraint fails once the domains hold fewer
+///! values between them than there are variables.
+#[derive(Debug, Clone)]
+pub struct DomainAllDiff<T> where T: Ord + Clone + Debug + 'static {
+    vars: Vec<Var<Domain<T>>>,
+}
+
+impl<T> DomainAllDiff<T> where T: Ord + Clone + Debug + 'static {
+    pub fn new(vars: Vec<Var<Domain<T>>>) -> DomainAllDiff<T> {
+        DomainAllDiff { vars: vars }
+    }
+}
+
+impl<T> ToConstraint for DomainAllDiff<T> where T: Ord + Clone + Debug + 'static {
+    type ConstraintType = DomainAllDiff<T>;
+    fn into_constraint(self, _: &mut State) -> DomainAllDiff<T> { self }
+}
+
+impl<T> Constraint for DomainAllDiff<T> where T: Ord + Clone + Debug + 'static {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<DomainAllDiff<T>> {
+        let orig: Vec<Option<Domain<T>>> = self.vars.iter().map(|&var| state.get_value(var).map(|x| x.clone())).collect();
+        let mut domains = orig.clone();
+        let mut removed = vec![false; domains.len()];
+        loop {
+            let mut found = Domain::err();
+            for (domain, removed) in domains.iter().zip(removed.iter_mut()) {
+                if let Some(value) = domain.as_ref().and_then(|x| x.single_value()) {
+                    if *removed { continue; }
+                    if found.contains(value) {
+                        return Failed;
+                    }
+                    found = found.union(&Domain::new_single(value.clone()));
+                    *removed = true;
+                }
+            }
+            if !found.is_valid() {
+                break;
+            }
+            for domain in domains.iter_mut() {
+                if let Some(ref mut domain) = *domain {
+                    if domain.single_value().is_none() {
+                        *domain = domain.difference(&found);
+                        if !domain.is_valid() {
+                            return Failed;
+                        }
+                    }
+                }
+            }
+        }
+
+        let bound: Vec<&Domain<T>> = domains.iter().filter_map(|x| x.as_ref()).collect();
+        if bound.iter().fold(Domain::err(), |all, domain| all.union(domain)).len() < bound.len() {
+            return Failed;
+        }
+        for ((&var, domain), orig) in self.vars.iter().zip(domains.iter()).zip(orig.iter()) {
+            if let (&Some(ref domain), &Some(ref orig)) = (domain, orig) {
+                if domain != orig {
+                    unsafe { state.overwrite_var(var, domain.clone()); }
+                }
+            }
+        }
+        if removed.iter().all(|&x| x) { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        self.vars.iter().any(|var| proxy.contains_key(&var.untyped()))
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        for var in self.vars.iter_mut() {
+            proxy.update_var(var.untyped_mut());
+        }
+    }
+}
+
+///! Constrains an Fd and a usize to have the same value.
+#[derive(Debug, Clone)]
+pub struct FdUsizeConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
+    fd: A,
+    u: B,
+}
+pub type VarFdUsizeConstraint = FdUsizeConstraint<Var<Fd>, Var<usize>>;
+
+impl<A, B> ToConstraint for FdUsizeConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
+    type ConstraintType = FdUsizeConstraint<Var<Fd>, Var<usize>>;
+    fn into_constraint(self, state: &mut State) -> VarFdUsizeConstraint {
+        let fd = state.make_var_of(self.fd);
+        let u = state.make_var_of(self.u);
+        FdUsizeConstraint { fd: fd, u: u }
+    }
+}
+
+impl<A, B> FdUsizeConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
+    pub fn new(fd: A, u: B) -> FdUsizeConstraint<A, B> {
+        FdUsizeConstraint { fd: fd, u: u }
+    }
+}
+
+impl Constraint for VarFdUsizeConstraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdUsizeConstraint> {
+        use core::ConstraintResult::*;
+        let single_value = {
+            if let Some(u) = state.get_value(self.u) { Some(*u as i64) }
+            else if let Some(fd) = state.get_value(self.fd) {
+                fd.single_value()
+            }
+            else { None }
+        };
+        if let Some(x) = single_value {
+            if x < 0 || x as u64 > ::std::usize::MAX as u64 { return Failed; }
+            state.unify(Fd::new_single(x), self.fd);
+            state.unify(x as usize, self.u);
+            Irrelevant
+        } else {
+            Unchanged
+        }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.fd.untyped()) || proxy.contains_key(&self.u.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.fd.untyped_mut());
+        proxy.update_var(self.u.untyped_mut());
+    }
+}
+
+///! Constrains an Fd and an i32 to have the same value, so that relations over `Var<i32>`, like
+///! `builtins::length`, can be linked to finite domain constraints.  Once either is known, so is
+///! the other; until then, the Fd is kept within the range of an i32.
+#[derive(Debug, Clone)]
+pub struct FdI32Constraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=i32> {
+    fd: A,
+    i: B,
+}
+pub type VarFdI32Constraint = FdI32Constraint<Var<Fd>, Var<i32>>;
+
+impl<A, B> ToConstraint for FdI32Constraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=i32> {
+    type ConstraintType = VarFdI32Constraint;
+    fn into_constraint(self, state: &mut State) -> VarFdI32Constraint {
+        let fd = state.make_var_of(self.fd);
+        let i = state.make_var_of(self.i);
+        FdI32Constraint { fd: fd, i: i }
+    }
+}
+
+impl<A, B> FdI32Constraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar<VarType=i32> {
+    pub fn new(fd: A, i: B) -> FdI32Constraint<A, B> {
+        FdI32Constraint { fd: fd, i: i }
+    }
+}
+
+impl Constraint for VarFdI32Constraint {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdI32Constraint> {
+        if let Some(&i) = state.get_value(self.i) {
+            state.unify(self.fd, Fd::new_single(i as i64));
+            return if state.ok() { Irrelevant } else { Failed };
+        }
+        let i32_range = Fd::new_range(::std::i32::MIN as i64, ::std::i32::MAX as i64);
+        let new = match state.get_value(self.fd) {
+            Some(fd) => fd.combine(&i32_range),
+            None => { return Unchanged; },
+        };
+        match new.single_value() {
+            Some(x) => { state.unify(self.i, x as i32); },
+            None => { state.unify(self.fd, new); },
+        }
+        if !state.ok() { Failed } else if state.get_value(self.i).is_some() { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.fd.untyped()) || proxy.contains_key(&self.i.untyped())
+    }
+    fn update_vars(&mut self, proxy: &State) {
+        proxy.update_var(self.fd.untyped_mut());
+        proxy.update_var(self.i.untyped_mut());
+    }
+}
+
+///! Constrains an Fd to hold the index of a `FiniteDomain` value, such as an enum with
+///! `#[derive(FiniteDomain)]`, so that the Fd constraints apply to it.  The Fd is kept within the
+///! indexes of the type, so a fresh value starts out as any of them.  Once either is known, so is
+///! the other.
+#[derive(Debug, Clone)]
+pub struct FdEnumConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar, B::VarType: FiniteDomain {
+    fd: A,
+    value: B,
+}
+pub type VarFdEnumConstraint<T> = FdEnumConstraint<Var<Fd>, Var<T>>;
+
+impl<A, B> ToConstraint for FdEnumConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar, B::VarType: FiniteDomain + ToVar<VarType=B::VarType> + Clone {
+    type ConstraintType = VarFdEnumConstraint<B::VarType>;
+    fn into_constraint(self, state: &mut State) -> VarFdEnumConstraint<B::VarType> {
+        let fd = state.make_var_of(self.fd);
+        let value = state.make_var_of(self.value);
+        FdEnumConstraint { fd: fd, value: value }
+    }
+}
+
+impl<A, B> FdEnumConstraint<A, B>
+where A: ToVar<VarType=Fd>, B: ToVar, B::VarType: FiniteDomain {
+    pub fn new(fd: A, value: B) -> FdEnumConstraint<A, B> {
+        FdEnumConstraint { fd: fd, value: value }
+    }
+}
+
+impl<T> Constraint for VarFdEnumConstraint<T> where T: FiniteDomain + ToVar<VarType=T> + VarWrapper + Clone {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<VarFdEnumConstraint<T>> {
+        if let Some(index) = state.get_value(self.value).map(T::to_index) {
+            state.unify(self.fd, Fd::new_single(index));
+            return if state.ok() { Irrelevant } else { Failed };
+        }
+        let (new, changed) = match state.get_value(self.fd) {
+            Some(fd) => {
+                let new = fd.combine(&T::domain());
+                let changed = new != *fd;
+                (new, changed)
+            },
+            None => (T::domain(), true),
+        };
+        if !new.is_valid() { return Failed; }
+        match new.single_value().and_then(T::from_index) {
+            Some(value) => { state.unify(self.value, value); },
+            None => if changed { state.unify(self.fd, new); },
+        }
+        if !state.ok() { Failed } else if state.get_value(self.value).is_some() { Irrelevant } else { Unchanged }
+    }
+    fn relevant(&self, proxy: &VarMap) -> bool {
+        proxy.contains_key(&self.fd.untyped()) || proxy.contains_key(&self.value.untyped())
     }
     fn update_vars(&mut self, proxy: &State) {
         proxy.update_var(self.fd.untyped_mut());
-        proxy.update_var(self.fd.untyped_mut());
+        proxy.update_var(self.value.untyped_mut());
     }
 }
 
@@ -107,6 +107,35 @@ impl Disequal {
         //println!("returning updated disequal {:?}", updated);
         return Updated(updated);
     }
+    ///! With only one pair left, once either side has a single value it can be removed from the
+    ///! other side's possible values, for types like `Fd` that support it.
+    fn exclude_single(&self, proxy: &mut StateProxy) -> ConstraintResult<Disequal> {
+        let (a, b, ty) = self.pairs[0];
+        let (target, newval) = {
+            let (a_id, a_val, _) = proxy.follow_ref(a);
+            let (b_id, b_val, _) = proxy.follow_ref(b);
+            let (a_val, b_val) = match (a_val, b_val) {
+                (Some(a_val), Some(b_val)) => (a_val, b_val),
+                _ => { return Unchanged; },
+            };
+            let (target, newval) = if b_val.value_count() == 1 {
+                (a_id, a_val.value_exclude(b_val))
+            } else if a_val.value_count() == 1 {
+                (b_id, b_val.value_exclude(a_val))
+            } else {
+                return Unchanged;
+            };
+            match newval {
+                Some(newval) => (target, newval),
+                None => { return Unchanged; },
+            }
+        };
+        if newval.value_count() == 0 {
+            return Failed;
+        }
+        proxy.parent.proxy_eqs.insert(target, Exactly(newval, ty));
+        Irrelevant
+    }
 }
 
 impl Constraint for Disequal {
@@ -114,7 +143,17 @@ impl Constraint for Disequal {
         let result = self.perform_test(proxy);
         proxy.parent.proxy_eqs.clear();
         proxy.parent.proxy_eqs.ok = proxy.parent.eqs.ok;
-        result
+        match result {
+            Unchanged if self.pairs.len() == 1 => self.exclude_single(proxy),
+            Updated(updated) => {
+                if updated.pairs.len() != 1 { return Updated(updated); }
+                match updated.exclude_single(proxy) {
+                    Unchanged => Updated(updated),
+                    result => result,
+                }
+            },
+            result => result,
+        }
     }
 
     fn relevant(&self, proxy: &VarMap) -> bool {
@@ -3,6 +3,8 @@ use std::cmp::Ordering::*;
 use std::collections::{BTreeSet, HashMap};
 use std::collections::hash_map::Entry::*;
 use std::rc::Rc;
+use std::any::TypeId;
+use finitedomain::Fd;
 use core::{UntypedVar, State, FollowRef, VarWrapper, Unifier, StateInner};
 use core::ExactVarRef::*;
 use iter::{StateIter, single, TailIter};
@@ -39,12 +41,12 @@ fn value_iter(state: Rc<StateInner>, var: UntypedVar, mut iter: VarWrapperIter)
     })
 }
 
-struct ParentStateIter<'a> {
+pub struct ParentStateIter<'a> {
     state: Option<&'a StateInner>,
 }
 
 impl<'a> ParentStateIter<'a> {
-    fn new(state: &'a State) -> ParentStateIter {
+    pub fn new(state: &'a State) -> ParentStateIter {
         ParentStateIter { state: Some(&**state) }
     }
 }
@@ -183,3 +185,461 @@ fn assign_values_inner(state: State, mut counted: BTreeSet<CountedVar>, mut vars
         assign_values_inner(state, counted, vars)
     })
 }
+
+///! How `assign_values_with` picks the next variable to assign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarSelection {
+    ///! The first unassigned variable, in the order they were provided.
+    InputOrder,
+    ///! The variable with the fewest possible values.  This is what `assign_values` does.
+    FirstFail,
+    ///! The variable with the fewest possible values, breaking ties by the number of constraints
+    ///! on it.
+    MostConstrained,
+    ///! The variable with the largest gap between its two smallest values, breaking ties by the
+    ///! number of possible values.
+    MaxRegret,
+}
+
+///! The order in which `assign_values_with` tries a variable's values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSelection {
+    Ascending,
+    Descending,
+    ///! Start from the median value and work outward, alternating below and above it.  Variables
+    ///! with more than `VALUE_ORDER_LIMIT` values are tried in ascending order instead.
+    MedianSplit,
+    ///! A shuffled order, which is the same for every run with the same seed.  Variables with more
+    ///! than `VALUE_ORDER_LIMIT` values are tried in ascending order instead.
+    Random(u64),
+}
+
+///! Options for `assign_values_with` and `assign_all_values_with`.  `Labeling::new()` behaves like
+///! `assign_values`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Labeling {
+    variables: VarSelection,
+    values: ValueSelection,
+    bisect: bool,
+    backjumping: bool,
+    nogoods: bool,
+}
+
+impl Default for Labeling {
+    fn default() -> Labeling { Labeling::new() }
+}
+
+impl Labeling {
+    pub fn new() -> Labeling {
+        Labeling { variables: VarSelection::FirstFail, values: ValueSelection::Ascending, bisect: false,
+                   backjumping: false, nogoods: false }
+    }
+    pub fn variables(self, variables: VarSelection) -> Labeling {
+        Labeling { variables: variables, .. self }
+    }
+    pub fn values(self, values: ValueSelection) -> Labeling {
+        Labeling { values: values, .. self }
+    }
+    ///! Rather than trying each value, split the variable's values in two and try each half,
+    ///! choosing variables again after each split.  Variables which can't be split are enumerated.
+    ///! With bisection, `Descending` tries the upper half first and `Random` picks a half at random.
+    pub fn bisect(self, bisect: bool) -> Labeling {
+        Labeling { bisect: bisect, .. self }
+    }
+    ///! When a variable runs out of values, work out which earlier assignments caused it and jump
+    ///! straight back to the latest of them, skipping the choices in between.  Only `Fd` variables
+    ///! are assigned this way, one value at a time, so `bisect` doesn't apply to them.
+    pub fn backjumping(self, backjumping: bool) -> Labeling {
+        Labeling { backjumping: backjumping, .. self }
+    }
+    ///! Also remember each set of assignments found to cause a failure, and reject any later branch
+    ///! which makes all of them again.  Implies `backjumping`.
+    pub fn nogoods(self, nogoods: bool) -> Labeling {
+        Labeling { nogoods: nogoods, .. self }
+    }
+}
+
+///! Mix a seed with the variable and its values, so each choice gets its own shuffle.
+pub fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+///! Like `assign_values`, but choosing variables and values as described by `labeling`.
+pub fn assign_values_with<I>(state: State, in_vars: I, labeling: Labeling) -> StateIter
+where I: IntoIterator<Item=UntypedVar> {
+    let vars: Vec<UntypedVar> = in_vars.into_iter().collect();
+    label(state, Rc::new(vars), labeling)
+}
+
+///! Like `assign_all_values`, but choosing variables and values as described by `labeling`.
+///! `VarSelection::InputOrder` assigns variables in the order they were created.
+pub fn assign_all_values_with(state: State, labeling: Labeling) -> StateIter {
+    let mut vars: Vec<UntypedVar> = ParentStateIter::new(&state)
+        .flat_map(|state| state.eqs.iter())
+        .filter(|&&(_, ref val)| val.as_exact().is_some())
+        .map(|&(var, _)| var)
+        .collect();
+    vars.sort();
+    vars.dedup();
+    label(state, Rc::new(vars), labeling)
+}
+
+///! Return the number of constraints which are relevant to `var`.
+fn constraint_count(state: &State, var: UntypedVar) -> usize {
+    use core::VarMap;
+    let map = VarMap { id: var, eqs: vec![(var, EqualTo(var))], ok: true };
+    state.constraints.constraints.iter().filter(|constraint| constraint.relevant(&map)).count()
+}
+
+fn choose_var(state: &State, vars: &[UntypedVar], selection: VarSelection) -> Option<UntypedVar> {
+    let mut candidates = vars.iter().filter_map(|&var| {
+        let var = state.follow_id(var);
+        match state.get_exact_val(var) {
+            Some(val) if val.value_count() > 1 => Some((var, val)),
+            _ => None,
+        }
+    });
+    // Each candidate gets a key, and the smallest wins; earlier variables win ties.
+    match selection {
+        VarSelection::InputOrder => candidates.next().map(|(var, _)| var),
+        VarSelection::FirstFail => candidates.map(|(var, val)| (var, val.value_count()))
+            .fold(None, min_key).map(|(var, _)| var),
+        VarSelection::MostConstrained => candidates.map(|(var, val)| {
+            (var, (val.value_count(), !constraint_count(state, var)))
+        }).fold(None, min_key).map(|(var, _)| var),
+        VarSelection::MaxRegret => candidates.map(|(var, val)| {
+            (var, (!val.value_regret(), val.value_count()))
+        }).fold(None, min_key).map(|(var, _)| var),
+    }
+}
+
+fn min_key<K: Ord>(best: Option<(UntypedVar, K)>, next: (UntypedVar, K)) -> Option<(UntypedVar, K)> {
+    match best {
+        Some(best) if best.1 <= next.1 => Some(best),
+        _ => Some(next),
+    }
+}
+
+///! The most values `ValueSelection::MedianSplit` and `Random` will collect to put in order.
+pub const VALUE_ORDER_LIMIT: usize = 4096;
+
+///! Return the values to try for `var`, in order.
+fn choose_values(var: UntypedVar, val: &VarWrapper, labeling: Labeling) -> VarWrapperIter {
+    if labeling.bisect {
+        if let Some((lower, upper)) = val.value_split() {
+            let upper_first = match labeling.values {
+                ValueSelection::Descending => true,
+                ValueSelection::Random(seed) => splitmix64(seed ^ var.0 as u64 ^ val.value_count() as u64) & 1 == 1,
+                ValueSelection::Ascending | ValueSelection::MedianSplit => false,
+            };
+            return Box::new(if upper_first { vec![upper, lower] } else { vec![lower, upper] }.into_iter());
+        }
+    }
+    let mut values: Vec<Box<VarWrapper>> = match labeling.values {
+        ValueSelection::Ascending => { return val.value_iter(); },
+        ValueSelection::Descending => { return val.value_iter_rev(); },
+        _ if val.value_count() > VALUE_ORDER_LIMIT => { return val.value_iter(); },
+        _ => val.value_iter().collect(),
+    };
+    match labeling.values {
+        ValueSelection::Ascending | ValueSelection::Descending => (),
+        ValueSelection::MedianSplit => {
+            // Take the median, then alternately step below and above it.
+            let mut lower = values;
+            let mut upper = lower.split_off(lower.len() / 2);
+            upper.reverse();
+            values = Vec::with_capacity(lower.len() + upper.len());
+            while let Some(x) = upper.pop() {
+                values.push(x);
+                if let Some(x) = lower.pop() { values.push(x); }
+            }
+        },
+        ValueSelection::Random(seed) => {
+            let mut rng = seed ^ (var.0 as u64).wrapping_mul(0x9e3779b97f4a7c15) ^ values.len() as u64;
+            for i in (1..values.len()).rev() {
+                rng = splitmix64(rng);
+                values.swap(i, (rng % (i as u64 + 1)) as usize);
+            }
+        },
+    }
+    Box::new(values.into_iter())
+}
+
+fn label(state: State, vars: Rc<Vec<UntypedVar>>, labeling: Labeling) -> StateIter {
+    if labeling.backjumping || labeling.nogoods {
+        backjump(state, vars, labeling)
+    } else {
+        label_inner(state, vars, labeling)
+    }
+}
+
+fn label_inner(state: State, vars: Rc<Vec<UntypedVar>>, labeling: Labeling) -> StateIter {
+    use iter::TailIterResult;
+    let var = match choose_var(&state, &vars, labeling.variables) {
+        Some(x) => x,
+        None => { return single(state); },
+    };
+    let values = choose_values(var, state.get_exact_val(var).unwrap(), labeling);
+    TailIterResult(None, Some(value_iter(Rc::new(state.unwrap()), var, values)))
+    .and(move |state| label_inner(state, vars.clone(), labeling))
+}
+
+///! Label the `Fd`s in `vars` with `Backjumper`, then anything else as usual.
+fn backjump(state: State, vars: Rc<Vec<UntypedVar>>, labeling: Labeling) -> StateIter {
+    use iter::TailIterResult;
+    let fd_vars: Vec<UntypedVar> = vars.iter().cloned().filter(|&var| {
+        state.get_exact_val(var).map_or(false, |val| val.get_type_id() == TypeId::of::<Fd>())
+    }).collect();
+    let search = Backjumper {
+        start: Some(state),
+        vars: fd_vars,
+        labeling: labeling,
+        choices: Vec::new(),
+        nogoods: if labeling.nogoods {
+            Some(Nogoods { sets: Vec::new(), by_assignment: HashMap::new() })
+        } else {
+            None
+        },
+    };
+    let rest = Labeling { backjumping: false, nogoods: false, .. labeling };
+    TailIterResult(None, Some(backjump_iter(search)))
+    .and(move |state| label_inner(state, vars.clone(), rest))
+}
+
+fn backjump_iter(mut search: Backjumper) -> TailIter {
+    use iter::{TailIterResult, wrap_fn};
+    wrap_fn(move || match search.next_solution() {
+        Some(state) => TailIterResult(Some(state), Some(backjump_iter(search))),
+        None => TailIterResult(None, None),
+    })
+}
+
+///! Assign a single value to `var`, and return whether the state is still consistent.
+pub fn assign_fd(state: &mut State, var: UntypedVar, value: i64) -> bool {
+    let tid = TypeId::of::<Fd>();
+    let newid = state.eqs.store_value_untyped(Exactly(Box::new(Fd::new_single(value)), tid));
+    state.untyped_unify(newid, var, tid, true);
+    state.ok()
+}
+
+///! A variable being labeled by `Backjumper`, and the state it's being labeled in.
+struct ChoicePoint {
+    state: Rc<StateInner>,
+    var: UntypedVar,
+    value: i64,
+    ///! The values left to try, in order.
+    remaining: Box<Iterator<Item=i64>>,
+    ///! Values which failed as soon as they were assigned.
+    failed: Vec<i64>,
+    ///! The levels of earlier choice points which caused values to fail further down.
+    conflict: BTreeSet<usize>,
+    ///! Whether a solution has been found under this choice point.  Its values didn't all fail
+    ///! then, so when it runs out it can only step back one level.
+    solved: bool,
+}
+
+///! A depth-first search over `Fd`s with conflict-directed backjumping.
+///!
+///! Constraints don't say why they fail, so when a variable runs out of values, the conflict is
+///! found by replaying the earlier assignments.  The shortest prefix of assignments which still
+///! rules out every value is the conflict.  When nogoods are kept, each assignment in it but the
+///! last is also dropped if the rest are enough on their own, which takes longer but finds smaller
+///! nogoods.  The search jumps back to the latest assignment in the conflict, and the others are
+///! passed on to that choice point in case its values run out too.
+struct Backjumper {
+    start: Option<State>,
+    vars: Vec<UntypedVar>,
+    labeling: Labeling,
+    choices: Vec<ChoicePoint>,
+    nogoods: Option<Nogoods>,
+}
+
+///! Sets of assignments which are known to fail, indexed by each assignment in them.
+struct Nogoods {
+    sets: Vec<Vec<(UntypedVar, i64)>>,
+    by_assignment: HashMap<(UntypedVar, i64), Vec<usize>>,
+}
+
+impl Nogoods {
+    fn add(&mut self, nogood: Vec<(UntypedVar, i64)>) {
+        for &x in nogood.iter() {
+            self.by_assignment.entry(x).or_insert_with(Vec::new).push(self.sets.len());
+        }
+        self.sets.push(nogood);
+    }
+}
+
+impl Backjumper {
+    fn next_solution(&mut self) -> Option<State> {
+        if let Some(state) = self.start.take() {
+            let var = match choose_var(&state, &self.vars, self.labeling.variables) {
+                Some(var) => var,
+                None => { return Some(state); },
+            };
+            self.push(Rc::new(state.unwrap()), var);
+        }
+        loop {
+            let value = match self.choices.last_mut() {
+                None => { return None; },
+                Some(choice) => choice.remaining.next(),
+            };
+            let value = match value {
+                Some(value) => value,
+                None => {
+                    self.dead_end();
+                    continue;
+                },
+            };
+            let level = self.choices.len() - 1;
+            self.choices[level].value = value;
+            if let Some(levels) = self.broken_nogood() {
+                let choice = &mut self.choices[level];
+                choice.conflict.extend(levels.into_iter().filter(|&x| x != level));
+                continue;
+            }
+            let (var, mut child) = {
+                let choice = &self.choices[level];
+                (choice.var, State::with_parent(choice.state.clone()))
+            };
+            if !assign_fd(&mut child, var, value) {
+                self.choices[level].failed.push(value);
+                continue;
+            }
+            match choose_var(&child, &self.vars, self.labeling.variables) {
+                Some(next) => { self.push(Rc::new(child.unwrap()), next); },
+                None => {
+                    for choice in self.choices.iter_mut() {
+                        choice.solved = true;
+                    }
+                    return Some(child);
+                },
+            }
+        }
+    }
+
+    fn push(&mut self, state: Rc<StateInner>, var: UntypedVar) {
+        let remaining = {
+            let labeling = Labeling { bisect: false, .. self.labeling };
+            let val = state.get_exact_val(var).unwrap();
+            choose_values(var, val, labeling).map(|x| x.get_wrapped_value::<Fd>().single_value().unwrap())
+        };
+        self.choices.push(ChoicePoint {
+            state: state,
+            var: var,
+            value: 0,
+            remaining: Box::new(remaining),
+            failed: Vec::new(),
+            conflict: BTreeSet::new(),
+            solved: false,
+        });
+    }
+
+    ///! If the latest assignment completes a nogood, return the levels it was made at.  Only nogoods
+    ///! with the latest assignment in them need checking: when one is learned, the search jumps
+    ///! back and changes the latest assignment in it.
+    fn broken_nogood(&self) -> Option<Vec<usize>> {
+        let nogoods = match self.nogoods {
+            Some(ref nogoods) => nogoods,
+            None => { return None; },
+        };
+        let latest = self.choices.last().unwrap();
+        let candidates = match nogoods.by_assignment.get(&(latest.var, latest.value)) {
+            Some(candidates) => candidates,
+            None => { return None; },
+        };
+        let levels: HashMap<(UntypedVar, i64), usize> = self.choices.iter().enumerate()
+            .map(|(level, choice)| ((choice.var, choice.value), level))
+            .collect();
+        candidates.iter()
+            .filter_map(|&i| nogoods.sets[i].iter().map(|x| levels.get(x).cloned()).collect())
+            .next()
+    }
+
+    ///! The last choice point has run out of values.  Find out why, and jump back.
+    fn dead_end(&mut self) {
+        let choice = self.choices.pop().unwrap();
+        let level = self.choices.len();
+        let conflict = if choice.solved {
+            (0..level).collect()
+        } else {
+            let mut conflict = self.explain(&choice);
+            conflict.extend(choice.conflict.iter().cloned());
+            conflict
+        };
+        if !choice.solved {
+            if let Some(ref mut nogoods) = self.nogoods {
+                let choices = &self.choices;
+                nogoods.add(conflict.iter().map(|&x| (choices[x].var, choices[x].value)).collect());
+            }
+        }
+        match conflict.iter().next_back() {
+            Some(&latest) => {
+                self.choices.truncate(latest + 1);
+                let choice = &mut self.choices[latest];
+                choice.conflict.extend(conflict.iter().cloned().filter(|&x| x != latest));
+            },
+            None => { self.choices.clear(); },
+        }
+    }
+
+    ///! Return the levels of a set of earlier assignments which leave no value for `choice.var`,
+    ///! apart from the ones whose failure further down is already in `choice.conflict`.
+    fn explain(&self, choice: &ChoicePoint) -> BTreeSet<usize> {
+        let level = self.choices.len();
+        // Each choice point's state already has every earlier assignment, so prefixes are cheap.
+        let prefix_state = |len: usize| -> Rc<StateInner> {
+            if len == level { choice.state.clone() } else { self.choices[len].state.clone() }
+        };
+        // The values to rule out: those removed before this choice point, and those which failed
+        // when they were assigned.  Any that are still possible in a state get tried, unless there
+        // are too many, in which case the state doesn't count as ruling them out.
+        let excluded = {
+            let start = prefix_state(0);
+            let start = start.get_exact_val_opt::<Fd>(choice.var).unwrap();
+            let here = choice.state.get_exact_val_opt::<Fd>(choice.var).unwrap();
+            let mut failed = choice.failed.clone();
+            failed.sort();
+            start.difference(here).union(&Fd::new_values(failed))
+        };
+        let rules_out = |state: Rc<StateInner>| -> bool {
+            if !state.ok() { return true; }
+            let possible = state.get_exact_val_opt::<Fd>(choice.var).unwrap().combine(&excluded);
+            possible.value_count() <= 64 && possible.iter().all(|value| {
+                !assign_fd(&mut State::with_parent(state.clone()), choice.var, value)
+            })
+        };
+
+        if !rules_out(prefix_state(level)) {
+            return (0..level).collect();
+        }
+        let (mut lo, mut hi) = (0, level);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if rules_out(prefix_state(mid)) { hi = mid; } else { lo = mid + 1; }
+        }
+        if lo == 0 {
+            return BTreeSet::new();
+        }
+
+        // The assignment at lo - 1 is needed.  Nogoods are only worth keeping if they're small, so
+        // when they're being kept, try dropping each of the ones before it, latest first.
+        // Everything before the dropped one is still in, so start from the state which has them.
+        let mut conflict: BTreeSet<usize> = (0..lo).collect();
+        if self.nogoods.is_none() {
+            return conflict;
+        }
+        for dropped in (0..lo - 1).rev() {
+            let mut state = State::with_parent(self.choices[dropped].state.clone());
+            let consistent = conflict.iter().filter(|&&x| x > dropped).all(|&x| {
+                assign_fd(&mut state, self.choices[x].var, self.choices[x].value)
+            });
+            if !consistent || rules_out(Rc::new(state.unwrap())) {
+                conflict.remove(&dropped);
+            }
+        }
+        conflict
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::any::TypeId;
+use finitedomain::{Fd, span};
+use core::{State, StateInner, StateProxy, UntypedVar, Var, VarMap, VarWrapper, FollowRef, Unifier};
+use core::{BoxedConstraint, ConstraintStore, ConstraintResult, ExactVal};
+use core::ExactVarRef::*;
+use core::get_values::{ParentStateIter, splitmix64, assign_fd};
+
+///! Values for a set of `Fd` variables, as passed to `Constraint::violations` and returned by
+///! `local_search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    values: HashMap<UntypedVar, i64>,
+}
+
+impl Assignment {
+    ///! Return the value of `var`, if it has one.
+    pub fn get(&self, var: Var<Fd>) -> Option<i64> {
+        self.values.get(&var.untyped()).cloned()
+    }
+}
+
+///! Options for `local_search`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalSearch {
+    seed: u64,
+    steps: usize,
+    tabu: usize,
+    restart: Option<usize>,
+}
+
+impl LocalSearch {
+    ///! Search for up to 10000 steps, never restarting.  Searches with the same seed make the same
+    ///! moves.
+    pub fn new(seed: u64) -> LocalSearch {
+        LocalSearch { seed: seed, steps: 10000, tabu: 10, restart: None }
+    }
+    ///! Give up after this many steps.
+    pub fn steps(self, steps: usize) -> LocalSearch {
+        LocalSearch { steps: steps, .. self }
+    }
+    ///! Don't move a variable back to a value it left within this many steps, unless that would
+    ///! give the best assignment yet.  With 0, this is plain min-conflicts.
+    pub fn tabu(self, tabu: usize) -> LocalSearch {
+        LocalSearch { tabu: tabu, .. self }
+    }
+    ///! Start again from random values after this many steps without a better assignment.
+    pub fn restart_after(self, steps: usize) -> LocalSearch {
+        LocalSearch { restart: Some(steps), .. self }
+    }
+}
+
+///! The best assignment found by `local_search`.
+#[derive(Debug)]
+pub struct LocalSearchResult {
+    assignment: Assignment,
+    violations: usize,
+    steps: usize,
+    state: Option<State>,
+}
+
+impl LocalSearchResult {
+    pub fn assignment(&self) -> &Assignment {
+        &self.assignment
+    }
+    ///! Return the total violations of every constraint with the best assignment, which is 0 for a
+    ///! solution.
+    pub fn violations(&self) -> usize {
+        self.violations
+    }
+    ///! Return the number of steps taken.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+    ///! Return the state with every `Fd` assigned, if the assignment is a solution.
+    pub fn into_state(self) -> Option<State> {
+        self.state
+    }
+}
+
+///! Assign every `Fd` in `state` a value from its domain, then repeatedly pick a variable from a
+///! violated constraint and give it the value which leaves the fewest violations, until every
+///! constraint is satisfied or the step limit runs out.  Unlike `assign_all_values`, this can't
+///! prove there's no solution, but it can find one in models too large to search completely.
+///!
+///! A state which has already failed has no assignment, and `usize::MAX` violations.
+pub fn local_search(state: State, options: LocalSearch) -> LocalSearchResult {
+    if !state.ok() {
+        let assignment = Assignment { values: HashMap::new() };
+        return LocalSearchResult { assignment: assignment, violations: ::std::usize::MAX, steps: 0, state: None };
+    }
+    let mut search = Search::new(state, options.seed);
+    search.randomize();
+    let mut best = (search.total, search.values.clone());
+    let mut tabu = HashMap::new();
+    let mut stalled = 0;
+    let mut steps = 0;
+    while best.0 > 0 && steps < options.steps {
+        steps += 1;
+        if !search.step(steps, options.tabu, best.0, &mut tabu) {
+            break;
+        }
+        if search.total < best.0 {
+            best = (search.total, search.values.clone());
+            stalled = 0;
+        } else {
+            stalled += 1;
+            if Some(stalled) == options.restart {
+                search.randomize();
+                tabu.clear();
+                stalled = 0;
+                if search.total < best.0 {
+                    best = (search.total, search.values.clone());
+                }
+            }
+        }
+    }
+    let state = if best.0 == 0 { search.solution(&best.1) } else { None };
+    LocalSearchResult { assignment: best.1, violations: best.0, steps: steps, state: state }
+}
+
+///! Try at most this many values for a variable in each step, picked at random from larger domains.
+const MAX_CANDIDATES: usize = 64;
+
+struct Search {
+    root: Rc<StateInner>,
+    vars: Vec<UntypedVar>,
+    domains: Vec<Fd>,
+    constraints: Vec<Box<BoxedConstraint>>,
+    ///! The variables with more than one value in each constraint, and the constraints on each.
+    constraint_vars: Vec<Vec<usize>>,
+    var_constraints: Vec<Vec<usize>>,
+    values: Assignment,
+    violations: Vec<usize>,
+    total: usize,
+    rng: u64,
+}
+
+impl Search {
+    fn new(state: State, seed: u64) -> Search {
+        let mut vars: Vec<UntypedVar> = ParentStateIter::new(&state)
+            .flat_map(|state| state.eqs.iter())
+            .filter(|&&(_, ref val)| val.as_exact().is_some())
+            .map(|&(var, _)| state.follow_id(var))
+            .filter(|&var| state.get_exact_val(var).map_or(false, |val| val.get_type_id() == TypeId::of::<Fd>()))
+            .collect();
+        vars.sort();
+        vars.dedup();
+        let domains: Vec<Fd> = vars.iter().map(|&var| state.get_exact_val_opt::<Fd>(var).unwrap().clone()).collect();
+
+        // Constraints refer to variables as they were when they last ran, so bring them up to date.
+        let root = Rc::new(state.unwrap());
+        let constraints: Vec<Box<BoxedConstraint>> = root.constraints.constraints.iter().map(|constraint| {
+            let mut constraint = constraint.clone_boxed();
+            constraint.update_vars(&root);
+            constraint
+        }).collect();
+        let mut constraint_vars = vec![Vec::new(); constraints.len()];
+        let mut var_constraints = vec![Vec::new(); vars.len()];
+        // Only variables which can change are listed; the others already have their values in
+        // `root`.
+        for (i, &var) in vars.iter().enumerate() {
+            if domains[i].value_count() == 1 { continue; }
+            let map = VarMap { id: var, eqs: vec![(var, EqualTo(var))], ok: true };
+            for (c, constraint) in constraints.iter().enumerate() {
+                if constraint.relevant(&map) {
+                    constraint_vars[c].push(i);
+                    var_constraints[i].push(c);
+                }
+            }
+        }
+        let violations = vec![0; constraints.len()];
+        Search {
+            root: root,
+            vars: vars,
+            domains: domains,
+            constraints: constraints,
+            constraint_vars: constraint_vars,
+            var_constraints: var_constraints,
+            values: Assignment { values: HashMap::new() },
+            violations: violations,
+            total: 0,
+            rng: seed,
+        }
+    }
+
+    fn random(&mut self, n: usize) -> usize {
+        self.rng = splitmix64(self.rng);
+        (self.rng % n as u64) as usize
+    }
+
+    fn random_value(&mut self, var: usize) -> i64 {
+        // Count the values as a u64, where the full range of i64 wraps around to 0.
+        let intervals = self.domains[var].intervals();
+        let count = intervals.iter().fold(0u64, |sum, &(lo, hi)| sum.wrapping_add(span(lo, hi)).wrapping_add(1));
+        self.rng = splitmix64(self.rng);
+        let mut n = if count == 0 { self.rng } else { self.rng % count };
+        for &(lo, hi) in intervals.iter() {
+            if n <= span(lo, hi) { return lo.wrapping_add(n as i64); }
+            n -= span(lo, hi) + 1;
+        }
+        unreachable!();
+    }
+
+    ///! Give every variable a random value, and count the violations.
+    fn randomize(&mut self) {
+        for var in 0..self.vars.len() {
+            let value = self.random_value(var);
+            self.values.values.insert(self.vars[var], value);
+        }
+        for c in 0..self.constraints.len() {
+            self.violations[c] = self.evaluate(c);
+        }
+        self.total = self.violations.iter().fold(0, |total, &x| total.saturating_add(x));
+    }
+
+    fn evaluate(&self, c: usize) -> usize {
+        match self.constraints[c].violations(&self.values) {
+            Some(violations) => violations,
+            None => if self.fails(c) { 1 } else { 0 },
+        }
+    }
+
+    ///! Return whether constraint `c` fails when its variables have their current values.  The
+    ///! constraint runs alone, in a state with no others.
+    fn fails(&self, c: usize) -> bool {
+        let mut state = StateInner {
+            eqs: VarMap::with_parent(&self.root.eqs),
+            parent: Some(self.root.clone()),
+            constraints: ConstraintStore::new(),
+            proxy_eqs: VarMap::new(),
+        };
+        for &var in self.constraint_vars[c].iter() {
+            let value = self.values.values[&self.vars[var]];
+            state.eqs.insert(self.vars[var], ExactVal::new(Fd::new_single(value)).as_var_ref());
+        }
+        let mut proxy = StateProxy::new(&mut state);
+        match self.constraints[c].update(&mut proxy) {
+            ConstraintResult::Failed => true,
+            _ => false,
+        }
+    }
+
+    ///! Pick a violated constraint, and move one of its variables to the value which leaves the
+    ///! fewest violations.  Returns false if there's nothing which can be moved.
+    fn step(&mut self, step: usize, tenure: usize, best: usize, tabu: &mut HashMap<(usize, i64), usize>) -> bool {
+        let violated: Vec<usize> = (0..self.constraints.len())
+            .filter(|&c| self.violations[c] > 0 && !self.constraint_vars[c].is_empty())
+            .collect();
+        if violated.is_empty() {
+            return false;
+        }
+        let c = violated[self.random(violated.len())];
+        let i = self.random(self.constraint_vars[c].len());
+        let var = self.constraint_vars[c][i];
+        let current = self.values.values[&self.vars[var]];
+        let count = self.domains[var].value_count();
+        let candidates: Vec<i64> = if count <= MAX_CANDIDATES {
+            self.domains[var].iter().collect()
+        } else {
+            (0..MAX_CANDIDATES).map(|_| self.random_value(var)).chain(Some(current)).collect()
+        };
+
+        // Pick the value with the fewest violations, breaking ties at random.  Staying put counts.
+        let mut chosen: Option<(usize, i64, Vec<usize>)> = None;
+        let mut ties = 0;
+        for &value in candidates.iter() {
+            self.values.values.insert(self.vars[var], value);
+            let violations: Vec<usize> = self.var_constraints[var].iter().map(|&c| self.evaluate(c)).collect();
+            let total = self.var_constraints[var].iter().zip(violations.iter())
+                .fold(self.total, |total, (&c, &x)| total.saturating_sub(self.violations[c]).saturating_add(x));
+            if value != current && tabu.get(&(var, value)).map_or(false, |&until| until > step) && total >= best {
+                continue;
+            }
+            let better = match chosen {
+                None => true,
+                Some((chosen_total, _, _)) => total < chosen_total,
+            };
+            if better {
+                ties = 1;
+                chosen = Some((total, value, violations));
+            } else if chosen.as_ref().map(|x| x.0) == Some(total) {
+                ties += 1;
+                if self.random(ties) == 0 { chosen = Some((total, value, violations)); }
+            }
+        }
+
+        match chosen.and_then(|x| if x.1 != current { Some(x) } else { None }) {
+            Some((total, value, violations)) => {
+                self.values.values.insert(self.vars[var], value);
+                for (&c, x) in self.var_constraints[var].iter().zip(violations) {
+                    self.violations[c] = x;
+                }
+                self.total = total;
+                tabu.insert((var, current), step + tenure);
+            },
+            None => { self.values.values.insert(self.vars[var], current); },
+        }
+        true
+    }
+
+    ///! Return a state with every variable assigned from `values`, if that's consistent.
+    fn solution(&self, values: &Assignment) -> Option<State> {
+        let mut state = State::with_parent(self.root.clone());
+        for (&var, domain) in self.vars.iter().zip(self.domains.iter()) {
+            if domain.value_count() > 1 && !assign_fd(&mut state, var, values.values[&var]) {
+                return None;
+            }
+        }
+        Some(state)
+    }
+}
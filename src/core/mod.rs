@@ -7,8 +7,11 @@ pub mod disequal;
 ///! variables.
 pub mod reify;
 mod get_values;
+mod local_search;
 
-pub use core::get_values::{assign_values, assign_all_values};
+pub use core::get_values::{assign_values, assign_all_values, assign_values_with, assign_all_values_with};
+pub use core::get_values::{Labeling, VarSelection, ValueSelection, VALUE_ORDER_LIMIT};
+pub use core::local_search::{local_search, LocalSearch, LocalSearchResult, Assignment};
 
 use std::rc::Rc;
 use std::fmt::{self, Debug, Formatter};
@@ -129,6 +132,11 @@ pub trait Constraint: Debug + Sized {
     ///! (Optional) Called to determine whether `update_vars()` needs to be called.  Should call
     ///! `varmap.need_update()` for each variable in the constraint.
     fn need_update(&self, vars: &VarMap) -> bool { self.relevant(vars) }
+    ///! (Optional) Return how far the constraint is from being satisfied when its variables have
+    ///! the values in `values`, or 0 if it is.  This is used by `local_search` to decide which
+    ///! values to change.  Without it, the constraint counts as a single violation whenever
+    ///! `update()` fails with those values.
+    fn violations(&self, _: &Assignment) -> Option<usize> { None }
 }
 
 ///! Trait for creating a `Constraint`, given a `StateInner`.
@@ -142,6 +150,7 @@ trait BoxedConstraint: Debug {
     fn relevant(&self, _: &VarMap) -> bool;
     fn update_vars(&mut self, _: &StateInner);
     fn need_update(&self, vars: &VarMap) -> bool;
+    fn violations(&self, values: &Assignment) -> Option<usize>;
     fn clone_boxed(&self) -> Box<BoxedConstraint>;
 }
 
@@ -162,6 +171,7 @@ impl<A> BoxedConstraint for ConstraintWrapper<A> where A : Constraint + Clone +
     fn relevant(&self, vars: &VarMap) -> bool { self.0.relevant(vars) }
     fn update_vars(&mut self, vars: &StateInner) { self.0.update_vars(vars) }
     fn need_update(&self, vars: &VarMap) -> bool { self.0.need_update(vars) }
+    fn violations(&self, values: &Assignment) -> Option<usize> { self.0.violations(values) }
     fn clone_boxed(&self) -> Box<BoxedConstraint> {
         Box::new(ConstraintWrapper(self.0.clone()))
     }
@@ -249,6 +259,23 @@ pub trait VarWrapper : Debug + 'static + Any {
     ///! (Optional) Return an iterator over the values this variable can take.  This shouldn't be called if
     ///! `value_count()` returns 1.
     fn value_iter(&self) -> Box<Iterator<Item=Box<VarWrapper>>> { panic!() }
+    ///! (Optional) Return the values from `value_iter` in reverse.  This is used by
+    ///! `ValueSelection::Descending`; by default, the values are collected and reversed.
+    fn value_iter_rev(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
+        let mut values: Vec<Box<VarWrapper>> = self.value_iter().collect();
+        values.reverse();
+        Box::new(values.into_iter())
+    }
+    ///! (Optional) Split the possible values into a lower and an upper half, each holding at least
+    ///! one value.  This is used by `Labeling::bisect()`; without it, values are tried one by one.
+    fn value_split(&self) -> Option<(Box<VarWrapper>, Box<VarWrapper>)> { None }
+    ///! (Optional) Return the distance between the two smallest possible values.  This is used by
+    ///! `VarSelection::MaxRegret`.
+    fn value_regret(&self) -> u64 { 0 }
+    ///! (Optional) Return the possible values without the single value held by `other`.  This is
+    ///! used by `Disequal` to narrow a variable once the other side is known; types that don't
+    ///! use overwrite can't be narrowed, and should return `None`.
+    fn value_exclude(&self, _: &VarWrapper) -> Option<Box<VarWrapper>> { None }
     ///! (Optional) Must be overridden to return `true` if `unify_with()` ever returns `Overwrite` -- this
     ///! disables an optimization that's incorrect in such a case.
     fn uses_overwrite(&self) -> bool { false }
@@ -0,0 +1,137 @@
+use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::slice;
+use core::{VarWrapper, StateProxy, ToVar, VarStore, Var, UnifyResult};
+
+///! Represents a finite domain over any ordered type, such as an enum.  Like `Fd`, unifying two
+///! domains narrows them to their common values, and `assign_all_values` will try each value in
+///! turn.
+///!
+///! The values are kept in a sorted list without duplicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain<T> where T: Ord + Clone {
+    values: Vec<T>,
+}
+
+impl<T> ToVar for Domain<T> where T: Ord + Clone + Debug + 'static {
+    type VarType = Domain<T>;
+    fn into_var<U: VarStore>(self, state: &mut U) -> Var<Domain<T>> {
+        state.store_value(self)
+    }
+}
+
+impl<T> Domain<T> where T: Ord + Clone {
+    ///! Create a domain from a list of values, in any order.
+    pub fn new(mut values: Vec<T>) -> Domain<T> {
+        values.sort();
+        values.dedup();
+        Domain { values: values }
+    }
+
+    pub fn new_single(value: T) -> Domain<T> {
+        Domain { values: vec![value] }
+    }
+
+    ///! Create an empty domain, which can't be unified with anything.
+    pub fn err() -> Domain<T> {
+        Domain { values: Vec::new() }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.values.is_empty()
+    }
+
+    ///! Return the number of values in the domain.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.values.binary_search(value).is_ok()
+    }
+
+    pub fn single_value(&self) -> Option<&T> {
+        if self.values.len() == 1 { self.values.first() } else { None }
+    }
+
+    ///! Iterate over the values in ascending order.
+    pub fn iter(&self) -> slice::Iter<T> {
+        self.values.iter()
+    }
+
+    ///! Return the values in both domains.
+    pub fn combine(&self, other: &Domain<T>) -> Domain<T> {
+        self.merge(other, false, false, true)
+    }
+
+    ///! Return the values in either domain.
+    pub fn union(&self, other: &Domain<T>) -> Domain<T> {
+        self.merge(other, true, true, true)
+    }
+
+    ///! Return the values in this domain but not in `other`.
+    pub fn difference(&self, other: &Domain<T>) -> Domain<T> {
+        self.merge(other, true, false, false)
+    }
+
+    ///! Walk both sorted lists together, keeping the values found only in `self`, only in `other`,
+    ///! or in both, as requested.
+    fn merge(&self, other: &Domain<T>, self_only: bool, other_only: bool, both: bool) -> Domain<T> {
+        let mut values = Vec::new();
+        let (mut a, mut b) = (self.values.iter().peekable(), other.values.iter().peekable());
+        loop {
+            let order = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => x.cmp(y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            match order {
+                Ordering::Less => {
+                    let x = a.next().unwrap();
+                    if self_only { values.push(x.clone()); }
+                },
+                Ordering::Greater => {
+                    let y = b.next().unwrap();
+                    if other_only { values.push(y.clone()); }
+                },
+                Ordering::Equal => {
+                    let x = a.next().unwrap();
+                    b.next();
+                    if both { values.push(x.clone()); }
+                },
+            }
+        }
+        Domain { values: values }
+    }
+}
+
+impl<T> VarWrapper for Domain<T> where T: Ord + Clone + Debug + 'static {
+    fn unify_with(&self, other: &VarWrapper, _: &mut StateProxy) -> UnifyResult {
+        let other = other.get_wrapped_value::<Domain<T>>();
+        let result = self.combine(other);
+        if !result.is_valid() { return false.into(); }
+
+        unsafe { return UnifyResult::overwrite(result); }
+    }
+    fn value_count(&self) -> usize {
+        self.values.len()
+    }
+    fn value_iter(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
+        Box::new(self.values.clone().into_iter().map(|val| Box::new(Domain::new_single(val)) as Box<VarWrapper>))
+    }
+    fn value_iter_rev(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
+        Box::new(self.values.clone().into_iter().rev().map(|val| Box::new(Domain::new_single(val)) as Box<VarWrapper>))
+    }
+    fn value_split(&self) -> Option<(Box<VarWrapper>, Box<VarWrapper>)> {
+        if self.values.len() < 2 { return None; }
+        let (lower, upper) = self.values.split_at(self.values.len() / 2);
+        Some((Box::new(Domain { values: lower.to_vec() }), Box::new(Domain { values: upper.to_vec() })))
+    }
+    fn value_exclude(&self, other: &VarWrapper) -> Option<Box<VarWrapper>> {
+        let other = other.get_wrapped_value::<Domain<T>>();
+        let result = self.difference(other);
+        if result == *self { None } else { Some(Box::new(result)) }
+    }
+    fn uses_overwrite(&self) -> bool { true }
+}
@@ -1,5 +1,5 @@
-use finitedomain::Fd::*;
 use std::collections::HashSet;
+use std::cmp::{min, max};
 use core::{VarWrapper, StateProxy, Var, ToVar, VarStore, VarRetrieve, State, Unifier, UnifyResult, StateInner};
 use iter::{StateIter, single};
 use std::rc::Rc;
@@ -7,223 +7,502 @@ use iter::{TailIter, TailIterResult};
 
 ///! Represents a finite-domain value.  By storing a set of possible values from that domain, these
 ///! can be more performant than backtracking for each value.
-#[derive(Debug, Clone)]
-pub enum Fd {
-    Values(Vec<usize>),
-    Single(usize),
+///!
+///! The values are signed integers.  Domains spanning fewer than 64 values are stored as a single
+///! bitset word, so the common small cases (sudoku, n-queens) never allocate; larger ones are
+///! stored as a sorted list of disjoint, non-adjacent inclusive intervals, so a domain like
+///! `0..1_000_000` costs no more than a single value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fd {
+    repr: FdRepr,
 }
 
+///! The two representations are kept canonical, so derived equality works: a domain is stored as
+///! `Bits` exactly when it's nonempty and spans at most `SMALL_DOMAIN_SPAN` values, and then bit 0
+///! of the word is always set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FdRepr {
+    Bits(i64, u64),
+    Intervals(Vec<(i64, i64)>),
+}
+
+use self::FdRepr::*;
+
+const SMALL_DOMAIN_SPAN: u64 = 64;
+
 impl ToVar for Fd {
     default_tovar_impl!(Fd);
 }
 
-impl PartialEq for Fd {
-    fn eq(&self, other: &Fd) -> bool {
-        if let (Some(l), Some(r)) = (self.single_value(), other.single_value()) {
-            l == r
-        } else if let (&Values(ref l), &Values(ref r)) = (self, other) {
-            l == r
-        } else {
-            false
+///! Append the interval `(lo, hi)` to a sorted list of intervals, merging it with the last one if
+///! they overlap or touch.
+fn push_range(ranges: &mut Vec<(i64, i64)>, lo: i64, hi: i64) {
+    if let Some(last) = ranges.last_mut() {
+        if lo <= last.1.saturating_add(1) {
+            last.1 = max(last.1, hi);
+            return;
         }
     }
+    ranges.push((lo, hi));
 }
 
-fn combine_single(values: &[usize], value: usize) -> Fd {
-    match values.binary_search(&value) {
-        Ok(..) => Fd::Single(value),
-        Err(..) => Fd::err(),
-    }
+///! Return the distance from `lo` up to `hi`, which must be no less than `lo`.
+pub fn span(lo: i64, hi: i64) -> u64 {
+    hi.wrapping_sub(lo) as u64
+}
+
+///! Return the bits covering the values `lo..hi` of a word starting at `base`.
+fn range_mask(base: i64, lo: i64, hi: i64) -> u64 {
+    let top = base.saturating_add(SMALL_DOMAIN_SPAN as i64 - 1);
+    let (lo, hi) = (max(lo, base), min(hi, top));
+    if lo > hi { return 0; }
+    let len = span(lo, hi) + 1;
+    let bits = if len == SMALL_DOMAIN_SPAN { !0 } else { (1 << len) - 1 };
+    bits << span(base, lo)
 }
 
 impl Fd {
-    pub fn new_values(values: Vec<usize>) -> Fd {
-        {
-            values.iter().fold(None, |a, b| {
-                if let Some(a) = a { assert!(a < b); }
-                Some(b)
-            });
+    fn from_bits(base: i64, word: u64) -> Fd {
+        if word == 0 { return Fd::err(); }
+        let shift = word.trailing_zeros();
+        Fd { repr: Bits(base + shift as i64, word >> shift) }
+    }
+
+    fn from_ranges(ranges: Vec<(i64, i64)>) -> Fd {
+        let small = match (ranges.first(), ranges.last()) {
+            (Some(first), Some(last)) => span(first.0, last.1) < SMALL_DOMAIN_SPAN,
+            _ => false,
+        };
+        if !small {
+            return Fd { repr: Intervals(ranges) };
         }
-        Fd::Values(values)
+        let base = ranges[0].0;
+        let word = ranges.iter().fold(0, |word, &(lo, hi)| word | range_mask(base, lo, hi));
+        Fd { repr: Bits(base, word) }
     }
 
-    pub fn new_single(value: usize) -> Fd {
-        Fd::Single(value)
+    ///! Return the bits of this domain which fall in a word starting at `base`.
+    fn mask_at(&self, base: i64) -> u64 {
+        match self.repr {
+            Bits(b, word) => {
+                if b >= base {
+                    let shift = span(base, b);
+                    if shift >= SMALL_DOMAIN_SPAN { 0 } else { word << shift }
+                } else {
+                    let shift = span(b, base);
+                    if shift >= SMALL_DOMAIN_SPAN { 0 } else { word >> shift }
+                }
+            },
+            Intervals(ref ranges) => {
+                ranges.iter().fold(0, |word, &(lo, hi)| word | range_mask(base, lo, hi))
+            },
+        }
     }
 
+    ///! Create a domain from a list of values, which must be in ascending order.
+    pub fn new_values(values: Vec<i64>) -> Fd {
+        let mut ranges = Vec::new();
+        values.iter().fold(None, |a, &b| {
+            if let Some(a) = a { assert!(a < b); }
+            push_range(&mut ranges, b, b);
+            Some(b)
+        });
+        Fd::from_ranges(ranges)
+    }
+
+    pub fn new_single(value: i64) -> Fd {
+        Fd { repr: Bits(value, 1) }
+    }
+
+    ///! Create a domain containing every value from `min` to `max`, inclusive.
+    pub fn new_range(min: i64, max: i64) -> Fd {
+        if min > max {
+            Fd::err()
+        } else if span(min, max) < SMALL_DOMAIN_SPAN {
+            Fd { repr: Bits(min, range_mask(min, min, max)) }
+        } else {
+            Fd { repr: Intervals(vec![(min, max)]) }
+        }
+    }
+
+    ///! Create a domain from a list of inclusive intervals, in any order.  Empty intervals are
+    ///! ignored, and overlapping ones are merged.
+    pub fn new_intervals(mut intervals: Vec<(i64, i64)>) -> Fd {
+        intervals.retain(|&(lo, hi)| lo <= hi);
+        intervals.sort();
+        let mut ranges = Vec::with_capacity(intervals.len());
+        for (lo, hi) in intervals {
+            push_range(&mut ranges, lo, hi);
+        }
+        Fd::from_ranges(ranges)
+    }
+
+    ///! Create an empty domain, which can't be unified with anything.
     pub fn err() -> Fd {
-        Fd::Values(Vec::new())
+        Fd { repr: Intervals(Vec::new()) }
     }
 
     pub fn is_valid(&self) -> bool {
-        match *self {
-            Fd::Values(ref values) => !values.is_empty(),
-            Fd::Single(..) => true,
+        match self.repr {
+            Bits(..) => true,
+            Intervals(ref ranges) => !ranges.is_empty(),
         }
     }
 
-    pub fn combine(&self, other: &Fd) -> Fd {
-        //println!("combining {:?} and {:?}", self, other);
-        let result = match (self, other) {
-            (&Single(lval), &Single(rval)) => match lval == rval {
-                true => Single(lval),
-                false => Values(Vec::new()),
-            },
-            (&Single(lval), &Values(ref rvals)) => combine_single(rvals, lval),
-            (&Values(ref rvals), &Single(lval)) => combine_single(rvals, lval),
-            (&Values(ref lvals), &Values(ref rvals)) => {
-                let combined = RangeMerge::new(lvals.iter().map(|x| *x), rvals.iter().map(|x| *x));
-                Values(combined.collect())
+    ///! Return the sorted, disjoint, inclusive intervals making up this domain.
+    pub fn intervals(&self) -> Vec<(i64, i64)> {
+        match self.repr {
+            Bits(base, word) => {
+                let mut ranges = Vec::new();
+                let mut offset = 0;
+                let mut word = word;
+                while word != 0 {
+                    let skip = word.trailing_zeros();
+                    word >>= skip;
+                    offset += skip;
+                    let len = (!word).trailing_zeros();
+                    let lo = base + offset as i64;
+                    ranges.push((lo, lo + len as i64 - 1));
+                    word = if len == 64 { 0 } else { word >> len };
+                    offset += len;
+                }
+                ranges
             },
-        };
-        //println!("got {:?}", result);
-        result
+            Intervals(ref ranges) => ranges.clone(),
+        }
     }
-    pub fn single_value(&self) -> Option<usize> {
-        match *self {
-            Fd::Values(ref values) if values.len() == 1 => Some(values[0]),
-            Fd::Single(x) => Some(x),
-            _ => None,
+
+    ///! Return the intersection of two domains.
+    pub fn combine(&self, other: &Fd) -> Fd {
+        let (a, b) = match (&self.repr, &other.repr) {
+            (&Bits(base, word), _) => { return Fd::from_bits(base, word & other.mask_at(base)); },
+            (_, &Bits(base, word)) => { return Fd::from_bits(base, word & self.mask_at(base)); },
+            (&Intervals(ref a), &Intervals(ref b)) => (a, b),
+        };
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            let lo = max(a[i].0, b[j].0);
+            let hi = min(a[i].1, b[j].1);
+            if lo <= hi {
+                ranges.push((lo, hi));
+            }
+            if a[i].1 < b[j].1 { i += 1; } else { j += 1; }
         }
+        Fd::from_ranges(ranges)
     }
-    pub fn in_range(&self, val: usize) -> bool {
-        match *self {
-            Fd::Values(ref values) => { values.binary_search(&val).is_ok() },
-            Fd::Single(x) => x == val,
+
+    ///! Return the union of two domains.
+    pub fn union(&self, other: &Fd) -> Fd {
+        if let (Some((amin, amax)), Some((bmin, bmax))) = (self.bounds(), other.bounds()) {
+            let base = min(amin, bmin);
+            if span(base, max(amax, bmax)) < SMALL_DOMAIN_SPAN {
+                return Fd::from_bits(base, self.mask_at(base) | other.mask_at(base));
+            }
+        }
+        let (a, b) = (self.intervals(), other.intervals());
+        let mut ranges = Vec::with_capacity(a.len() + b.len());
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            let next = if j == b.len() || (i < a.len() && a[i].0 <= b[j].0) {
+                i += 1;
+                a[i - 1]
+            } else {
+                j += 1;
+                b[j - 1]
+            };
+            push_range(&mut ranges, next.0, next.1);
         }
+        Fd::from_ranges(ranges)
     }
 
-    pub fn remove_values(&mut self, val: &HashSet<usize>) {
-        match *self {
-            Fd::Values(ref mut values) => {
-                //println!("removing from {:?}", values);
-                values.retain(|x| !val.contains(x));
-            },
-            Fd::Single(x) => {
-                if val.contains(&x) {
-                    *self = Fd::err();
-                }
+    ///! Return every `i64` not in this domain.
+    pub fn complement(&self) -> Fd {
+        let intervals = self.intervals();
+        let mut ranges = Vec::with_capacity(intervals.len() + 1);
+        let mut start = ::std::i64::MIN;
+        for (lo, hi) in intervals {
+            if lo > start {
+                ranges.push((start, lo - 1));
+            }
+            if hi == ::std::i64::MAX {
+                return Fd::from_ranges(ranges);
             }
+            start = hi + 1;
         }
+        ranges.push((start, ::std::i64::MAX));
+        Fd::from_ranges(ranges)
     }
-}
 
+    ///! Return the negation of every value in this domain.  `i64::MIN` has no negation, so it
+    ///! becomes `i64::MAX`.
+    pub fn negate(&self) -> Fd {
+        let intervals = self.intervals();
+        let ranges = intervals.into_iter().rev()
+            .map(|(lo, hi)| (hi.checked_neg().unwrap_or(::std::i64::MAX), lo.checked_neg().unwrap_or(::std::i64::MAX)))
+            .collect();
+        Fd::new_intervals(ranges)
+    }
 
-impl VarWrapper for Fd {
-    fn unify_with(&self, other: &VarWrapper, _: &mut StateProxy) -> UnifyResult {
-        let other = other.get_wrapped_value::<Fd>();
-        if let (Some(x), Some(y)) = (self.single_value(), other.single_value()) {
-            return (x == y).into();
+    ///! Return the values in this domain which aren't in `other`.
+    pub fn difference(&self, other: &Fd) -> Fd {
+        match self.repr {
+            Bits(base, word) => Fd::from_bits(base, word & !other.mask_at(base)),
+            Intervals(_) => self.combine(&other.complement()),
         }
-        if self == other {
-            return true.into();
+    }
+
+    ///! Return the values in this domain which are at least `min` and at most `max`.
+    pub fn constrain_range(&self, min: Option<i64>, max: Option<i64>) -> Fd {
+        let min = min.unwrap_or(::std::i64::MIN);
+        let max = max.unwrap_or(::std::i64::MAX);
+        match self.repr {
+            Bits(base, word) => Fd::from_bits(base, word & range_mask(base, min, max)),
+            Intervals(_) => self.combine(&Fd::new_range(min, max)),
         }
-        let result = self.combine(other);
-        if !result.is_valid() { return false.into(); }
+    }
 
-        unsafe { return UnifyResult::overwrite(result); }
+    pub fn min(&self) -> Option<i64> {
+        match self.repr {
+            Bits(base, _) => Some(base),
+            Intervals(ref ranges) => ranges.first().map(|x| x.0),
+        }
     }
-    fn value_count(&self) -> usize {
-        match *self {
-            Single(_) => 1,
-            Values(ref x) => x.len(),
+
+    pub fn max(&self) -> Option<i64> {
+        match self.repr {
+            Bits(base, word) => Some(base + (63 - word.leading_zeros()) as i64),
+            Intervals(ref ranges) => ranges.last().map(|x| x.1),
         }
     }
-    fn value_iter(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
-        match *self {
-            Single(_) => panic!(),
-            Values(ref x) => {
-                let fds: Vec<Box<VarWrapper>> = x.iter().map(|&val| {
-                    Box::new(Fd::new_single(val)) as Box<VarWrapper>
-                }).collect();
-                Box::new(fds.into_iter())
-            }
+
+    ///! Return the smallest and largest values in this domain.
+    pub fn bounds(&self) -> Option<(i64, i64)> {
+        match (self.min(), self.max()) {
+            (Some(min), Some(max)) => Some((min, max)),
+            _ => None,
+        }
+    }
+
+    pub fn single_value(&self) -> Option<i64> {
+        match self.repr {
+            Bits(base, 1) => Some(base),
+            _ => None,
         }
     }
-    fn uses_overwrite(&self) -> bool { true }
-}
 
+    pub fn in_range(&self, val: i64) -> bool {
+        match self.repr {
+            Bits(base, word) => {
+                val >= base && span(base, val) < SMALL_DOMAIN_SPAN && word & (1 << span(base, val)) != 0
+            },
+            Intervals(ref ranges) => match ranges.binary_search_by(|&(lo, _)| lo.cmp(&val)) {
+                Ok(_) => true,
+                Err(0) => false,
+                Err(x) => ranges[x - 1].1 >= val,
+            },
+        }
+    }
 
+    pub fn remove_values(&mut self, val: &HashSet<i64>) {
+        if let Bits(base, word) = self.repr {
+            let mask = val.iter().fold(0, |mask, &x| mask | range_mask(base, x, x));
+            *self = Fd::from_bits(base, word & !mask);
+            return;
+        }
+        let mut removed: Vec<i64> = val.iter().cloned().filter(|&x| self.in_range(x)).collect();
+        if removed.is_empty() {
+            return;
+        }
+        removed.sort();
+        *self = self.difference(&Fd::new_values(removed));
+    }
 
-struct RangeMerge<A, B, C> where A: Iterator<Item=C>, B: Iterator<Item=C>, C: PartialOrd + PartialEq {
-    a: A,
-    b: B,
+    ///! Return an iterator over the values in this domain, in ascending order.
+    pub fn iter(&self) -> FdValues {
+        match self.repr {
+            Bits(base, word) => FdValues { bits: Some((base, word)), ranges: Vec::new().into_iter(), current: None, back: None },
+            Intervals(ref ranges) => FdValues { bits: None, ranges: ranges.clone().into_iter(), current: None, back: None },
+        }
+    }
 }
 
-macro_rules! try_opt {
-    ($x:expr) => {
-        match $x {
-            Some(x) => x,
-            None => return None,
+///! Iterator over the values in an `Fd`, in ascending order, or descending with `rev()`.
+pub struct FdValues {
+    bits: Option<(i64, u64)>,
+    ranges: ::std::vec::IntoIter<(i64, i64)>,
+    ///! What's left of the ranges being taken from the front and the back.
+    current: Option<(i64, i64)>,
+    back: Option<(i64, i64)>,
+}
+
+impl Iterator for FdValues {
+    type Item = i64;
+    fn next(&mut self) -> Option<i64> {
+        if let Some((base, word)) = self.bits {
+            if word == 0 { return None; }
+            let offset = word.trailing_zeros();
+            self.bits = Some((base, word & (word - 1)));
+            return Some(base + offset as i64);
+        }
+        loop {
+            if let Some((lo, hi)) = self.current {
+                self.current = if lo < hi { Some((lo + 1, hi)) } else { None };
+                return Some(lo);
+            }
+            match self.ranges.next() {
+                Some(range) => { self.current = Some(range); },
+                None => match self.back.take() {
+                    Some(range) => { self.current = Some(range); },
+                    None => { return None; },
+                },
+            }
         }
     }
 }
 
-impl<A, B, C> Iterator for RangeMerge<A, B, C> where A: Iterator<Item=C>, B: Iterator<Item=C>, C: PartialOrd + PartialEq {
-    type Item=C;
-    fn next(&mut self) -> Option<C> {
-        let mut a = try_opt!(self.a.next());
-        let mut b = try_opt!(self.b.next());
+impl DoubleEndedIterator for FdValues {
+    fn next_back(&mut self) -> Option<i64> {
+        if let Some((base, word)) = self.bits {
+            if word == 0 { return None; }
+            let offset = 63 - word.leading_zeros();
+            self.bits = Some((base, word & !(1 << offset)));
+            return Some(base + offset as i64);
+        }
         loop {
-            if a == b {
-                return Some(a);
-            } else if a < b {
-                a = try_opt!(self.a.next());
-            } else {
-                b = try_opt!(self.b.next());
+            if let Some((lo, hi)) = self.back {
+                self.back = if lo < hi { Some((lo, hi - 1)) } else { None };
+                return Some(hi);
+            }
+            match self.ranges.next_back() {
+                Some(range) => { self.back = Some(range); },
+                None => match self.current.take() {
+                    Some(range) => { self.back = Some(range); },
+                    None => { return None; },
+                },
             }
         }
     }
 }
 
-impl<A, B, C> RangeMerge<A, B, C> where A: Iterator<Item=C>, B: Iterator<Item=C>, C: PartialOrd + PartialEq {
-    fn new<D, E>(a: D, b: E) -> RangeMerge<A, B, C> where D: IntoIterator<IntoIter=A, Item=C>, E: IntoIterator<IntoIter=B, Item=C> {
-        RangeMerge { a: a.into_iter(), b: b.into_iter() }
+///! Maps a type with a fixed set of values, like a C-like enum, onto the indexes `0..count()`.  A
+///! `Var` of the type holds a single value, and is left fresh while it could be any of them;
+///! `constraints::FdEnumConstraint` links it to a `Fd` of its index, which narrows with the `Fd`
+///! constraints and sets the value once only one index is left.
+///!
+///! This is normally derived with `#[derive(FiniteDomain)]` from the `kanren_derive` crate, which
+///! also provides `ToVar` and `VarWrapper`, so `state.make_var_of(Color::Red)` makes a `Var<Color>`.
+pub trait FiniteDomain: Sized {
+    fn to_index(&self) -> i64;
+    fn from_index(index: i64) -> Option<Self>;
+    ///! Return the number of values.
+    fn count() -> i64;
+
+    ///! Return a domain containing every value.
+    fn domain() -> Fd {
+        Fd::new_range(0, Self::count() - 1)
+    }
+    ///! Return a domain containing only the provided values.
+    fn domain_of(values: &[Self]) -> Fd {
+        let mut indexes: Vec<i64> = values.iter().map(Self::to_index).collect();
+        indexes.sort();
+        indexes.dedup();
+        Fd::new_values(indexes)
+    }
+    ///! Return the value of a domain narrowed down to a single index.
+    fn from_fd(fd: &Fd) -> Option<Self> {
+        fd.single_value().and_then(Self::from_index)
+    }
+    ///! Return the values whose indexes are in a domain, in index order.
+    fn values_of(fd: &Fd) -> Vec<Self> {
+        fd.combine(&Self::domain()).iter().filter_map(Self::from_index).collect()
+    }
+}
+
+impl VarWrapper for Fd {
+    fn unify_with(&self, other: &VarWrapper, _: &mut StateProxy) -> UnifyResult {
+        let other = other.get_wrapped_value::<Fd>();
+        if let (Some(x), Some(y)) = (self.single_value(), other.single_value()) {
+            return (x == y).into();
+        }
+        let result = self.combine(other);
+        if !result.is_valid() { return false.into(); }
+
+        unsafe { return UnifyResult::overwrite(result); }
+    }
+    fn value_count(&self) -> usize {
+        match self.repr {
+            Bits(_, word) => word.count_ones() as usize,
+            Intervals(ref ranges) => ranges.iter().fold(0usize, |count, &(lo, hi)| {
+                let size = span(lo, hi).saturating_add(1);
+                let size = if size > ::std::usize::MAX as u64 { ::std::usize::MAX } else { size as usize };
+                count.saturating_add(size)
+            }),
+        }
+    }
+    fn value_iter(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
+        Box::new(self.iter().map(|val| Box::new(Fd::new_single(val)) as Box<VarWrapper>))
     }
+    fn value_iter_rev(&self) -> Box<Iterator<Item=Box<VarWrapper>>> {
+        Box::new(self.iter().rev().map(|val| Box::new(Fd::new_single(val)) as Box<VarWrapper>))
+    }
+    fn value_split(&self) -> Option<(Box<VarWrapper>, Box<VarWrapper>)> {
+        let (lo, hi) = match self.bounds() {
+            Some(x) if x.0 < x.1 => x,
+            _ => { return None; },
+        };
+        let mid = lo + (span(lo, hi) / 2) as i64;
+        Some((Box::new(self.constrain_range(None, Some(mid))), Box::new(self.constrain_range(Some(mid + 1), None))))
+    }
+    fn value_regret(&self) -> u64 {
+        let mut values = self.iter();
+        match (values.next(), values.next()) {
+            (Some(a), Some(b)) => span(a, b),
+            _ => 0,
+        }
+    }
+    fn value_exclude(&self, other: &VarWrapper) -> Option<Box<VarWrapper>> {
+        let other = other.get_wrapped_value::<Fd>();
+        let result = self.difference(other);
+        if result == *self { None } else { Some(Box::new(result)) }
+    }
+    fn uses_overwrite(&self) -> bool { true }
 }
 
-///! Provides an iterator unifying a `Fd` variable with any of its possible values.
+///! Provides an iterator unifying a `Fd` variable with any of its possible values.  Negative values
+///! are skipped, since they can't be represented by the `usize`.
 pub fn fd_values<A, B>(mut state: State, fd: A, u: B) -> StateIter
 where A: ToVar<VarType=Fd>, B: ToVar<VarType=usize> {
     let fd = state.make_var_of(fd);
     let u = state.make_var_of(u);
     match state.get_value(fd).map(|x| x.clone()) {
-        Some(Single(x)) => {
-            state.unify(x, u);
-            single(state)
-        },
         None => { single(state) },
-        Some(Values(values)) => {
-            let valiter = values.into_iter();
-            TailIterResult(None, Some(fd_value_iter(Rc::new(state.unwrap()), fd, valiter, u)))
-        }
+        Some(values) => match values.single_value() {
+            Some(x) => {
+                if x < 0 { state.fail(); } else { state.unify(x as usize, u); }
+                single(state)
+            },
+            None => {
+                let values = values.constrain_range(Some(0), None);
+                if !values.is_valid() {
+                    state.fail();
+                    return single(state);
+                }
+                TailIterResult(None, Some(fd_value_iter(Rc::new(state.unwrap()), fd, values.iter(), u)))
+            },
+        },
     }
 }
 
-fn fd_value_iter(state: Rc<StateInner>, fd: Var<Fd>, mut vals: ::std::vec::IntoIter<usize>, u: Var<usize>) -> TailIter {
+fn fd_value_iter(state: Rc<StateInner>, fd: Var<Fd>, mut vals: FdValues, u: Var<usize>) -> TailIter {
     use iter::wrap_fn;
     wrap_fn(move || {
         while let Some(x) = vals.next() {
             let mut child = State::with_parent(state.clone());
-            child.unify(x, u);
-            child.unify(Single(x), fd);
+            child.unify(x as usize, u);
+            child.unify(Fd::new_single(x), fd);
             if child.ok() { return TailIterResult(Some(child), Some(fd_value_iter(state, fd, vals, u))); }
         }
         return TailIterResult(None, None);
     })
 }
-
-//impl TailIterator for FdValueIter {
-    //type Item = State;
-    //fn next_inner(&mut self) -> TailIterItem<State> {
-        //while let Some(x) = self.vals.next() {
-            //let mut child = State::with_parent(self.state.clone().unwrap());
-            ////println!("fdvalueiter: unifying {} with {:?} and {:?}, {} remain",
-                     ////x, child.get_value(self.fd), child.get_value(self.u), self.vals.len());
-            //child.unify(x, self.u);
-            //child.unify(Single(x), self.fd);
-            //if child.ok() { return SingleItem(child); }
-        //}
-        //return Done;
-    //}
-//}
@@ -16,5 +16,13 @@ pub mod iter;
 pub mod builtins;
 ///! Contains `Fd`, which represents a finite-domain value.
 pub mod finitedomain;
+///! Contains `Domain`, a finite domain over any ordered type.
+pub mod domain;
+///! Contains `Dfa`, a finite automaton for the `Regular` constraint.
+pub mod automaton;
 ///! Contains a number of built-in constraints.
 pub mod constraints;
+///! Contains branch-and-bound search for optimal solutions.
+pub mod optimize;
+///! Contains relational arithmetic on binary numbers, from The Reasoned Schemer.
+pub mod arith;
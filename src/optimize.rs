@@ -0,0 +1,139 @@
+use std::rc::Rc;
+use std::cell::Cell;
+use core::{State, Var, StateProxy, ToConstraint, Constraint, ConstraintResult, VarMap, VarRetrieve, StateInner, assign_all_values};
+use finitedomain::Fd;
+use iter::{StateIter, TailIterIter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    Minimize,
+    Maximize,
+}
+
+impl Direction {
+    fn improves(self, value: i64, best: i64) -> bool {
+        match self {
+            Direction::Minimize => value < best,
+            Direction::Maximize => value > best,
+        }
+    }
+}
+
+///! Keeps the objective strictly better than the best solution found so far.  The bound is shared
+///! with the `Improvements` iterator, which tightens it after each solution, so every state still
+///! waiting to be searched sees the new bound the next time it unifies anything.
+#[derive(Debug, Clone)]
+struct ObjectiveBound {
+    objective: Var<Fd>,
+    direction: Direction,
+    best: Rc<Cell<Option<i64>>>,
+}
+
+impl ToConstraint for ObjectiveBound {
+    type ConstraintType = ObjectiveBound;
+    fn into_constraint(self, _: &mut StateInner) -> ObjectiveBound { self }
+}
+
+impl Constraint for ObjectiveBound {
+    fn update(&self, state: &mut StateProxy) -> ConstraintResult<ObjectiveBound> {
+        use core::ConstraintResult::*;
+        let best = match self.best.get() {
+            Some(best) => best,
+            None => { return Unchanged; },
+        };
+        let new_value = {
+            let value = match state.get_value(self.objective) {
+                Some(value) => value,
+                None => { return Unchanged; },
+            };
+            let new_value = match self.direction {
+                Direction::Minimize => value.constrain_range(None, Some(best.saturating_sub(1))),
+                Direction::Maximize => value.constrain_range(Some(best.saturating_add(1)), None),
+            };
+            if new_value == *value { return Unchanged; }
+            new_value
+        };
+        if !new_value.is_valid() { return Failed; }
+        unsafe { state.overwrite_var(self.objective, new_value); }
+        // Never irrelevant, since the bound can tighten again later.
+        Unchanged
+    }
+    fn relevant(&self, _: &VarMap) -> bool {
+        // The bound changes outside of unification, so check it whenever anything changes.
+        true
+    }
+    fn update_vars(&mut self, state: &StateInner) {
+        state.update_var(self.objective.untyped_mut());
+    }
+}
+
+///! Iterates over successively better solutions, as returned by `minimize_iter` and
+///! `maximize_iter`.  The last solution is optimal.
+pub struct Improvements {
+    iter: TailIterIter,
+    objective: Var<Fd>,
+    direction: Direction,
+    best: Rc<Cell<Option<i64>>>,
+}
+
+impl Improvements {
+    fn new<F>(mut state: State, objective: Var<Fd>, direction: Direction, goal: F) -> Improvements
+    where F: FnOnce(State) -> StateIter {
+        let best = Rc::new(Cell::new(None));
+        state.add_constraint(ObjectiveBound { objective: objective, direction: direction, best: best.clone() });
+        let iter = goal(state).and(assign_all_values).into_iter();
+        Improvements { iter: iter, objective: objective, direction: direction, best: best }
+    }
+
+    ///! Return the objective value of the best solution found so far.
+    pub fn best(&self) -> Option<i64> {
+        self.best.get()
+    }
+}
+
+impl Iterator for Improvements {
+    type Item = State;
+    fn next(&mut self) -> Option<State> {
+        for state in self.iter.by_ref() {
+            // A solution found before the bound was tightened may no longer be an improvement.
+            let value = match state.get_value(self.objective).and_then(|x| x.single_value()) {
+                Some(value) => value,
+                None => { continue; },
+            };
+            match self.best.get() {
+                Some(best) if !self.direction.improves(value, best) => { continue; },
+                _ => (),
+            }
+            self.best.set(Some(value));
+            return Some(state);
+        }
+        None
+    }
+}
+
+///! Search for the solution to `goal` with the smallest value of `objective`, assigning values to
+///! every finite-domain variable along the way.  Each time a solution is found, the objective is
+///! constrained to be smaller than it for the rest of the search.  Solutions that leave the
+///! objective without a single value are skipped.
+pub fn minimize<F>(state: State, objective: Var<Fd>, goal: F) -> Option<State>
+where F: FnOnce(State) -> StateIter {
+    minimize_iter(state, objective, goal).last()
+}
+
+///! Search for the solution to `goal` with the largest value of `objective`.  See `minimize`.
+pub fn maximize<F>(state: State, objective: Var<Fd>, goal: F) -> Option<State>
+where F: FnOnce(State) -> StateIter {
+    maximize_iter(state, objective, goal).last()
+}
+
+///! Like `minimize`, but return each improving solution as it's found.
+pub fn minimize_iter<F>(state: State, objective: Var<Fd>, goal: F) -> Improvements
+where F: FnOnce(State) -> StateIter {
+    Improvements::new(state, objective, Direction::Minimize, goal)
+}
+
+///! Like `maximize`, but return each improving solution as it's found.
+pub fn maximize_iter<F>(state: State, objective: Var<Fd>, goal: F) -> Improvements
+where F: FnOnce(State) -> StateIter {
+    Improvements::new(state, objective, Direction::Maximize, goal)
+}
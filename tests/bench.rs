@@ -5,10 +5,13 @@ extern crate kanren;
 extern crate test;
 use test::Bencher;
 
-use kanren::core::{State, Unifier, Var, ToVar, VarStore};
+use kanren::core::{State, Unifier, Var, ToVar, VarStore, VarRetrieve};
 use kanren::iter::{StateIter, single}; use kanren::core::vars::__;
 use kanren::builtins::contains;
 use kanren::list::{List, Pair};
+use kanren::finitedomain::Fd;
+use kanren::constraints::AllDiffConstraint;
+use std::collections::HashSet;
 use Cigarettes::*;
 use Nationalities::*;
 use Colors::*;
@@ -154,6 +157,36 @@ fn test_neighborhood(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn fd_combine_small(b: &mut Bencher) {
+    let x = Fd::new_values(vec![1, 3, 5, 7, 9]);
+    let y = Fd::new_range(2, 8);
+    b.iter(|| x.combine(&y));
+}
+
+#[bench]
+fn fd_remove_values_small(b: &mut Bencher) {
+    let x = Fd::new_range(1, 9);
+    let remove: HashSet<i64> = vec![2, 5, 8].into_iter().collect();
+    b.iter(|| {
+        let mut x = x.clone();
+        x.remove_values(&remove);
+        x
+    });
+}
+
+#[bench]
+fn fd_alldiff_row(b: &mut Bencher) {
+    b.iter(|| {
+        let mut state = State::new();
+        let vars: Vec<Var<Fd>> = (0..9).map(|_| state.make_var_of(Fd::new_range(1, 9))).collect();
+        state.add_constraint(AllDiffConstraint::new(vars.clone()));
+        for (i, &var) in vars.iter().enumerate().skip(1) {
+            state.unify(var, Fd::new_single(i as i64 + 1));
+        }
+        assert!(state.get_value(vars[0]).unwrap().single_value() == Some(1));
+    });
+}
 
 #[cfg(not(test))]
 fn main() {
@@ -1,11 +1,16 @@
 #[macro_use]
 extern crate kanren;
+#[macro_use]
+extern crate kanren_derive;
 
-use kanren::core::{State, Var, Unifier, VarStore, VarRetrieve, VarMap, StateInner};
+use kanren::core::{State, Var, Unifier, VarStore, VarRetrieve, VarMap, StateInner, VarWrapper};
 use kanren::core::vars::__;
 use kanren::core::assign_all_values;
 use kanren::finitedomain::{Fd, fd_values};
-use kanren::constraints::{SumConstraint, FdLessOrEqual, AllDiffConstraint, Disequal};
+use kanren::constraints::{SumConstraint, FdSumConstraint, FdLessOrEqual, AllDiffConstraint, Disequal};
+use kanren::constraints::{FdDifferenceConstraint, FdProductConstraint, FdQuotientConstraint, FdRemainderConstraint};
+use kanren::constraints::{FdAbsConstraint, FdMinConstraint, FdMaxConstraint, FdLessThan};
+use kanren::constraints::{FdLinear, FdRelation, AllDiffPropagation};
 use kanren::core::{Constraint, ToConstraint, ConstraintResult, StateProxy, UntypedVar};
 use std::fmt::{self, Write, Debug, Formatter};
 use kanren::iter::{single, IterBuilder, VarIter, StateIterExt};
@@ -309,6 +314,78 @@ fn fd_intersection() {
     assert!(!state.ok());
 }
 
+#[test]
+fn fd_intervals() {
+    let a = Fd::new_intervals(vec![(5, 9), (-3, 0), (1, 2), (20, 20)]);
+    assert!(a.intervals() == vec![(-3, 2), (5, 9), (20, 20)]);
+    assert!(a.bounds() == Some((-3, 20)));
+    assert!(a.value_count() == 12);
+    assert!(a.in_range(-1) && a.in_range(20) && !a.in_range(3) && !a.in_range(21));
+
+    let b = Fd::new_range(0, 6);
+    assert!(a.combine(&b) == Fd::new_intervals(vec![(0, 2), (5, 6)]));
+    assert!(a.union(&b) == Fd::new_intervals(vec![(-3, 9), (20, 20)]));
+    assert!(a.difference(&b) == Fd::new_intervals(vec![(-3, -1), (7, 9), (20, 20)]));
+    assert!(a.complement().complement() == a);
+    assert!(!a.combine(&a.complement()).is_valid());
+    assert!(Fd::new_values(vec![1, 2, 3, 5]) == Fd::new_intervals(vec![(1, 3), (5, 5)]));
+    assert!(a.iter().take(4).collect::<Vec<_>>() == vec![-3, -2, -1, 0]);
+    assert!(a.iter().rev().take(4).collect::<Vec<_>>() == vec![20, 9, 8, 7]);
+    let mut both = Fd::new_intervals(vec![(1, 2), (5, 5)]).iter();
+    assert!(both.next_back() == Some(5) && both.next() == Some(1) && both.next_back() == Some(2));
+    assert!(both.next() == None && both.next_back() == None);
+
+    let big = Fd::new_range(0, 1_000_000_000);
+    assert!(big.value_count() == 1_000_000_001);
+    assert!(big.constrain_range(Some(-5), Some(3)) == Fd::new_range(0, 3));
+}
+
+#[test]
+fn fd_small_domains() {
+    // domains switch between bitsets and interval lists at 64 values, and should compare equal
+    // whichever way they were built
+    let wide = Fd::new_range(-10, 100);
+    let narrow = wide.constrain_range(Some(-10), Some(53));
+    assert!(narrow == Fd::new_range(-10, 53));
+    assert!(narrow.value_count() == 64);
+    assert!(narrow.bounds() == Some((-10, 53)));
+    assert!(narrow.union(&Fd::new_single(54)) == Fd::new_intervals(vec![(-10, 54)]));
+    assert!(wide.difference(&Fd::new_range(-9, 100)) == Fd::new_single(-10));
+
+    let odd = Fd::new_values((0..32).map(|x| x * 2 + 1).collect());
+    assert!(odd.value_count() == 32);
+    assert!(odd.min() == Some(1) && odd.max() == Some(63));
+    assert!(odd.combine(&Fd::new_range(10, 1000)) == Fd::new_values((5..32).map(|x| x * 2 + 1).collect()));
+    assert!(odd.union(&odd.constrain_range(Some(0), Some(62)).complement()).complement() == Fd::err());
+    assert!(odd.iter().take(3).collect::<Vec<_>>() == vec![1, 3, 5]);
+    assert!(odd.iter().rev().take(3).collect::<Vec<_>>() == vec![63, 61, 59]);
+    let mut removed = odd.clone();
+    removed.remove_values(&HashSet::from_iter(vec![1, 2, 63]));
+    assert!(removed.bounds() == Some((3, 61)) && removed.value_count() == 30);
+
+    let top = Fd::new_range(::std::i64::MAX - 10, ::std::i64::MAX);
+    assert!(top.max() == Some(::std::i64::MAX));
+    assert!(top.bounds() == Some((::std::i64::MAX - 10, ::std::i64::MAX)));
+    assert!(top.iter().last() == Some(::std::i64::MAX));
+}
+
+#[test]
+fn fd_large_domain_sum() {
+    let mut state = State::new();
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(-1_000_000, 1_000_000));
+    state.unify(b, Fd::new_range(0, 10));
+    state.unify(c, Fd::new_range(-5, 5));
+    state.add_constraint(FdSumConstraint::new(a, b, c));
+    assert!(state.ok());
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(-15, 5));
+    state.add_constraint(FdLessOrEqual::new(c, b));
+    state.unify(a, Fd::new_single(-12));
+    assert!(state.ok());
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(7, 10));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(-5, -2));
+}
+
 #[derive(Clone)]
 struct ConstraintFn {
     f: Rc<Box<Fn(&mut StateProxy) -> ConstraintResult<ConstraintFn>>>,
@@ -480,6 +557,1437 @@ fn less_fd() {
     assert!(*state.get_value(c).unwrap() == Fd::new_single(2));
 }
 
+#[test]
+fn fd_product() {
+    let mut state = State::new();
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(1, 9));
+    state.unify(b, Fd::new_range(1, 9));
+    state.unify(c, Fd::new_single(12));
+    state.add_constraint(FdProductConstraint::new(a, b, c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_values(vec![2, 3, 4, 6]));
+    assert!(*state.get_value(b).unwrap() == Fd::new_values(vec![2, 3, 4, 6]));
+    state.unify(a, Fd::new_single(3));
+    assert!(state.get_value(b).unwrap().single_value() == Some(4));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(-1_000_000, 1_000_000));
+    state.unify(b, Fd::new_range(2, 5));
+    state.unify(c, Fd::new_range(-10, 10));
+    state.add_constraint(FdProductConstraint::new(a, b, c));
+    assert!(state.ok());
+    assert!(state.get_value(a).unwrap().bounds() == Some((-5, 5)));
+    assert!(!state.get_value(c).unwrap().in_range(7));
+    state.unify(c, Fd::new_values(vec![-7, 1, 7]));
+    assert!(!state.ok());
+}
+
+#[test]
+fn fd_difference_quotient_remainder() {
+    let mut state = State::new();
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_single(10));
+    state.unify(c, Fd::new_range(0, 3));
+    state.add_constraint(FdDifferenceConstraint::new(a, b, c));
+    state.unify(b, Fd::new_range(-100, 100));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(7, 10));
+
+    fresh!(state, a, c);
+    state.unify(a, Fd::new_range(0, 20));
+    state.unify(c, Fd::new_single(4));
+    state.add_constraint(FdQuotientConstraint::new(a, Fd::new_single(3), c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(12, 14));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(-10, 10));
+    state.unify(c, Fd::new_single(3));
+    state.add_constraint(FdRemainderConstraint::new(a, Fd::new_single(4), c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_values(vec![3, 7]));
+    state.add_constraint(FdRemainderConstraint::new(Fd::new_single(-7), Fd::new_single(4), b));
+    assert!(state.get_value(b).unwrap().single_value() == Some(-3));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(-1_000_000_000, 1_000_000_000));
+    state.unify(b, Fd::new_range(-1000, 1000));
+    state.unify(c, Fd::new_range(3, 1_000_000_000));
+    state.add_constraint(FdRemainderConstraint::new(a, b, c));
+    assert!(state.ok());
+    assert!(state.get_value(a).unwrap().min() == Some(3));
+    assert!(state.get_value(c).unwrap().bounds() == Some((3, 999)));
+    assert!(!state.get_value(b).unwrap().in_range(-3));
+}
+
+#[test]
+fn fd_abs_min_max() {
+    let mut state = State::new();
+    fresh!(state, a, b);
+    state.unify(a, Fd::new_range(-5, 5));
+    state.unify(b, Fd::new_values(vec![2, 7]));
+    state.add_constraint(FdAbsConstraint::new(a, b));
+    assert!(*state.get_value(a).unwrap() == Fd::new_values(vec![-2, 2]));
+    assert!(state.get_value(b).unwrap().single_value() == Some(2));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(3, 8));
+    state.unify(b, Fd::new_range(5, 10));
+    state.unify(c, Fd::new_range(0, 4));
+    state.add_constraint(FdMinConstraint::new(a, b, c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(3, 4));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(5, 10));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(3, 4));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(3, 8));
+    state.unify(b, Fd::new_range(5, 10));
+    state.unify(c, Fd::new_range(0, 6));
+    state.add_constraint(FdMaxConstraint::new(a, b, c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(3, 6));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(5, 6));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(5, 6));
+
+    fresh!(state, a, b, c);
+    state.unify(a, Fd::new_range(0, 1_000_000));
+    state.unify(b, Fd::new_range(500, 1_000_000));
+    state.unify(c, Fd::new_range(-1_000_000, 100));
+    state.add_constraint(FdMinConstraint::new(a, b, c));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(0, 100));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(500, 1_000_000));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(0, 100));
+}
+
+#[test]
+fn fd_less_than() {
+    let mut state = State::new();
+    fresh!(state, a, b);
+    state.unify(a, Fd::new_range(1, 3));
+    state.unify(b, Fd::new_range(1, 3));
+    state.add_constraint(FdLessThan::new(a, b));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(1, 2));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(2, 3));
+    state.unify(b, Fd::new_single(2));
+    assert!(state.get_value(a).unwrap().single_value() == Some(1));
+    state.add_constraint(FdLessThan::new(b, a));
+    assert!(!state.ok());
+}
+
+#[test]
+fn fd_nqueens() {
+    let n = 6;
+    let mut state = State::new();
+    let queens: Vec<Var<Fd>> = (0..n).map(|_| state.make_var_of(Fd::new_range(0, n - 1))).collect();
+    state.add_constraint(AllDiffConstraint::new(queens.clone()));
+    for i in 0..n {
+        for j in (i + 1)..n {
+            // |q_i - q_j| != |i - j|
+            fresh!(state, diff, absdiff);
+            state.unify(diff, Fd::new_range(1 - n, n - 1));
+            state.unify(absdiff, Fd::new_range(1, n - 1).difference(&Fd::new_single(j - i)));
+            state.add_constraint(FdDifferenceConstraint::new(queens[i as usize], queens[j as usize], diff));
+            state.add_constraint(FdAbsConstraint::new(diff, absdiff));
+        }
+    }
+    let solutions: Vec<Vec<i64>> = assign_all_values(state).into_iter().map(|state| {
+        queens.iter().map(|&q| state.get_value(q).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    assert!(solutions.len() == 4);
+    assert!(solutions.contains(&vec![1, 3, 5, 0, 2, 4]));
+}
+
+#[test]
+fn fd_linear() {
+    let mut state = State::new();
+    fresh!(state, x, y);
+    state.unify(x, Fd::new_range(0, 10));
+    state.unify(y, Fd::new_range(0, 10));
+    state.add_constraint(FdLinear::new(vec![(2, x), (3, y)], FdRelation::Equal, 12));
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(0, 6));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(0, 4));
+    state.unify(x, Fd::new_single(3));
+    assert!(state.get_value(y).unwrap().single_value() == Some(2));
+
+    fresh!(state, x, y);
+    state.unify(x, Fd::new_range(3, 10));
+    state.unify(y, Fd::new_range(0, 10));
+    state.add_constraint(FdLinear::new(vec![(1, x), (1, y)], FdRelation::LessOrEqual, 5));
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(3, 5));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(0, 2));
+
+    fresh!(state, x, y);
+    state.unify(y, Fd::new_range(0, 10));
+    state.add_constraint(FdLinear::new(vec![(1, x), (1, y)], FdRelation::NotEqual, 5));
+    state.unify(x, Fd::new_single(2));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(0, 10).difference(&Fd::new_single(3)));
+
+    fresh!(state, x, y);
+    state.unify(x, Fd::new_range(0, 1_000_000_000_000));
+    state.unify(y, Fd::new_range(-3, 3));
+    state.add_constraint(FdLinear::new(vec![(-1, x), (1_000_000, y)], FdRelation::Equal, 0));
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(0, 3_000_000));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(0, 3));
+    state.unify(x, Fd::new_single(2_000_001));
+    assert!(!state.ok());
+
+    // i64::MIN / -1 overflows, so no value is ruled out
+    let mut state = State::new();
+    fresh!(state, x, y);
+    state.unify(x, Fd::new_range(0, 10));
+    state.add_constraint(FdLinear::new(vec![(-1, x), (1, y)], FdRelation::NotEqual, ::std::i64::MIN));
+    state.unify(y, Fd::new_single(0));
+    assert!(state.ok());
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(0, 10));
+
+    // the other terms sum to i64::MAX - 2, though adding them in order passes i64::MAX
+    let mut state = State::new();
+    fresh!(state, w, x, y, z);
+    state.unify(w, Fd::new_range(0, 10));
+    state.unify(x, Fd::new_single(::std::i64::MAX - 1));
+    state.unify(y, Fd::new_single(::std::i64::MAX - 1));
+    state.unify(z, Fd::new_single(::std::i64::MIN + 1));
+    state.add_constraint(FdLinear::new(vec![(1, w), (1, x), (1, y), (1, z)], FdRelation::Equal, ::std::i64::MAX));
+    assert!(state.get_value(w).unwrap().single_value() == Some(2));
+
+    // with the terms at the limits, nothing can be pruned, but nothing valid is either
+    let mut state = State::new();
+    fresh!(state, w, x, y, z);
+    state.unify(w, Fd::new_range(0, 10));
+    state.unify(x, Fd::new_single(::std::i64::MAX));
+    state.unify(y, Fd::new_single(::std::i64::MAX));
+    state.unify(z, Fd::new_single(::std::i64::MIN));
+    state.add_constraint(FdLinear::new(vec![(1, w), (1, x), (1, y), (1, z)], FdRelation::LessOrEqual, ::std::i64::MAX));
+    assert!(state.ok());
+    assert!(state.get_value(w).unwrap().min() == Some(0));
+    state.unify(w, Fd::new_single(1));
+    assert!(state.ok());
+}
+
+#[test]
+fn fd_linear_send_more_money() {
+    let mut state = State::new();
+    fresh!(state, s, e, n, d, m, o, r, y);
+    let letters = vec![s, e, n, d, m, o, r, y];
+    state.add_constraint(AllDiffConstraint::new(letters.clone()));
+    for &var in letters.iter() {
+        state.unify(var, Fd::new_range(0, 9));
+    }
+    state.unify(s, Fd::new_range(1, 9));
+    state.unify(m, Fd::new_range(1, 9));
+    state.add_constraint(FdLinear::new(vec![
+        (1000, s), (100, e), (10, n), (1, d),
+        (1000, m), (100, o), (10, r), (1, e),
+        (-10000, m), (-1000, o), (-100, n), (-10, e), (-1, y),
+    ], FdRelation::Equal, 0));
+    let solutions: Vec<Vec<i64>> = assign_all_values(state).into_iter().map(|state| {
+        letters.iter().map(|&x| state.get_value(x).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    assert!(solutions == vec![vec![9, 5, 6, 7, 1, 0, 8, 2]]);
+}
+
+#[test]
+fn alldiff_propagation_levels() {
+    fn propagate(level: AllDiffPropagation, domains: Vec<Fd>) -> Option<Vec<Fd>> {
+        let mut state = State::new();
+        let vars: Vec<Var<Fd>> = domains.into_iter().map(|fd| state.make_var_of(fd)).collect();
+        state.add_constraint(AllDiffConstraint::with_propagation(vars.clone(), level));
+        if !state.ok() { return None; }
+        Some(vars.iter().map(|&var| state.get_value(var).unwrap().clone()).collect())
+    }
+    use kanren::constraints::AllDiffPropagation::*;
+
+    // {1, 2} is a Hall interval
+    let hall = vec![Fd::new_range(1, 2), Fd::new_range(1, 2), Fd::new_range(1, 4)];
+    assert!(propagate(Value, hall.clone()).unwrap()[2] == Fd::new_range(1, 4));
+    assert!(propagate(Bounds, hall.clone()).unwrap()[2] == Fd::new_range(3, 4));
+    assert!(propagate(Domain, hall.clone()).unwrap()[2] == Fd::new_range(3, 4));
+
+    // the third domain straddles the Hall interval without having any of its values
+    let straddle = vec![Fd::new_range(1, 2), Fd::new_range(1, 2), Fd::new_values(vec![0, 5])];
+    assert!(propagate(Bounds, straddle).unwrap()[2] == Fd::new_values(vec![0, 5]));
+
+    // {1, 3} is a Hall set but not an interval
+    let holes = vec![Fd::new_values(vec![1, 3]), Fd::new_values(vec![1, 3]), Fd::new_range(1, 3)];
+    assert!(propagate(Bounds, holes.clone()).unwrap()[2] == Fd::new_range(1, 3));
+    assert!(propagate(Domain, holes.clone()).unwrap()[2] == Fd::new_single(2));
+
+    let pigeons = vec![Fd::new_range(1, 3); 4];
+    assert!(propagate(Value, pigeons.clone()).is_some());
+    assert!(propagate(Bounds, pigeons.clone()).is_none());
+    assert!(propagate(Domain, pigeons.clone()).is_none());
+
+    // too many values to list, so this falls back to bounds
+    let large = vec![Fd::new_range(0, 1), Fd::new_range(0, 1), Fd::new_range(0, 1_000_000_000)];
+    assert!(propagate(Domain, large).unwrap()[2] == Fd::new_range(2, 1_000_000_000));
+
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(1, 4))).collect();
+    state.add_constraint(AllDiffConstraint::with_propagation(vars.clone(), Domain));
+    state.unify(vars[0], Fd::new_range(1, 2));
+    state.unify(vars[1], Fd::new_values(vec![1, 2]));
+    state.unify(vars[2], Fd::new_values(vec![2, 3]));
+    assert!(state.get_value(vars[2]).unwrap().single_value() == Some(3));
+    assert!(state.get_value(vars[3]).unwrap().single_value() == Some(4));
+    assert!(assign_all_values(state).into_iter().count() == 2);
+}
+
+#[test]
+fn fd_reified() {
+    use kanren::constraints::{Reified, FdEqual, FdNotEqual};
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(1, 3));
+    let y = state.make_var_of(Fd::new_range(5, 6));
+    state.add_constraint(Reified::new(b, FdLessOrEqual::new(x, y)));
+    assert!(state.get_value(b) == Some(&true));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(1, 9));
+    let y = state.make_var_of(Fd::new_range(3, 4));
+    state.add_constraint(Reified::new(b, FdLessOrEqual::new(x, y)));
+    assert!(state.get_value(b).is_none());
+    state.unify(b, false);
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(4, 9));
+
+    let mut state = State::new();
+    fresh!(state, b, c);
+    let x = state.make_var_of(Fd::new_range(1, 3));
+    let y = state.make_var_of(Fd::new_range(4, 6));
+    state.add_constraint(Reified::new(b, FdEqual::new(x, y)));
+    state.add_constraint(Reified::new(c, FdNotEqual::new(x, y)));
+    assert!(state.get_value(b) == Some(&false));
+    assert!(state.get_value(c) == Some(&true));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(1, 3));
+    let y = state.make_var_of(Fd::new_range(1, 3));
+    state.add_constraint(Reified::new(b, FdEqual::new(x, y)));
+    state.unify(x, Fd::new_single(2));
+    assert!(state.get_value(b).is_none());
+    state.unify(b, false);
+    assert!(*state.get_value(y).unwrap() == Fd::new_values(vec![1, 3]));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(1, 3));
+    let y = state.make_var_of(Fd::new_range(2, 5));
+    state.add_constraint(Reified::new(b, FdEqual::new(x, y)));
+    state.unify(b, true);
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(2, 3));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(2, 3));
+    state.unify(y, Fd::new_single(3));
+    assert!(state.get_value(x).unwrap().single_value() == Some(3));
+}
+
+#[test]
+fn fd_reified_sum() {
+    use kanren::constraints::Reified;
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(0, 2));
+    let y = state.make_var_of(Fd::new_range(0, 2));
+    let z = state.make_var_of(Fd::new_range(5, 9));
+    state.add_constraint(Reified::new(b, FdSumConstraint::new(x, y, z)));
+    assert!(state.get_value(b) == Some(&false));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(0, 2));
+    let y = state.make_var_of(Fd::new_range(0, 2));
+    let z = state.make_var_of(Fd::new_range(0, 9));
+    state.add_constraint(Reified::new(b, FdSumConstraint::new(x, y, z)));
+    state.unify(b, true);
+    assert!(*state.get_value(z).unwrap() == Fd::new_range(0, 4));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(0, 2));
+    let y = state.make_var_of(Fd::new_range(0, 2));
+    let z = state.make_var_of(Fd::new_range(0, 4));
+    state.add_constraint(Reified::new(b, FdSumConstraint::new(x, y, z)));
+    state.unify(b, false);
+    state.unify(x, Fd::new_single(1));
+    state.unify(y, Fd::new_single(2));
+    assert!(*state.get_value(z).unwrap() == Fd::new_values(vec![0, 1, 2, 4]));
+
+    let mut state = State::new();
+    fresh!(state, b);
+    let x = state.make_var_of(Fd::new_range(0, 2));
+    let y = state.make_var_of(Fd::new_range(0, 2));
+    let z = state.make_var_of(Fd::new_single(2));
+    state.add_constraint(Reified::new(b, FdSumConstraint::new(x, y, z)));
+    let mut counts = (0, 0);
+    for state in assign_all_values(state).into_iter() {
+        let sum = state.get_value(x).unwrap().single_value().unwrap() + state.get_value(y).unwrap().single_value().unwrap();
+        assert!(state.get_value(b) == Some(&(sum == 2)));
+        if sum == 2 { counts.0 += 1; } else { counts.1 += 1; }
+    }
+    assert!(counts == (3, 6));
+}
+
+#[test]
+fn bool_ops() {
+    use kanren::constraints::{BoolAnd, BoolOr, BoolXor, BoolImplies, BoolNot};
+    fn label(state: State, vars: Vec<Var<bool>>) -> ::kanren::iter::StateIter {
+        vars.into_iter().fold(single(state), |iter, var| {
+            iter.and(move |state| conde!(state, {
+                state.unify(var, false);
+                state
+            }, {
+                state.unify(var, true);
+                state
+            }))
+        })
+    }
+
+    // every labeling that survives propagation satisfies the operation
+    let ops: Vec<(fn(bool, bool) -> bool, usize)> = vec![
+        (|l, r| l && r, 0), (|l, r| l || r, 1), (|l, r| l != r, 2), (|l, r| !l || r, 3),
+    ];
+    for (f, i) in ops.into_iter() {
+        let mut state = State::new();
+        fresh!(state, l, r, result);
+        match i {
+            0 => state.add_constraint(BoolAnd::new(l, r, result)),
+            1 => state.add_constraint(BoolOr::new(l, r, result)),
+            2 => state.add_constraint(BoolXor::new(l, r, result)),
+            _ => state.add_constraint(BoolImplies::new(l, r, result)),
+        };
+        let results: Vec<(bool, bool, bool)> = label(state, vec![l, r, result]).into_iter().map(|state| {
+            (*state.get_value(l).unwrap(), *state.get_value(r).unwrap(), *state.get_value(result).unwrap())
+        }).collect();
+        assert!(results.len() == 4);
+        assert!(results.iter().all(|&(l, r, result)| f(l, r) == result));
+    }
+
+    let mut state = State::new();
+    fresh!(state, l, r, result);
+    state.add_constraint(BoolAnd::new(l, r, result));
+    state.unify(result, true);
+    assert!(state.get_value(l) == Some(&true) && state.get_value(r) == Some(&true));
+
+    let mut state = State::new();
+    fresh!(state, l, r);
+    state.add_constraint(BoolOr::new(l, r, false));
+    assert!(state.get_value(l) == Some(&false) && state.get_value(r) == Some(&false));
+
+    let mut state = State::new();
+    fresh!(state, l, r, result);
+    state.add_constraint(BoolXor::new(l, r, result));
+    state.unify(l, true);
+    assert!(state.get_value(result).is_none());
+    state.unify(r, true);
+    assert!(state.get_value(result) == Some(&false));
+
+    let mut state = State::new();
+    fresh!(state, a, b, c);
+    state.add_constraint(BoolImplies::new(a, b, true));
+    state.add_constraint(BoolImplies::new(b, c, true));
+    state.unify(a, true);
+    assert!(state.get_value(c) == Some(&true));
+    state.add_constraint(BoolNot::new(c, b));
+    assert!(!state.ok());
+
+    let mut state = State::new();
+    fresh!(state, a, b);
+    state.add_constraint(BoolNot::new(a, b));
+    state.unify(b, false);
+    assert!(state.get_value(a) == Some(&true));
+}
+
+#[test]
+fn bool_clauses() {
+    use kanren::constraints::BoolClause;
+    let mut state = State::new();
+    fresh!(state, a, b, c);
+    // a or b or not c
+    state.add_constraint(BoolClause::new(vec![a, b], vec![c]));
+    state.unify(a, false);
+    state.unify(c, true);
+    assert!(state.get_value(b) == Some(&true));
+
+    // pigeonhole: three pigeons can't share two holes
+    let mut state = State::new();
+    let holes: Vec<Vec<Var<bool>>> = (0..3).map(|_| vec![state.make_var(), state.make_var()]).collect();
+    for pigeon in holes.iter() {
+        state.add_constraint(BoolClause::new(pigeon.clone(), Vec::<Var<bool>>::new()));
+    }
+    for hole in 0..2 {
+        for i in 0..3 {
+            for j in (i + 1)..3 {
+                state.add_constraint(BoolClause::new(Vec::<Var<bool>>::new(), vec![holes[i][hole], holes[j][hole]]));
+            }
+        }
+    }
+    assert!(state.ok());
+    // placing any one pigeon forces the rest until two collide
+    state.unify(holes[0][0], true);
+    assert!(!state.ok());
+}
+
+#[test]
+fn cardinality() {
+    use kanren::constraints::Cardinality;
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..4).map(|_| state.make_var()).collect();
+    state.add_constraint(Cardinality::exactly(vars.clone(), 2));
+    state.unify(vars[0], true);
+    assert!(state.get_value(vars[1]).is_none());
+    state.unify(vars[1], true);
+    assert!(state.get_value(vars[2]) == Some(&false));
+    assert!(state.get_value(vars[3]) == Some(&false));
+
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..4).map(|_| state.make_var()).collect();
+    state.add_constraint(Cardinality::at_least(vars.clone(), 3));
+    state.unify(vars[2], false);
+    assert!(vars.iter().all(|&var| var.untyped() == vars[2].untyped() || state.get_value(var) == Some(&true)));
+
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(Cardinality::at_most(vars.clone(), 1));
+    state.unify(vars[0], true);
+    state.unify(vars[1], true);
+    assert!(!state.ok());
+
+    // the count follows the variables, and the variables follow the count
+    let mut state = State::new();
+    fresh!(state, count);
+    let vars = vec![state.make_var_of(true), state.make_var(), state.make_var(), state.make_var_of(false)];
+    state.add_constraint(Cardinality::new(vars.clone(), count));
+    assert!(*state.get_value(count).unwrap() == Fd::new_range(1, 3));
+    state.unify(vars[1], false);
+    assert!(*state.get_value(count).unwrap() == Fd::new_range(1, 2));
+    state.unify(count, Fd::new_single(2));
+    assert!(state.get_value(vars[2]) == Some(&true));
+
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(Cardinality::new(vars.clone(), Fd::new_values(vec![0, 3])));
+    state.unify(vars[1], true);
+    assert!(vars.iter().all(|&var| state.get_value(var) == Some(&true)));
+}
+
+#[test]
+fn pseudo_boolean() {
+    use kanren::constraints::PseudoBoolean;
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..3).map(|_| state.make_var()).collect();
+    let weights = vec![5, 4, 3];
+    state.add_constraint(PseudoBoolean::at_most(weights.iter().cloned().zip(vars.iter().cloned()).collect(), 8));
+    state.unify(vars[0], true);
+    assert!(state.get_value(vars[1]) == Some(&false));
+    assert!(state.get_value(vars[2]).is_none());
+
+    // negative weights
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(PseudoBoolean::at_least(vec![(2, vars[0]), (-3, vars[1]), (1, vars[2])], 2));
+    assert!(state.get_value(vars[0]) == Some(&true));
+    assert!(state.get_value(vars[1]) == Some(&false));
+    assert!(state.get_value(vars[2]).is_none());
+
+    // the total is an ordinary Fd variable
+    let mut state = State::new();
+    fresh!(state, total);
+    let vars: Vec<Var<bool>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(PseudoBoolean::new(vec![(2, vars[0]), (3, vars[1]), (4, vars[2])], total));
+    assert!(*state.get_value(total).unwrap() == Fd::new_range(0, 9));
+    state.add_constraint(FdLessOrEqual::new(Fd::new_single(8), total));
+    // only 2 + 3 + 4 reaches 8
+    assert!(vars.iter().all(|&var| state.get_value(var) == Some(&true)));
+    assert!(state.get_value(total).unwrap().single_value() == Some(9));
+    state.unify(total, Fd::new_range(7, 8));
+    assert!(!state.ok());
+
+    // knapsack: every labeling which survives fits, and none that fit are lost
+    fn label(state: State, vars: Vec<Var<bool>>) -> ::kanren::iter::StateIter {
+        vars.into_iter().fold(single(state), |iter, var| {
+            iter.and(move |state| conde!(state, {
+                state.unify(var, false);
+                state
+            }, {
+                state.unify(var, true);
+                state
+            }))
+        })
+    }
+    let weights = vec![7, 3, 5, 2, 6];
+    let mut state = State::new();
+    let vars: Vec<Var<bool>> = (0..5).map(|_| state.make_var()).collect();
+    state.add_constraint(PseudoBoolean::at_most(weights.iter().cloned().zip(vars.iter().cloned()).collect(), 10));
+    let found = label(state, vars.clone()).into_iter().map(|state| {
+        let total: i64 = weights.iter().zip(vars.iter()).filter(|&(_, &var)| *state.get_value(var).unwrap()).map(|(w, _)| w).sum();
+        assert!(total <= 10);
+    }).count();
+    let expected = (0..32).filter(|&bits: &u32| {
+        weights.iter().enumerate().filter(|&(i, _)| bits & (1 << i) != 0).map(|(_, w)| w).sum::<i64>() <= 10
+    }).count();
+    assert!(found == expected);
+}
+
+#[test]
+fn generic_domain() {
+    use kanren::domain::Domain;
+    let a = Domain::new(vec!["c", "a", "b", "a"]);
+    assert!(a.iter().cloned().collect::<Vec<_>>() == vec!["a", "b", "c"]);
+    let b = Domain::new(vec!["b", "c", "d"]);
+    assert!(a.combine(&b) == Domain::new(vec!["b", "c"]));
+    assert!(a.union(&b) == Domain::new(vec!["a", "b", "c", "d"]));
+    assert!(a.difference(&b) == Domain::new_single("a"));
+    assert!(a.difference(&b).single_value() == Some(&"a"));
+    assert!(!a.difference(&a).is_valid());
+
+    let mut state = State::new();
+    let x = state.make_var_of(a.clone());
+    state.unify(x, b.clone());
+    assert!(*state.get_value(x).unwrap() == Domain::new(vec!["b", "c"]));
+    state.unify(x, Domain::new_single("a"));
+    assert!(!state.ok());
+
+    let mut state = State::new();
+    let x = state.make_var_of(a.clone());
+    let values: Vec<&str> = assign_all_values(state).into_iter().map(|state| {
+        *state.get_value(x).unwrap().single_value().unwrap()
+    }).collect();
+    assert!(values == vec!["a", "b", "c"]);
+}
+
+#[test]
+fn generic_domain_alldiff() {
+    use kanren::domain::Domain;
+    use kanren::constraints::DomainAllDiff;
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    enum Color { Red, Green, Blue }
+    let (red, green, blue) = (Color::Red, Color::Green, Color::Blue);
+
+    let mut state = State::new();
+    let vars: Vec<Var<Domain<Color>>> = (0..3).map(|_| state.make_var_of(Domain::new(vec![red, green, blue]))).collect();
+    state.add_constraint(DomainAllDiff::new(vars.clone()));
+    state.unify(vars[0], Domain::new_single(red));
+    assert!(*state.get_value(vars[1]).unwrap() == Domain::new(vec![green, blue]));
+    state.unify(vars[1], Domain::new(vec![red, green]));
+    assert!(state.get_value(vars[2]).unwrap().single_value() == Some(&blue));
+
+    let mut state = State::new();
+    let vars: Vec<Var<Domain<Color>>> = (0..3).map(|_| state.make_var_of(Domain::new(vec![red, green, blue]))).collect();
+    state.add_constraint(DomainAllDiff::new(vars.clone()));
+    let solutions: HashSet<Vec<Color>> = assign_all_values(state).into_iter().map(|state| {
+        vars.iter().map(|&var| *state.get_value(var).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    assert!(solutions.len() == 6);
+
+    // two variables can't share two values with a third
+    let mut state = State::new();
+    let vars: Vec<Var<Domain<Color>>> = (0..3).map(|_| state.make_var_of(Domain::new(vec![red, green]))).collect();
+    state.add_constraint(DomainAllDiff::new(vars.clone()));
+    assert!(!state.ok());
+}
+
+#[test]
+fn derive_finite_domain() {
+    use kanren::finitedomain::FiniteDomain;
+    use kanren::constraints::{FdNotEqual, FdEnumConstraint};
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FiniteDomain)]
+    enum Color { Red, Green, Blue }
+
+    assert!(Color::count() == 3);
+    assert!(Color::Blue.to_index() == 2);
+    assert!(Color::from_index(1) == Some(Color::Green));
+    assert!(Color::from_index(3).is_none());
+    assert!(Color::domain() == Fd::new_range(0, 2));
+    assert!(Color::domain_of(&[Color::Blue, Color::Red]) == Fd::new_values(vec![0, 2]));
+
+    assert!(Color::values_of(&Fd::new_range(-5, 1)) == vec![Color::Red, Color::Green]);
+
+    // a Var<Color> holds a Color, and is linked to the Fd of its index to use the Fd constraints
+    let mut state = State::new();
+    let colors: Vec<Var<Color>> = (0..3).map(|_| state.make_var()).collect();
+    let fds: Vec<Var<Fd>> = colors.iter().map(|&color| {
+        let fd = state.make_var();
+        state.add_constraint(FdEnumConstraint::new(fd, color));
+        fd
+    }).collect();
+    assert!(*state.get_value(fds[0]).unwrap() == Color::domain());
+    state.add_constraint(AllDiffConstraint::new(fds.clone()));
+    state.add_constraint(FdNotEqual::new(fds[0], Color::domain_of(&[Color::Red])));
+    state.unify(colors[1], Color::Green);
+    assert!(state.get_value(colors[0]) == Some(&Color::Blue));
+    assert!(state.get_value(colors[2]) == Some(&Color::Red));
+
+    let mut state = State::new();
+    let color = state.make_var();
+    let fd = state.make_var_of(Color::domain_of(&[Color::Green, Color::Blue]));
+    state.add_constraint(FdEnumConstraint::new(fd, color));
+    state.unify(color, Color::Red);
+    assert!(!state.ok());
+
+    let mut state = State::new();
+    let colors: Vec<Var<Color>> = (0..3).map(|_| state.make_var()).collect();
+    let fds: Vec<Var<Fd>> = colors.iter().map(|&color| {
+        let fd = state.make_var();
+        state.add_constraint(FdEnumConstraint::new(fd, color));
+        fd
+    }).collect();
+    state.add_constraint(AllDiffConstraint::new(fds));
+    let solutions: HashSet<Vec<Color>> = assign_all_values(state).into_iter().map(|state| {
+        colors.iter().map(|&var| *state.get_value(var).unwrap()).collect()
+    }).collect();
+    assert!(solutions.len() == 6);
+}
+
+#[test]
+fn labeling_values() {
+    use kanren::core::{assign_values_with, Labeling, ValueSelection};
+    fn order(values: ValueSelection, bisect: bool) -> Vec<i64> {
+        let mut state = State::new();
+        let x = state.make_var_of(Fd::new_range(1, 5));
+        let labeling = Labeling::new().values(values).bisect(bisect);
+        assign_values_with(state, vec![x.untyped()], labeling).into_iter().map(|state| {
+            state.get_value(x).unwrap().single_value().unwrap()
+        }).collect()
+    }
+    assert!(order(ValueSelection::Ascending, false) == vec![1, 2, 3, 4, 5]);
+    assert!(order(ValueSelection::Descending, false) == vec![5, 4, 3, 2, 1]);
+    assert!(order(ValueSelection::MedianSplit, false) == vec![3, 2, 4, 1, 5]);
+
+    let shuffled = order(ValueSelection::Random(7), false);
+    assert!(shuffled == order(ValueSelection::Random(7), false));
+    let mut sorted = shuffled.clone();
+    sorted.sort();
+    assert!(sorted == vec![1, 2, 3, 4, 5]);
+
+    // the halves are searched together, so only the values themselves are fixed
+    for &values in [ValueSelection::Ascending, ValueSelection::Descending, ValueSelection::Random(7)].iter() {
+        let mut bisected = order(values, true);
+        bisected.sort();
+        assert!(bisected == vec![1, 2, 3, 4, 5]);
+    }
+
+    // wide domains are tried lazily, and ones too wide to reorder fall back to ascending
+    fn first_two(values: ValueSelection, backjumping: bool) -> Vec<i64> {
+        let mut state = State::new();
+        let x = state.make_var_of(Fd::new_intervals(vec![(0, 1 << 40), (1 << 50, (1 << 50) + 1)]));
+        let labeling = Labeling::new().values(values).backjumping(backjumping);
+        assign_values_with(state, vec![x.untyped()], labeling).into_iter().take(2).map(|state| {
+            state.get_value(x).unwrap().single_value().unwrap()
+        }).collect()
+    }
+    for &backjumping in [false, true].iter() {
+        assert!(first_two(ValueSelection::Ascending, backjumping) == vec![0, 1]);
+        assert!(first_two(ValueSelection::Descending, backjumping) == vec![(1 << 50) + 1, 1 << 50]);
+        assert!(first_two(ValueSelection::MedianSplit, backjumping) == vec![0, 1]);
+        assert!(first_two(ValueSelection::Random(7), backjumping) == vec![0, 1]);
+    }
+}
+
+#[test]
+fn labeling_variables() {
+    use kanren::core::{assign_values_with, assign_all_values_with, Labeling, VarSelection};
+    // The variable chosen first changes least often in the solutions.
+    fn solutions(state: State, x: Var<Fd>, y: Var<Fd>, variables: VarSelection) -> Vec<(i64, i64)> {
+        let labeling = Labeling::new().variables(variables);
+        assign_values_with(state, vec![x.untyped(), y.untyped()], labeling).into_iter().map(|state| {
+            (state.get_value(x).unwrap().single_value().unwrap(), state.get_value(y).unwrap().single_value().unwrap())
+        }).collect()
+    }
+
+    fn smaller_y() -> (State, Var<Fd>, Var<Fd>) {
+        let mut state = State::new();
+        let x = state.make_var_of(Fd::new_range(1, 3));
+        let y = state.make_var_of(Fd::new_range(1, 2));
+        (state, x, y)
+    }
+    let (state, x, y) = smaller_y();
+    assert!(solutions(state, x, y, VarSelection::InputOrder) == vec![(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (3, 2)]);
+    let (state, x, y) = smaller_y();
+    assert!(solutions(state, x, y, VarSelection::FirstFail) == vec![(1, 1), (2, 1), (3, 1), (1, 2), (2, 2), (3, 2)]);
+
+    fn constrained_y() -> (State, Var<Fd>, Var<Fd>) {
+        let mut state = State::new();
+        let x = state.make_var_of(Fd::new_range(1, 2));
+        let y = state.make_var_of(Fd::new_range(1, 2));
+        let z = state.make_var_of(Fd::new_range(0, 9));
+        state.add_constraint(FdLessOrEqual::new(y, z));
+        (state, x, y)
+    }
+    let (state, x, y) = constrained_y();
+    assert!(solutions(state, x, y, VarSelection::FirstFail) == vec![(1, 1), (1, 2), (2, 1), (2, 2)]);
+    let (state, x, y) = constrained_y();
+    assert!(solutions(state, x, y, VarSelection::MostConstrained) == vec![(1, 1), (2, 1), (1, 2), (2, 2)]);
+
+    // {1, 5, 6} has a larger gap between its smallest values than {1, 2}
+    let mut state = State::new();
+    let x = state.make_var_of(Fd::new_range(1, 2));
+    let y = state.make_var_of(Fd::new_values(vec![1, 5, 6]));
+    assert!(solutions(state, x, y, VarSelection::MaxRegret) == vec![(1, 1), (2, 1), (1, 5), (2, 5), (1, 6), (2, 6)]);
+
+    // every strategy finds the same solutions
+    let mut expected = None;
+    for &variables in [VarSelection::InputOrder, VarSelection::FirstFail, VarSelection::MostConstrained, VarSelection::MaxRegret].iter() {
+        for &bisect in [false, true].iter() {
+            let mut state = State::new();
+            let vars: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(1, 4))).collect();
+            state.add_constraint(AllDiffConstraint::new(vars.clone()));
+            state.add_constraint(FdLessThan::new(vars[0], vars[3]));
+            let labeling = Labeling::new().variables(variables).bisect(bisect);
+            let solutions: HashSet<Vec<i64>> = assign_all_values_with(state, labeling).into_iter().map(|state| {
+                vars.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap()).collect()
+            }).collect();
+            assert!(solutions.len() == 12);
+            match expected {
+                None => { expected = Some(solutions); },
+                Some(ref expected) => assert!(*expected == solutions),
+            }
+        }
+    }
+}
+
+#[test]
+fn labeling_backjumping() {
+    use kanren::core::{assign_values_with, assign_all_values_with, Labeling, VarSelection};
+    use kanren::domain::Domain;
+    // the same solutions are found with and without backjumping, and other variables still get
+    // labeled afterwards
+    let mut expected = None;
+    for &(backjumping, nogoods) in [(false, false), (true, false), (false, true)].iter() {
+        for &variables in [VarSelection::InputOrder, VarSelection::FirstFail].iter() {
+            let mut state = State::new();
+            let vars: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(1, 4))).collect();
+            let letter = state.make_var_of(Domain::new(vec!['a', 'b']));
+            state.add_constraint(AllDiffConstraint::with_propagation(vars.clone(), AllDiffPropagation::Value));
+            state.add_constraint(FdLessThan::new(vars[0], vars[3]));
+            let labeling = Labeling::new().variables(variables).backjumping(backjumping).nogoods(nogoods);
+            let solutions: HashSet<(Vec<i64>, char)> = assign_all_values_with(state, labeling).into_iter().map(|state| {
+                let values = vars.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap()).collect();
+                (values, *state.get_value(letter).unwrap().single_value().unwrap())
+            }).collect();
+            assert!(solutions.len() == 24);
+            match expected {
+                None => { expected = Some(solutions); },
+                Some(ref expected) => assert!(*expected == solutions),
+            }
+        }
+    }
+
+    // Three variables can't take two values, whatever the six before them are.  Backtracking
+    // would try all million of those; backjumping gives up after the first.
+    for &nogoods in [false, true].iter() {
+        let mut state = State::new();
+        let free: Vec<Var<Fd>> = (0..6).map(|_| state.make_var_of(Fd::new_range(0, 9))).collect();
+        let pigeons: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(1, 2))).collect();
+        state.add_constraint(AllDiffConstraint::with_propagation(pigeons.clone(), AllDiffPropagation::Value));
+        let vars = free.iter().chain(pigeons.iter()).map(|var| var.untyped());
+        let labeling = Labeling::new().variables(VarSelection::InputOrder).nogoods(nogoods).backjumping(true);
+        assert!(assign_values_with(state, vars, labeling).into_iter().next().is_none());
+    }
+}
+
+#[test]
+fn branch_and_bound() {
+    use kanren::optimize::{minimize, maximize, minimize_iter};
+    // a + b = total, with a, b and c all different and c < a
+    fn setup() -> (State, Vec<Var<Fd>>, Var<Fd>) {
+        let mut state = State::new();
+        let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(1, 9))).collect();
+        let total = state.make_var_of(Fd::new_range(0, 100));
+        state.add_constraint(AllDiffConstraint::new(vars.clone()));
+        state.add_constraint(FdSumConstraint::new(vars[0], vars[1], total));
+        state.add_constraint(FdLessThan::new(vars[2], vars[0]));
+        (state, vars, total)
+    }
+
+    let (state, vars, total) = setup();
+    let best = minimize(state, total, single).unwrap();
+    assert!(best.get_value(total).unwrap().single_value() == Some(4));
+    assert!(best.get_value(vars[0]).unwrap().single_value() == Some(3));
+
+    let (state, _, total) = setup();
+    let best = maximize(state, total, single).unwrap();
+    assert!(best.get_value(total).unwrap().single_value() == Some(17));
+
+    let (state, _, total) = setup();
+    let mut improvements = minimize_iter(state, total, single);
+    let totals: Vec<i64> = improvements.by_ref().map(|state| state.get_value(total).unwrap().single_value().unwrap()).collect();
+    assert!(totals.windows(2).all(|pair| pair[0] > pair[1]));
+    assert!(totals.last() == Some(&4));
+    assert!(improvements.best() == Some(4));
+
+    // the goal's own choices are searched too, and a goal with no solutions has no optimum
+    let (state, vars, total) = setup();
+    let a = vars[0];
+    let best = maximize(state, total, move |state| conde!(state, {
+        state.unify(a, Fd::new_range(1, 5));
+        state
+    }, {
+        state.unify(a, Fd::new_range(3, 6));
+        state
+    })).unwrap();
+    assert!(best.get_value(total).unwrap().single_value() == Some(15));
+    let (state, _, total) = setup();
+    assert!(minimize(state, total, move |mut state| { state.unify(a, Fd::new_single(1)); single(state) }).is_none());
+}
+
+#[test]
+fn fd_disequal_pruning() {
+    use kanren::domain::Domain;
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(1, 5));
+    let b = state.make_var_of(Fd::new_range(1, 5));
+    state.add_constraint(Disequal::new(a, b));
+    state.unify(a, Fd::new_single(3));
+    assert!(state.ok());
+    assert!(*state.get_value(b).unwrap() == Fd::new_values(vec![1, 2, 4, 5]));
+
+    // a value known when the constraint is added is removed right away
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(3, 4));
+    state.add_constraint(Disequal::new(a, Fd::new_single(3)));
+    assert!(state.get_value(a).unwrap().single_value() == Some(4));
+    state.add_constraint(Disequal::new(Fd::new_single(4), a));
+    assert!(!state.ok());
+
+    let mut state = State::new();
+    let a = state.make_var_of(Domain::new(vec!['x', 'y', 'z']));
+    let b = state.make_var_of(Domain::new(vec!['x', 'y']));
+    state.add_constraint(Disequal::new(a, b));
+    state.unify(b, Domain::new_single('y'));
+    assert!(*state.get_value(a).unwrap() == Domain::new(vec!['x', 'z']));
+
+    // [x, 1] != [2, y] can be satisfied by either element, so neither is narrowed
+    let mut state = State::new();
+    let (x, y) = (state.make_var_of(Fd::new_range(1, 2)), state.make_var_of(Fd::new_range(1, 2)));
+    let (one, two) = (state.make_var_of(Fd::new_single(1)), state.make_var_of(Fd::new_single(2)));
+    state.add_constraint(Disequal::new(Pair(x, Pair(one, Nil)), Pair(two, Pair(y, Nil))));
+    assert!(state.ok());
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(1, 2));
+    assert!(*state.get_value(y).unwrap() == Fd::new_range(1, 2));
+}
+
+#[test]
+fn element() {
+    use kanren::constraints::Element;
+    let prices: Vec<Fd> = [10, 20, 20, 30, 50].iter().map(|&x| Fd::new_single(x)).collect();
+    let mut state = State::new();
+    let choice = state.make_var_of(Fd::new_range(0, 9));
+    let cost = state.make_var();
+    state.add_constraint(Element::new(choice, prices.clone(), cost));
+    assert!(*state.get_value(choice).unwrap() == Fd::new_range(0, 4));
+    assert!(*state.get_value(cost).unwrap() == Fd::new_values(vec![10, 20, 30, 50]));
+    state.unify(cost, Fd::new_range(0, 25));
+    assert!(*state.get_value(choice).unwrap() == Fd::new_range(0, 2));
+    state.unify(choice, Fd::new_range(1, 9));
+    assert!(state.get_value(cost).unwrap().single_value() == Some(20));
+    state.unify(cost, Fd::new_single(30));
+    assert!(!state.ok());
+
+    // with variable elements, a known index links that element to the value
+    let mut state = State::new();
+    let index = state.make_var_of(Fd::new_range(0, 1));
+    let array = vec![state.make_var_of(Fd::new_range(1, 3)), state.make_var_of(Fd::new_range(5, 6))];
+    let value = state.make_var_of(Fd::new_range(0, 9));
+    state.add_constraint(Element::new(index, array.clone(), value));
+    assert!(*state.get_value(value).unwrap() == Fd::new_values(vec![1, 2, 3, 5, 6]));
+    state.unify(value, Fd::new_range(4, 5));
+    assert!(state.get_value(index).unwrap().single_value() == Some(1));
+    assert!(state.get_value(array[1]).unwrap().single_value() == Some(5));
+    assert!(*state.get_value(array[0]).unwrap() == Fd::new_range(1, 3));
+
+    // labeling gives exactly one solution per (index, element) pair
+    let mut state = State::new();
+    let index = state.make_var();
+    let array: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Element::new(index, array.clone(), Fd::new_single(1)));
+    let solutions: Vec<State> = assign_all_values(state).into_iter().collect();
+    assert!(solutions.len() == 3 * 9);
+    assert!(solutions.iter().all(|state| {
+        let index = state.get_value(index).unwrap().single_value().unwrap();
+        state.get_value(array[index as usize]).unwrap().single_value() == Some(1)
+    }));
+}
+
+#[test]
+fn table() {
+    use kanren::constraints::Table;
+    let compatible = vec![vec![0, 1, 2], vec![0, 2, 1], vec![1, 0, 0], vec![2, 2, 2]];
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(Table::new(vars.clone(), compatible.clone()));
+    assert!(*state.get_value(vars[0]).unwrap() == Fd::new_range(0, 2));
+    state.unify(vars[1], Fd::new_range(1, 2));
+    assert!(*state.get_value(vars[0]).unwrap() == Fd::new_values(vec![0, 2]));
+    assert!(*state.get_value(vars[2]).unwrap() == Fd::new_range(1, 2));
+    state.unify(vars[2], Fd::new_single(1));
+    assert!(state.get_value(vars[0]).unwrap().single_value() == Some(0));
+    assert!(state.get_value(vars[1]).unwrap().single_value() == Some(2));
+
+    // labeling finds exactly the allowed tuples, without the table branching
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Table::new(vars.clone(), compatible.clone()));
+    let solutions: HashSet<Vec<i64>> = assign_all_values(state).into_iter().map(|state| {
+        vars.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    assert!(solutions == compatible.iter().cloned().collect());
+
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Table::forbidden(vars.clone(), compatible.clone()));
+    let solutions: HashSet<Vec<i64>> = assign_all_values(state).into_iter().map(|state| {
+        vars.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    assert!(solutions.len() == 27 - 4);
+    assert!(solutions.iter().all(|tuple| !compatible.contains(tuple)));
+
+    // a value is removed once every combination including it is forbidden
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(0, 2));
+    let b = state.make_var_of(Fd::new_range(0, 1));
+    state.add_constraint(Table::forbidden(vec![a, b], vec![vec![1, 0], vec![1, 1], vec![2, 0], vec![1, 0]]));
+    assert!(*state.get_value(a).unwrap() == Fd::new_values(vec![0, 2]));
+    state.unify(a, Fd::new_single(2));
+    assert!(state.get_value(b).unwrap().single_value() == Some(1));
+    let c = state.make_var_of(Fd::new_range(0, 1));
+    state.add_constraint(Table::new(vec![b, c], vec![vec![0, 0], vec![0, 1]]));
+    assert!(!state.ok());
+}
+
+#[test]
+fn circuit() {
+    use kanren::constraints::Circuit;
+    let mut state = State::new();
+    let succ: Vec<Var<Fd>> = (0..4).map(|_| state.make_var()).collect();
+    state.add_constraint(Circuit::new(succ.clone()));
+    assert!(*state.get_value(succ[0]).unwrap() == Fd::new_range(1, 3));
+    // 0 -> 1 -> 2 can't go back to 0 yet
+    state.unify(succ[0], Fd::new_single(1));
+    state.unify(succ[1], Fd::new_single(2));
+    assert!(state.ok());
+    assert!(state.get_value(succ[2]).unwrap().single_value() == Some(3));
+    assert!(state.get_value(succ[3]).unwrap().single_value() == Some(0));
+
+    let mut state = State::new();
+    let succ: Vec<Var<Fd>> = (0..4).map(|_| state.make_var()).collect();
+    state.add_constraint(Circuit::new(succ.clone()));
+    state.unify(succ[0], Fd::new_single(1));
+    assert!(!state.get_value(succ[1]).unwrap().in_range(0));
+    state.unify(succ[2], Fd::new_single(3));
+    assert!(state.get_value(succ[1]).unwrap().single_value() == Some(2));
+    assert!(state.get_value(succ[3]).unwrap().single_value() == Some(0));
+
+    // a 5-node circuit has (5 - 1)! orderings
+    let mut state = State::new();
+    let succ: Vec<Var<Fd>> = (0..5).map(|_| state.make_var_of(Fd::new_range(0, 4))).collect();
+    state.add_constraint(Circuit::new(succ.clone()));
+    let tours: Vec<Vec<usize>> = assign_all_values(state).into_iter().map(|state| {
+        succ.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap() as usize).collect()
+    }).collect();
+    assert!(tours.len() == 24);
+    for tour in tours.iter() {
+        let (mut node, mut len) = (tour[0], 1);
+        while node != 0 {
+            node = tour[node];
+            len += 1;
+        }
+        assert!(len == 5);
+    }
+}
+
+#[test]
+fn scheduling() {
+    use kanren::constraints::{Cumulative, Disjunctive};
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(0, 4));
+    let b = state.make_var_of(Fd::new_range(0, 4));
+    state.add_constraint(Disjunctive::new(vec![(a, 3), (b, 2)]));
+    state.unify(a, Fd::new_single(0));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(3, 4));
+    state.unify(b, Fd::new_range(0, 3));
+    assert!(state.ok());
+    state.unify(b, Fd::new_range(0, 2));
+    assert!(!state.ok());
+
+    // three jobs taking six time units can't fit into five
+    let mut state = State::new();
+    let jobs: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 3))).collect();
+    state.add_constraint(Disjunctive::new(jobs.iter().map(|&job| (job, 2)).collect()));
+    assert!(!state.ok());
+
+    // the compulsory part of a, from 1 to 4, leaves no room for b
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(0, 1));
+    let b = state.make_var_of(Fd::new_range(0, 6));
+    let c = state.make_var_of(Fd::new_range(0, 6));
+    state.add_constraint(Cumulative::new(vec![(a, 4, 2), (b, 2, 1), (c, 2, 1)], 2));
+    assert!(*state.get_value(b).unwrap() == Fd::new_range(4, 6));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(4, 6));
+    assert!(*state.get_value(a).unwrap() == Fd::new_range(0, 1));
+
+    // windows spanning most of the i64 range have room to spare
+    let mut state = State::new();
+    let a = state.make_var_of(Fd::new_range(::std::i64::MIN, ::std::i64::MAX - 10));
+    let b = state.make_var_of(Fd::new_range(0, 5));
+    state.add_constraint(Cumulative::new(vec![(a, 1, 1), (b, 1, 1)], 1));
+    assert!(state.ok());
+    state.unify(b, Fd::new_single(0));
+    assert!(state.ok());
+    assert!(!state.get_value(a).unwrap().in_range(0));
+
+    // jobs packed into exactly their total duration can only be reordered
+    let mut state = State::new();
+    let durations = [2, 3, 1];
+    let jobs: Vec<Var<Fd>> = durations.iter().map(|&d| state.make_var_of(Fd::new_range(0, 6 - d))).collect();
+    state.add_constraint(Disjunctive::new(jobs.iter().cloned().zip(durations.iter().cloned()).collect()));
+    let schedules: Vec<State> = assign_all_values(state).into_iter().collect();
+    assert!(schedules.len() == 6);
+
+    let mut state = State::new();
+    let jobs: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Cumulative::new(jobs.iter().map(|&job| (job, 2, 1)).collect(), 2));
+    let schedules: Vec<State> = assign_all_values(state).into_iter().collect();
+    assert!(schedules.len() == 6);
+    for state in schedules.into_iter() {
+        let starts: Vec<i64> = jobs.iter().map(|&job| state.get_value(job).unwrap().single_value().unwrap()).collect();
+        for time in 0..4 {
+            assert!(starts.iter().filter(|&&start| start <= time && time < start + 2).count() <= 2);
+        }
+    }
+}
+
+#[test]
+fn global_cardinality() {
+    use kanren::constraints::{GlobalCardinality, Count};
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(1, 3))).collect();
+    let n = state.make_var();
+    state.add_constraint(Count::new(vars.clone(), 2, n));
+    assert!(*state.get_value(n).unwrap() == Fd::new_range(0, 4));
+    state.unify(vars[0], Fd::new_single(2));
+    state.unify(vars[1], Fd::new_single(1));
+    assert!(*state.get_value(n).unwrap() == Fd::new_range(1, 3));
+    state.unify(n, Fd::new_single(3));
+    assert!(state.get_value(vars[3]).unwrap().single_value() == Some(2));
+
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(1, 3))).collect();
+    state.add_constraint(Count::new(vars.clone(), 2, Fd::new_single(1)));
+    state.unify(vars[2], Fd::new_single(2));
+    assert!(vars.iter().enumerate().all(|(i, &var)| i == 2 || *state.get_value(var).unwrap() == Fd::new_values(vec![1, 3])));
+
+    // two 1s and two 2s leave room for at most one 3
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..5).map(|_| state.make_var_of(Fd::new_range(1, 3))).collect();
+    let (ones, twos, threes) = (state.make_var_of(Fd::new_single(2)), state.make_var_of(Fd::new_single(2)), state.make_var());
+    state.add_constraint(GlobalCardinality::new(vars.clone(), vec![(1, ones), (2, twos), (3, threes)]));
+    assert!(*state.get_value(threes).unwrap() == Fd::new_range(0, 1));
+    assert!(assign_all_values(state).into_iter().count() == 30);
+
+    // with every count at most 1, it's an AllDiffConstraint
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(1, 3))).collect();
+    state.add_constraint(GlobalCardinality::new(vars.clone(), (1..4).map(|x| (x, Fd::new_range(0, 1))).collect()));
+    let solutions: Vec<State> = assign_all_values(state).into_iter().collect();
+    assert!(solutions.len() == 6);
+
+    // a value listed twice has both counts unified
+    let mut state = State::new();
+    fresh!(state, x, y, c1, c2);
+    state.add_constraint(GlobalCardinality::new(vec![x, y], vec![(1, c1), (1, c2)]));
+    state.unify(x, Fd::new_single(1));
+    state.unify(y, Fd::new_single(1));
+    assert!(state.ok());
+    assert!(state.get_value(c1).unwrap().single_value() == Some(2));
+    assert!(state.get_value(c2).unwrap().single_value() == Some(2));
+
+    // variables without a domain still count towards the values they could take
+    let mut state = State::new();
+    fresh!(state, x, y, z);
+    state.add_constraint(Count::new(vec![x, y, z], 5, Fd::new_single(2)));
+    state.unify(y, Fd::new_single(5));
+    assert!(state.get_value(x).is_none() && state.get_value(z).is_none());
+    state.unify(z, Fd::new_single(4));
+    assert!(state.get_value(x).unwrap().single_value() == Some(5));
+
+    let mut state = State::new();
+    fresh!(state, x, y, c);
+    state.add_constraint(Count::new(vec![x, y], 3, c));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(0, 2));
+    state.unify(x, Fd::new_single(3));
+    assert!(*state.get_value(c).unwrap() == Fd::new_range(1, 2));
+    state.unify(c, Fd::new_single(1));
+    assert!(!state.get_value(y).unwrap().in_range(3) && state.get_value(y).unwrap().in_range(4));
+}
+
+#[test]
+fn regex_dfa() {
+    use kanren::automaton::Dfa;
+    let dfa = Dfa::from_regex("([01]|2[01]|22[01])*(2|22)?").unwrap();
+    assert!(dfa.accepts(&[]));
+    assert!(dfa.accepts(&[2, 2, 0, 1, 2]));
+    assert!(!dfa.accepts(&[0, 2, 2, 2]));
+    assert!(!dfa.accepts(&[3]));
+
+    let dfa = Dfa::from_regex("<-1>+ (<10> | [3-5]?)").unwrap();
+    assert!(dfa.accepts(&[-1, -1, 10]));
+    assert!(dfa.accepts(&[-1, 4]));
+    assert!(dfa.accepts(&[-1]));
+    assert!(!dfa.accepts(&[10]));
+    assert!(!dfa.accepts(&[-1, 1]));
+
+    assert!(Dfa::from_regex("(01").is_err());
+    assert!(Dfa::from_regex("0)").is_err());
+    assert!(Dfa::from_regex("[5-3]").is_err());
+    assert!(Dfa::from_regex("[0-<1000000000000>]").is_err());
+    assert!(Dfa::from_regex("[<-9223372036854775808>-<9223372036854775807>]").is_err());
+    let top = Dfa::from_regex("[<9223372036854775806>-<9223372036854775807>]").unwrap();
+    assert!(top.accepts(&[::std::i64::MAX]) && top.accepts(&[::std::i64::MAX - 1]) && !top.accepts(&[0]));
+    assert!(Dfa::from_regex("a").is_err());
+
+    let dfa = Dfa::new(2, 0, &[1]).transition(0, 7, 1).transition(1, 7, 0);
+    assert!(dfa.accepts(&[7, 7, 7]));
+    assert!(!dfa.accepts(&[7, 7]));
+}
+
+#[test]
+fn regular() {
+    use kanren::automaton::Dfa;
+    use kanren::constraints::Regular;
+    // 0 is a day off, 1 a day shift, 2 a night shift; no more than two nights in a row
+    let shifts = Dfa::from_regex("([01]|2[01]|22[01])*(2|22)?").unwrap();
+    let mut state = State::new();
+    let days: Vec<Var<Fd>> = (0..5).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Regular::new(days.clone(), shifts.clone()));
+    state.unify(days[0], Fd::new_single(2));
+    state.unify(days[1], Fd::new_single(2));
+    assert!(*state.get_value(days[2]).unwrap() == Fd::new_range(0, 1));
+    assert!(*state.get_value(days[3]).unwrap() == Fd::new_range(0, 2));
+
+    let mut state = State::new();
+    let days: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(0, 2))).collect();
+    state.add_constraint(Regular::new(days.clone(), shifts.clone()));
+    let rosters: Vec<Vec<i64>> = assign_all_values(state).into_iter().map(|state| {
+        days.iter().map(|&day| state.get_value(day).unwrap().single_value().unwrap()).collect()
+    }).collect();
+    // 81 sequences, less the 2 * 3 with three nights starting at day 0 or 1, plus 2222 counted twice
+    assert!(rosters.len() == 81 - 6 + 1);
+    assert!(rosters.iter().all(|roster| shifts.accepts(roster)));
+
+    // a sequence needing an exact length fails when it's too short
+    let mut state = State::new();
+    let days: Vec<Var<Fd>> = (0..2).map(|_| state.make_var()).collect();
+    state.add_constraint(Regular::new(days.clone(), Dfa::from_regex("123").unwrap()));
+    assert!(!state.ok());
+    let mut state = State::new();
+    let days: Vec<Var<Fd>> = (0..3).map(|_| state.make_var()).collect();
+    state.add_constraint(Regular::new(days.clone(), Dfa::from_regex("12[34]").unwrap()));
+    assert!(state.get_value(days[1]).unwrap().single_value() == Some(2));
+    assert!(*state.get_value(days[2]).unwrap() == Fd::new_range(3, 4));
+}
+
+#[test]
+fn lex_ordering() {
+    use kanren::constraints::{LexLessEq, LexChain};
+    let mut state = State::new();
+    let a: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 5))).collect();
+    let b: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 5))).collect();
+    state.add_constraint(LexLessEq::new(a.clone(), b.clone()));
+    state.unify(b[0], Fd::new_range(0, 2));
+    assert!(*state.get_value(a[0]).unwrap() == Fd::new_range(0, 2));
+    state.unify(a[0], Fd::new_single(2));
+    assert!(state.get_value(b[0]).unwrap().single_value() == Some(2));
+    // with a[2] > b[2], the middle pair has to break the tie
+    state.unify(a[2], Fd::new_single(4));
+    state.unify(b[2], Fd::new_single(3));
+    assert!(*state.get_value(a[1]).unwrap() == Fd::new_range(0, 4));
+    assert!(*state.get_value(b[1]).unwrap() == Fd::new_range(1, 5));
+    state.unify(b[1], Fd::new_single(1));
+    assert!(state.get_value(a[1]).unwrap().single_value() == Some(0));
+
+    let mut state = State::new();
+    state.add_constraint(LexLessEq::new(vec![Fd::new_single(1), Fd::new_single(2)], vec![Fd::new_single(1), Fd::new_single(1)]));
+    assert!(!state.ok());
+
+    // reversing the board maps n-queens solutions onto each other, so only half are kept
+    let n = 6;
+    let mut state = State::new();
+    let queens: Vec<Var<Fd>> = (0..n).map(|_| state.make_var_of(Fd::new_range(0, n - 1))).collect();
+    state.add_constraint(AllDiffConstraint::new(queens.clone()));
+    for i in 0..n {
+        for j in (i + 1)..n {
+            fresh!(state, diff, absdiff);
+            state.unify(diff, Fd::new_range(1 - n, n - 1));
+            state.unify(absdiff, Fd::new_range(1, n - 1).difference(&Fd::new_single(j - i)));
+            state.add_constraint(FdDifferenceConstraint::new(queens[i as usize], queens[j as usize], diff));
+            state.add_constraint(FdAbsConstraint::new(diff, absdiff));
+        }
+    }
+    let reversed: Vec<Var<Fd>> = queens.iter().rev().cloned().collect();
+    state.add_constraint(LexLessEq::new(queens.clone(), reversed));
+    assert!(assign_all_values(state).into_iter().count() == 2);
+
+    // interchangeable rows are enumerated once each, in sorted order
+    let mut state = State::new();
+    let rows: Vec<Vec<Var<Fd>>> = (0..3).map(|_| (0..2).map(|_| state.make_var_of(Fd::new_range(0, 1))).collect()).collect();
+    state.add_constraint(LexChain::new(rows.clone()));
+    let solutions: Vec<Vec<Vec<i64>>> = assign_all_values(state).into_iter().map(|state| {
+        rows.iter().map(|row| row.iter().map(|&var| state.get_value(var).unwrap().single_value().unwrap()).collect()).collect()
+    }).collect();
+    // multisets of 3 rows from 4 possible rows
+    assert!(solutions.len() == 20);
+    assert!(solutions.iter().all(|rows| rows.windows(2).all(|pair| pair[0] <= pair[1])));
+}
+
+#[test]
+fn channeling() {
+    use kanren::constraints::{Inverse, BoolChannel, FdI32Constraint};
+    let mut state = State::new();
+    let f: Vec<Var<Fd>> = (0..3).map(|_| state.make_var()).collect();
+    let g: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 9))).collect();
+    state.add_constraint(Inverse::new(f.clone(), g.clone()));
+    assert!(*state.get_value(g[0]).unwrap() == Fd::new_range(0, 2));
+    state.unify(f[0], Fd::new_single(2));
+    assert!(state.get_value(g[2]).unwrap().single_value() == Some(0));
+    assert!(*state.get_value(g[1]).unwrap() == Fd::new_range(1, 2));
+    state.unify(g[1], Fd::new_single(2));
+    assert!(state.get_value(f[2]).unwrap().single_value() == Some(1));
+
+    let mut state = State::new();
+    let f: Vec<Var<Fd>> = (0..4).map(|_| state.make_var_of(Fd::new_range(0, 3))).collect();
+    let g: Vec<Var<Fd>> = (0..4).map(|_| state.make_var()).collect();
+    state.add_constraint(Inverse::new(f.clone(), g.clone()));
+    state.add_constraint(AllDiffConstraint::new(f.clone()));
+    let permutations: Vec<State> = assign_all_values(state).into_iter().collect();
+    assert!(permutations.len() == 24);
+    for state in permutations.into_iter() {
+        let value = |var: Var<Fd>| state.get_value(var).unwrap().single_value().unwrap() as usize;
+        assert!((0..4).all(|i| value(g[value(f[i])]) == i));
+    }
+
+    let mut state = State::new();
+    let bools: Vec<Var<bool>> = (0..4).map(|_| state.make_var()).collect();
+    let x = state.make_var_of(Fd::new_range(1, 9));
+    state.add_constraint(BoolChannel::new(bools.clone(), x));
+    assert!(*state.get_value(x).unwrap() == Fd::new_range(1, 3));
+    assert!(state.get_value(bools[0]) == Some(&false));
+    state.unify(bools[2], false);
+    assert!(*state.get_value(x).unwrap() == Fd::new_values(vec![1, 3]));
+    state.unify(x, Fd::new_single(3));
+    assert!(state.get_value(bools[3]) == Some(&true));
+    assert!(state.get_value(bools[1]) == Some(&false));
+    state.unify(bools[1], true);
+    assert!(!state.ok());
+
+    // once the Fd is known, length builds the list directly
+    let mut state = State::new();
+    fresh!(state, len);
+    let list: Var<List<i32>> = state.make_var();
+    let x = state.make_var_of(Fd::new_range(0, 5));
+    state.add_constraint(FdI32Constraint::new(x, len));
+    state.add_constraint(FdLessOrEqual::new(Fd::new_single(4), x));
+    state.add_constraint(FdLessOrEqual::new(x, Fd::new_single(4)));
+    assert!(state.get_value(len) == Some(&4));
+    let lists: Vec<State> = length(state, list, len).into_iter().take(2).collect();
+    assert!(lists.len() == 1);
+
+    let mut state = State::new();
+    fresh!(state, len);
+    let x = state.make_var_of(Fd::new_range(0, 5));
+    state.add_constraint(FdI32Constraint::new(x, len));
+    state.unify(len, 7);
+    assert!(!state.ok());
+}
+
+#[test]
+fn fd_usize_aliased() {
+    use kanren::constraints::FdUsizeConstraint;
+    // the usize var is unified with another after the constraint is posted, and should still be
+    // linked to the Fd through whichever var ends up holding the value
+    for &flip in [false, true].iter() {
+        let mut state = State::new();
+        fresh!(state, u, other);
+        let x = state.make_var_of(Fd::new_range(0, 5));
+        state.add_constraint(FdUsizeConstraint::new(x, u));
+        if flip { state.unify(other, u); } else { state.unify(u, other); }
+        state.unify(other, 3usize);
+        assert!(state.get_value(x).unwrap().single_value() == Some(3));
+
+        let mut state = State::new();
+        fresh!(state, u, other);
+        let x = state.make_var_of(Fd::new_range(0, 5));
+        state.add_constraint(FdUsizeConstraint::new(x, u));
+        if flip { state.unify(other, u); } else { state.unify(u, other); }
+        state.unify(x, Fd::new_single(4));
+        assert!(state.get_value(other) == Some(&4));
+    }
+}
+
+#[test]
+fn local_search() {
+    use kanren::core::{local_search, LocalSearch};
+    use kanren::constraints::Table;
+    fn queens(state: &mut State, n: i64) -> Vec<Var<Fd>> {
+        let queens: Vec<Var<Fd>> = (0..n).map(|_| state.make_var_of(Fd::new_range(0, n - 1))).collect();
+        state.add_constraint(AllDiffConstraint::with_propagation(queens.clone(), AllDiffPropagation::Value));
+        for i in 0..queens.len() {
+            for j in i + 1..queens.len() {
+                let gap = (j - i) as i64;
+                state.add_constraint(FdLinear::new(vec![(1, queens[i]), (-1, queens[j])], FdRelation::NotEqual, gap));
+                state.add_constraint(FdLinear::new(vec![(1, queens[i]), (-1, queens[j])], FdRelation::NotEqual, -gap));
+            }
+        }
+        queens
+    }
+    fn solve(seed: u64) -> (Vec<i64>, usize) {
+        let mut state = State::new();
+        let vars = queens(&mut state, 12);
+        let result = local_search(state, LocalSearch::new(seed).steps(5000));
+        assert!(result.violations() == 0);
+        let values = vars.iter().map(|&var| result.assignment().get(var).unwrap()).collect();
+        let steps = result.steps();
+        let state = result.into_state().unwrap();
+        for (&var, &value) in vars.iter().zip(&values) {
+            assert!(state.get_value(var).unwrap().single_value() == Some(value));
+        }
+        (values, steps)
+    }
+    let (values, steps) = solve(3);
+    for i in 0..values.len() {
+        for j in i + 1..values.len() {
+            assert!(values[i] != values[j]);
+            assert!((values[i] - values[j]).abs() != (j - i) as i64);
+        }
+    }
+    // the same seed makes the same moves
+    assert!(solve(3) == (values, steps));
+
+    // constraints without a violation count still count when they fail
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(0, 5))).collect();
+    let tuples = vec![vec![1, 2, 3], vec![4, 0, 2], vec![5, 5, 0]];
+    state.add_constraint(Table::new(vars.clone(), tuples.clone()));
+    let result = local_search(state, LocalSearch::new(1).restart_after(20));
+    assert!(result.violations() == 0);
+    let values: Vec<i64> = vars.iter().map(|&var| result.assignment().get(var).unwrap()).collect();
+    assert!(tuples.contains(&values));
+
+    // three variables can't take two different values, so it runs out of steps
+    let mut state = State::new();
+    let vars: Vec<Var<Fd>> = (0..3).map(|_| state.make_var_of(Fd::new_range(1, 2))).collect();
+    state.add_constraint(AllDiffConstraint::with_propagation(vars, AllDiffPropagation::Value));
+    let result = local_search(state, LocalSearch::new(1).steps(100).tabu(0));
+    assert!(result.violations() == 1);
+    assert!(result.steps() == 100);
+    assert!(result.into_state().is_none());
+
+    // values are drawn from domains as wide as i64 itself
+    let mut state = State::new();
+    let x = state.make_var_of(Fd::new_range(-1, ::std::i64::MAX));
+    let y = state.make_var_of(Fd::new_range(::std::i64::MIN, ::std::i64::MAX));
+    state.add_constraint(AllDiffConstraint::with_propagation(vec![x, y], AllDiffPropagation::Value));
+    let result = local_search(state, LocalSearch::new(1));
+    assert!(result.violations() == 0);
+    assert!(result.assignment().get(x).unwrap() >= -1);
+}
+
 #[test]
 fn fd_value_diff_test() {
     let mut state = State::new();
@@ -489,7 +1997,7 @@ fn fd_value_diff_test() {
     let states =
         fd_values(state, f, __())
         .and(move |state| fd_values(state, f2, __()));
-    let result: Vec<(usize, usize)> = states.into_iter().map(|state| {
+    let result: Vec<(i64, i64)> = states.into_iter().map(|state| {
         let fval = state.get_value(f);
         let f2val = state.get_value(f2);
         println!("{:?}, {:?}", fval, f2val);
@@ -509,7 +2017,7 @@ fn fd_value_test() {
     let states =
         fd_values(state, f, __())
         .and(move |state| fd_values(state, f2, __()));
-    let result: Vec<(usize, usize)> = states.into_iter().map(|state| {
+    let result: Vec<(i64, i64)> = states.into_iter().map(|state| {
         let fval = state.get_value(f);
         let f2val = state.get_value(f2);
         println!("{:?}, {:?}", fval, f2val);
@@ -518,12 +2026,22 @@ fn fd_value_test() {
     for item in result.iter() {
         println!("{:?}", item);
     }
-    let hashitems: HashSet<(usize, usize)> = HashSet::from_iter(result.clone());
+    let hashitems: HashSet<(i64, i64)> = HashSet::from_iter(result.clone());
     assert!(hashitems == HashSet::from_iter(vec![
         (0, 3), (0, 4), (0, 5),
         (1, 3), (1, 4), (1, 5),
         (2, 3), (2, 4), (2, 5)
     ]));
+
+    // negative values are skipped without visiting each one
+    let mut state = State::new();
+    let f = state.make_var_of(Fd::new_range(::std::i64::MIN, 5));
+    let u = state.make_var();
+    let values: Vec<usize> = fd_values(state, f, u).into_iter().map(|state| *state.get_value(u).unwrap()).collect();
+    assert!(values == vec![0, 1, 2, 3, 4, 5]);
+    let mut state = State::new();
+    let f = state.make_var_of(Fd::new_range(::std::i64::MIN, -1));
+    assert!(fd_values(state, f, __()).into_iter().count() == 0);
 }
 
 #[test]
@@ -568,3 +2086,113 @@ fn fd_unify() {
     state.unify(f, Fd::new_single(1));
     assert!(state.get_value(f2) == Some(&Fd::new_single(1)));
 }
+
+#[test]
+fn arith_num_roundtrip() {
+    use kanren::arith::{build_num, num_value};
+    let mut state = State::new();
+    for &n in [0, 1, 6, 1 << 40, ::std::u64::MAX].iter() {
+        let var = build_num(&mut state, n);
+        assert!(num_value(&state, var) == Some(n));
+    }
+    fresh!(state, unset);
+    assert!(num_value(&state, unset) == None);
+}
+
+#[test]
+fn arith_plus_minus() {
+    use kanren::arith::{build_num, num_value, pluso, minuso};
+    let mut state = State::new();
+    fresh!(state, n, m, k);
+    let five = build_num(&mut state, 5);
+    let sums: HashSet<(u64, u64)> = pluso(state, n, m, five).into_iter().map(|state| {
+        (num_value(&state, n).unwrap(), num_value(&state, m).unwrap())
+    }).collect();
+    assert!(sums == HashSet::from_iter(vec![(0, 5), (1, 4), (2, 3), (3, 2), (4, 1), (5, 0)]));
+
+    let mut state = State::new();
+    let five = build_num(&mut state, 5);
+    let three = build_num(&mut state, 3);
+    let diffs: Vec<u64> = minuso(state, five, three, k).into_iter().map(|state| {
+        num_value(&state, k).unwrap()
+    }).collect();
+    assert!(diffs == vec![2]);
+}
+
+#[test]
+fn arith_mult_div() {
+    use kanren::arith::{build_num, num_value, multo, divo};
+    let mut state = State::new();
+    fresh!(state, n, m);
+    let twelve = build_num(&mut state, 12);
+    let factors: HashSet<(u64, u64)> = multo(state, n, m, twelve).into_iter().map(|state| {
+        (num_value(&state, n).unwrap(), num_value(&state, m).unwrap())
+    }).collect();
+    assert!(factors == HashSet::from_iter(vec![
+        (1, 12), (2, 6), (3, 4), (4, 3), (6, 2), (12, 1)
+    ]));
+
+    let mut state = State::new();
+    fresh!(state, q, r);
+    let seventeen = build_num(&mut state, 17);
+    let five = build_num(&mut state, 5);
+    let results: Vec<(u64, u64)> = divo(state, seventeen, five, q, r).into_iter().map(|state| {
+        (num_value(&state, q).unwrap(), num_value(&state, r).unwrap())
+    }).collect();
+    assert!(results == vec![(3, 2)]);
+}
+
+#[test]
+fn arith_log() {
+    use kanren::arith::{build_num, num_value, logo};
+    let mut state = State::new();
+    fresh!(state, q, r);
+    let n = build_num(&mut state, 14);
+    let b = build_num(&mut state, 2);
+    let results: Vec<(u64, u64)> = logo(state, n, b, q, r).into_iter().map(|state| {
+        (num_value(&state, q).unwrap(), num_value(&state, r).unwrap())
+    }).collect();
+    assert!(results == vec![(3, 6)]);
+
+    let mut state = State::new();
+    fresh!(state, q, r);
+    let n = build_num(&mut state, 9);
+    let b = build_num(&mut state, 3);
+    let results: Vec<(u64, u64)> = logo(state, n, b, q, r).into_iter().map(|state| {
+        (num_value(&state, q).unwrap(), num_value(&state, r).unwrap())
+    }).collect();
+    assert!(results == vec![(2, 0)]);
+
+    let mut state = State::new();
+    fresh!(state, q, r);
+    let n = build_num(&mut state, 343);
+    let b = build_num(&mut state, 7);
+    let results: Vec<(u64, u64)> = logo(state, n, b, q, r).into_iter().map(|state| {
+        (num_value(&state, q).unwrap(), num_value(&state, r).unwrap())
+    }).collect();
+    assert!(results == vec![(3, 0)]);
+}
+
+#[test]
+fn arith_refutation() {
+    use kanren::arith::{build_num, num_value, lto, leo, divo};
+    // These would search forever without refutational completeness.
+    let mut state = State::new();
+    fresh!(state, n);
+    let three = build_num(&mut state, 3);
+    let smaller: HashSet<u64> = lto(state, n, three).into_iter().map(|state| {
+        num_value(&state, n).unwrap()
+    }).collect();
+    assert!(smaller == HashSet::from_iter(vec![0, 1, 2]));
+
+    let mut state = State::new();
+    fresh!(state, n);
+    let three = build_num(&mut state, 3);
+    assert!(leo(state, n, three).into_iter().count() == 4);
+
+    let mut state = State::new();
+    fresh!(state, q);
+    let five = build_num(&mut state, 5);
+    let zero = build_num(&mut state, 0);
+    assert!(divo(state, five, zero, q, __()).into_iter().next().is_none());
+}